@@ -0,0 +1,375 @@
+//! Who may do what with the PCs of a directory.
+//!
+//! Everyone is the [Role::Owner] of their own PCs and may read their mailboxes.
+//! Further roles are assigned in a TOML file:
+//!
+//! ```toml
+//! admin = ["maria@dingong.com"]
+//! helpdesk = ["hans@overkill.com", "karl@keule.com"]
+//! auditor = ["don@drumpf.com"]
+//! ```
+//!
+//! The operations of a [PcDirectory] ending in `_as` check the permissions of
+//! the [Caller] and fail with [PcDirectoryError::PermissionDenied] otherwise.
+//! They are the only way to add, maintain, turn off, transfer and reclaim PCs,
+//! send email and read mailboxes from outside this crate. The processes acting
+//! on a directory over time, like the [crate::scheduler::MaintenanceScheduler]
+//! and the [crate::engagement::EngagementSweep], check the caller once when
+//! they are set up or run.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::Path,
+    rc::Rc,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    pc::PcBuilder,
+    pc_directory::{MaintenanceHandle, PcDirectory, PcDirectoryError},
+    person::{EmailAddr, Person, PersonId},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// May do everything.
+    Admin,
+    /// Sets up and maintains PCs and informs their owners.
+    Helpdesk,
+    /// May read all mailboxes, but change nothing.
+    Auditor,
+    /// May read the mailboxes of their own PCs.
+    Owner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Maintain,
+    SendEmail,
+    ReadMailbox,
+    Transfer,
+    AddPc,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Maintain => "maintain",
+            Self::SendEmail => "send email",
+            Self::ReadMailbox => "read the mailbox of",
+            Self::Transfer => "transfer",
+            Self::AddPc => "add PCs",
+        })
+    }
+}
+
+impl Role {
+    /// Whether the role grants `permission`. `own` tells whether the PC in
+    /// question belongs to the caller.
+    pub fn allows(&self, permission: Permission, own: bool) -> bool {
+        match (self, permission) {
+            (Self::Admin, _) => true,
+            (Self::Helpdesk, Permission::Maintain | Permission::SendEmail | Permission::AddPc) => {
+                true
+            }
+            (Self::Auditor, Permission::ReadMailbox) => true,
+            (Self::Owner, Permission::ReadMailbox) => own,
+            _ => false,
+        }
+    }
+}
+
+/// Someone acting on a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caller {
+    pub email: EmailAddr,
+    /// The roles besides [Role::Owner], which everyone has.
+    pub roles: BTreeSet<Role>,
+}
+
+impl Caller {
+    pub fn new(email: EmailAddr, roles: impl IntoIterator<Item = Role>) -> Self {
+        Self {
+            email,
+            roles: roles.into_iter().collect(),
+        }
+    }
+
+    fn roles(&self) -> impl Iterator<Item = Role> + '_ {
+        self.roles.iter().copied().chain([Role::Owner])
+    }
+}
+
+/// Which people have which roles.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RoleConfig(BTreeMap<Role, BTreeSet<EmailAddr>>);
+
+#[derive(Debug, Error)]
+pub enum RoleConfigError {
+    #[error("Could not read the roles: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The roles are malformed: {0}")]
+    Format(#[from] toml::de::Error),
+}
+
+impl RoleConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, RoleConfigError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(s: &str) -> Result<Self, RoleConfigError> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// The caller with the given email address and the roles assigned to it.
+    pub fn caller(&self, email: EmailAddr) -> Caller {
+        let roles: Vec<_> = self
+            .0
+            .iter()
+            .filter(|(_, members)| members.contains(&email))
+            .map(|(&role, _)| role)
+            .collect();
+        Caller::new(email, roles)
+    }
+}
+
+impl PcDirectory {
+    /// Check whether `caller` may act on the PC with the given id, or on PCs
+    /// in general if `pc` is `None`.
+    pub fn authorize(
+        &self,
+        caller: &Caller,
+        permission: Permission,
+        pc: Option<usize>,
+    ) -> Result<(), PcDirectoryError> {
+        let own = match pc {
+            Some(id) => {
+                let pc = self.get_pc(id).ok_or(PcDirectoryError::PcNotFound { id })?;
+                pc.owner
+                    .as_ref()
+                    .is_some_and(|p| p.has_email(&caller.email))
+            }
+            None => false,
+        };
+        match caller.roles().any(|role| role.allows(permission, own)) {
+            true => Ok(()),
+            false => Err(PcDirectoryError::PermissionDenied {
+                caller: caller.email.clone(),
+                permission,
+                pc,
+            }),
+        }
+    }
+
+    /// Add a new PC to the directory on behalf of `caller`. It fails if the
+    /// owner's email address is already used by someone else or if the
+    /// hardware cannot run the operating system.
+    pub fn add_pc_as(&mut self, caller: &Caller, pcb: PcBuilder) -> Result<(), PcDirectoryError> {
+        self.authorize(caller, Permission::AddPc, None)?;
+        self.add_pc(pcb)
+    }
+
+    /// Acquire a maintenance lock for the PC with the given id on behalf of
+    /// `caller`. The owner of the PC and their manager are notified, if
+    /// maintenance notifications are enabled.
+    pub fn acquire_maintenance_lock_as<S: ToString>(
+        &self,
+        caller: &Caller,
+        id: usize,
+        reason: S,
+    ) -> Result<MaintenanceHandle<'_>, PcDirectoryError> {
+        self.authorize(caller, Permission::Maintain, Some(id))?;
+        self.acquire_maintenance_lock(id, reason)
+    }
+
+    /// Send an email to the person with address `to` on behalf of `caller`.
+    /// It is put into the mailbox of the first of their PCs that is turned on.
+    pub fn send_email_as<E: TryInto<EmailAddr>, T: ToString>(
+        &self,
+        caller: &Caller,
+        to: E,
+        message: T,
+    ) -> Result<(), PcDirectoryError> {
+        self.authorize(caller, Permission::SendEmail, None)?;
+        self.send_email(to, message)
+    }
+
+    /// Send `message` to each of `people` who owns a PC on behalf of `caller`.
+    /// Mail that cannot be delivered right away is deferred.
+    ///
+    /// # Returns
+    ///
+    /// The people the message was sent to.
+    pub fn broadcast_as(
+        &self,
+        caller: &Caller,
+        people: &BTreeSet<PersonId>,
+        message: &str,
+    ) -> Result<Vec<PersonId>, PcDirectoryError> {
+        self.authorize(caller, Permission::SendEmail, None)?;
+        Ok(self.broadcast(people, message))
+    }
+
+    /// The messages in the mailbox of the PC with the given id, if `caller`
+    /// may read them.
+    pub fn read_mailbox_as(
+        &self,
+        caller: &Caller,
+        id: usize,
+    ) -> Result<Vec<String>, PcDirectoryError> {
+        self.authorize(caller, Permission::ReadMailbox, Some(id))?;
+        Ok(self.get_pc(id).expect("checked by authorize").mailbox())
+    }
+
+    /// Turn the PC with the given id off on behalf of `caller`. PCs that are
+    /// being maintained cannot be turned off.
+    pub fn power_off_as(&self, caller: &Caller, id: usize) -> Result<(), PcDirectoryError> {
+        self.authorize(caller, Permission::Maintain, Some(id))?;
+        self.power_off(id)
+    }
+
+    /// Take the PC with the given id back from its owner on behalf of
+    /// `caller`: it is turned off, its mailbox is emptied and it no longer has
+    /// an owner. The owner keeps their [PersonId].
+    ///
+    /// # Returns
+    ///
+    /// The previous owner.
+    pub fn reclaim_as(
+        &mut self,
+        caller: &Caller,
+        id: usize,
+    ) -> Result<Option<Rc<Person>>, PcDirectoryError> {
+        self.authorize(caller, Permission::Transfer, Some(id))?;
+        self.reclaim(id)
+    }
+
+    /// Give the PC with the given id to the person with id `to`, or to nobody,
+    /// on behalf of `caller`. Its mailbox is emptied.
+    pub fn transfer_pc_as(
+        &mut self,
+        caller: &Caller,
+        id: usize,
+        to: Option<PersonId>,
+    ) -> Result<(), PcDirectoryError> {
+        self.authorize(caller, Permission::Transfer, Some(id))?;
+        self.transfer_pc(id, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pc_directory::get_directory;
+
+    use super::*;
+
+    fn config() -> RoleConfig {
+        RoleConfig::parse(
+            r#"
+            admin = ["maria@dingong.com"]
+            helpdesk = ["hans@overkill.com"]
+            auditor = ["don@drumpf.com"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn caller(email: &str) -> Caller {
+        config().caller(EmailAddr::try_from(email).unwrap())
+    }
+
+    fn is_denied<T>(result: Result<T, PcDirectoryError>) -> bool {
+        matches!(result, Err(PcDirectoryError::PermissionDenied { .. }))
+    }
+
+    #[test]
+    fn test_mailboxes() {
+        let dir = get_directory();
+        dir.send_email("sue@whatever.com", "hi").unwrap();
+        let sue = caller("sue@whatever.com");
+        assert_eq!(
+            dir.read_mailbox_as(&sue, 2).unwrap(),
+            vec!["hi".to_string()]
+        );
+        assert!(is_denied(dir.read_mailbox_as(&sue, 3)));
+        assert!(dir.read_mailbox_as(&caller("don@drumpf.com"), 2).is_ok());
+        assert!(is_denied(
+            dir.read_mailbox_as(&caller("hans@overkill.com"), 2)
+        ));
+        assert!(matches!(
+            dir.read_mailbox_as(&sue, 9),
+            Err(PcDirectoryError::PcNotFound { id: 9 })
+        ));
+    }
+
+    #[test]
+    fn test_maintenance_and_email() {
+        let dir = get_directory();
+        let (hans, don) = (caller("hans@overkill.com"), caller("don@drumpf.com"));
+        assert!(dir.acquire_maintenance_lock_as(&hans, 3, "upgrade").is_ok());
+        assert!(is_denied(
+            dir.acquire_maintenance_lock_as(&don, 3, "upgrade")
+        ));
+        // Owners cannot maintain their own PCs either.
+        assert!(is_denied(dir.acquire_maintenance_lock_as(&don, 4, "fix")));
+        assert!(dir.send_email_as(&hans, "sue@whatever.com", "hi").is_ok());
+        let err = dir
+            .send_email_as(&don, "sue@whatever.com", "hi")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "don@drumpf.com is not allowed to send email."
+        );
+        let platform = dir
+            .org()
+            .members(&crate::org::OrgUnit::Team("platform".into()))
+            .unwrap();
+        assert!(is_denied(dir.broadcast_as(&don, &platform, "hi")));
+        assert_eq!(dir.broadcast_as(&hans, &platform, "hi").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut dir = get_directory();
+        let hans = caller("hans@overkill.com");
+        assert!(is_denied(dir.transfer_pc_as(&hans, 3, Some(PersonId(1)))));
+        dir.transfer_pc_as(&caller("maria@dingong.com"), 3, Some(PersonId(1)))
+            .unwrap();
+        assert_eq!(dir.get_pc(3).unwrap().owner_id(), Some(PersonId(1)));
+        assert!(matches!(
+            dir.transfer_pc(3, Some(PersonId(42))),
+            Err(PcDirectoryError::PersonNotFound { .. })
+        ));
+        // Hans owns PC 3 now.
+        assert!(dir.read_mailbox_as(&hans, 3).is_ok());
+    }
+
+    #[test]
+    fn test_power_off_and_reclaim() {
+        let mut dir = get_directory();
+        let (hans, don) = (caller("hans@overkill.com"), caller("don@drumpf.com"));
+        assert!(is_denied(dir.power_off_as(&don, 3)));
+        dir.power_off_as(&hans, 3).unwrap();
+        assert!(!dir.get_pc(3).unwrap().operational_state().is_on());
+
+        assert!(is_denied(dir.reclaim_as(&hans, 3)));
+        let owner = dir.reclaim_as(&caller("maria@dingong.com"), 3).unwrap();
+        assert_eq!(owner.unwrap().first, "Don");
+        assert_eq!(dir.get_pc(3).unwrap().owner_id(), None);
+    }
+
+    #[test]
+    fn test_add_pc() {
+        let mut dir = get_directory();
+        let err = dir
+            .add_pc_as(&caller("don@drumpf.com"), PcBuilder::default())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "don@drumpf.com is not allowed to add PCs.");
+        dir.add_pc_as(&caller("hans@overkill.com"), PcBuilder::default())
+            .unwrap();
+        assert_eq!(dir.iter_pcs().count(), 7);
+    }
+}
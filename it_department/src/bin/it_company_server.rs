@@ -0,0 +1,60 @@
+//! Serves a directory file as a JSON API, see [it_company::server].
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::Parser;
+use it_company::{access::RoleConfig, profiles::Profiles, server::DirectoryServer};
+
+#[derive(Parser)]
+#[command(about, long_about = None)]
+struct Cli {
+    /// The directory file to serve; created when the first PC is added.
+    #[arg(long)]
+    directory: PathBuf,
+
+    /// The address to listen on. Callers are not authenticated, so only
+    /// listen on a loopback address or behind an authenticating proxy.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    bind: SocketAddr,
+
+    /// Who has which roles, for requests acting on behalf of someone.
+    #[arg(long, default_value = "roles.toml")]
+    roles: PathBuf,
+
+    /// The hardware and operating system profiles for new PCs (defaults to
+    /// the built-in ones).
+    #[arg(long)]
+    profiles: Option<PathBuf>,
+}
+
+fn fail(e: impl std::fmt::Display) -> ! {
+    eprintln!("{e}");
+    std::process::exit(1);
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let roles = RoleConfig::load(&cli.roles)
+        .unwrap_or_else(|e| fail(format!("{}: {e}", cli.roles.display())));
+    let profiles = match &cli.profiles {
+        Some(path) => {
+            Profiles::load(path).unwrap_or_else(|e| fail(format!("{}: {e}", path.display())))
+        }
+        None => Profiles::default(),
+    };
+    let server = DirectoryServer::bind(cli.bind, cli.directory)
+        .unwrap_or_else(|e| fail(e))
+        .with_roles(roles)
+        .with_profiles(profiles);
+    if !cli.bind.ip().is_loopback() {
+        eprintln!(
+            "Warning: callers are not authenticated; anyone who can reach {} can act as anyone.",
+            cli.bind
+        );
+    }
+    if let Some(addr) = server.local_addr() {
+        eprintln!("Listening on http://{addr}");
+    }
+    if let Err(e) = server.run() {
+        fail(e);
+    }
+}
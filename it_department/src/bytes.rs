@@ -0,0 +1,291 @@
+//! Amounts of memory and storage.
+//!
+//! [NumBytes] parses and prints sizes with units, e.g. "16 GiB" or "2TB".
+//! Binary (KiB, MiB, ...) as well as decimal units (kB, MB, ...) are
+//! understood; sizes are always printed with binary units.
+use std::{
+    fmt,
+    iter::Sum,
+    ops::{Add, AddAssign, Mul, Sub},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A number of bytes.
+// This used to be a phantom_newtype::Amount, which neither lets us implement
+// FromStr nor a Display with units. Like the Amount, it is serialized as a
+// plain number, but it can also be deserialized from strings like "16 GiB".
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(try_from = "SizeRepr", into = "u64")]
+pub struct NumBytes(u64);
+
+pub const KIBIBYTE: NumBytes = NumBytes::new(1u64 << 10);
+pub const MEBIBYTE: NumBytes = NumBytes::new(1u64 << 20);
+pub const GIBIBYTE: NumBytes = NumBytes::new(1u64 << 30);
+pub const TEBIBYTE: NumBytes = NumBytes::new(1u64 << 40);
+
+// Ordered from the largest unit down, so the first that fits is used for
+// printing.
+const BINARY_UNITS: [(&str, u64); 5] = [
+    ("PiB", 1 << 50),
+    ("TiB", 1 << 40),
+    ("GiB", 1 << 30),
+    ("MiB", 1 << 20),
+    ("KiB", 1 << 10),
+];
+
+const DECIMAL_UNITS: [(&str, u64); 5] = [
+    ("PB", 1_000_000_000_000_000),
+    ("TB", 1_000_000_000_000),
+    ("GB", 1_000_000_000),
+    ("MB", 1_000_000),
+    ("kB", 1_000),
+];
+
+impl NumBytes {
+    pub const fn new(n: u64) -> Self {
+        Self(n)
+    }
+
+    pub const fn get(&self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, rhs: NumBytes) -> Option<NumBytes> {
+        self.0.checked_add(rhs.0).map(Self)
+    }
+
+    pub fn checked_sub(self, rhs: NumBytes) -> Option<NumBytes> {
+        self.0.checked_sub(rhs.0).map(Self)
+    }
+
+    pub fn checked_mul(self, rhs: u64) -> Option<NumBytes> {
+        self.0.checked_mul(rhs).map(Self)
+    }
+
+    pub fn saturating_sub(self, rhs: NumBytes) -> NumBytes {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SizeParseError {
+    #[error("No size given.")]
+    Empty,
+    #[error("Invalid number: {0}")]
+    InvalidNumber(String),
+    #[error("Unknown unit: {0}")]
+    UnknownUnit(String),
+    #[error("The size is too large.")]
+    Overflow,
+}
+
+/// Parse sizes like "512", "16GiB", "512 MiB", "1.5 TB" or "2tb". Units are
+/// case-insensitive, a number without unit is a number of bytes.
+impl FromStr for NumBytes {
+    type Err = SizeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(SizeParseError::Empty);
+        }
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (number, unit) = (&s[..split], s[split..].trim());
+        let multiplier = match unit.to_lowercase().as_str() {
+            "" | "b" => 1,
+            lower => BINARY_UNITS
+                .iter()
+                .chain(&DECIMAL_UNITS)
+                .find(|(name, _)| name.to_lowercase() == lower)
+                .map(|(_, multiplier)| *multiplier)
+                .ok_or_else(|| SizeParseError::UnknownUnit(unit.into()))?,
+        };
+
+        let invalid = || SizeParseError::InvalidNumber(number.into());
+        let (int, frac) = number.split_once('.').unwrap_or((number, ""));
+        if int.is_empty() || frac.contains('.') {
+            return Err(invalid());
+        }
+        let int: u64 = int.parse().map_err(|_| invalid())?;
+        // Fractions are computed exactly and rounded down to whole bytes; more
+        // than a few digits make no difference in practice.
+        let frac = &frac[..frac.len().min(6)];
+        let frac_bytes = match frac.is_empty() {
+            true => 0,
+            false => {
+                let digits: u128 = frac.parse().map_err(|_| invalid())?;
+                digits * multiplier as u128 / 10u128.pow(frac.len() as u32)
+            }
+        };
+        int.checked_mul(multiplier)
+            .and_then(|n| n.checked_add(frac_bytes as u64))
+            .map(Self)
+            .ok_or(SizeParseError::Overflow)
+    }
+}
+
+/// Print the size in the largest binary unit it reaches, with up to two
+/// decimals, e.g. "16 GiB", "1.5 TiB" or "512 B".
+impl fmt::Display for NumBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((name, unit)) = BINARY_UNITS.iter().find(|(_, unit)| self.0 >= *unit) else {
+            return write!(f, "{} B", self.0);
+        };
+        // Rounded to hundredths of the unit.
+        let hundredths = (self.0 as u128 * 100 + *unit as u128 / 2) / *unit as u128;
+        let (int, frac) = (hundredths / 100, hundredths % 100);
+        match frac {
+            0 => write!(f, "{int} {name}"),
+            _ if frac % 10 == 0 => write!(f, "{int}.{} {name}", frac / 10),
+            _ => write!(f, "{int}.{frac:02} {name}"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SizeRepr {
+    Number(u64),
+    Text(String),
+}
+
+impl TryFrom<SizeRepr> for NumBytes {
+    type Error = SizeParseError;
+
+    fn try_from(value: SizeRepr) -> Result<Self, Self::Error> {
+        match value {
+            SizeRepr::Number(n) => Ok(Self(n)),
+            SizeRepr::Text(s) => s.parse(),
+        }
+    }
+}
+
+impl From<NumBytes> for u64 {
+    fn from(value: NumBytes) -> Self {
+        value.0
+    }
+}
+
+impl From<u64> for NumBytes {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+// Like with the Amount, the operators panic on overflow in debug builds. Use the
+// checked_* methods where that matters.
+impl Add for NumBytes {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for NumBytes {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for NumBytes {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Mul<u64> for NumBytes {
+    type Output = Self;
+
+    fn mul(self, rhs: u64) -> Self {
+        Self(self.0 * rhs)
+    }
+}
+
+impl Sum for NumBytes {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self(0), Add::add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> NumBytes {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("16GiB"), GIBIBYTE * 16);
+        assert_eq!(parse("512 MiB"), MEBIBYTE * 512);
+        assert_eq!(parse("2TB").get(), 2_000_000_000_000);
+        assert_eq!(parse("2tb"), parse("2 TB"));
+        assert_eq!(parse("1.5 GiB"), MEBIBYTE * 1536);
+        assert_eq!(parse("4096"), KIBIBYTE * 4);
+        assert_eq!(parse("1 kB").get(), 1000);
+
+        assert_eq!("".parse::<NumBytes>(), Err(SizeParseError::Empty));
+        assert!(matches!(
+            "16 GiBs".parse::<NumBytes>(),
+            Err(SizeParseError::UnknownUnit(_))
+        ));
+        assert!(matches!(
+            "1.2.3 GiB".parse::<NumBytes>(),
+            Err(SizeParseError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            "GiB".parse::<NumBytes>(),
+            Err(SizeParseError::InvalidNumber(_))
+        ));
+        assert_eq!(
+            "20000000 PiB".parse::<NumBytes>(),
+            Err(SizeParseError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(GIBIBYTE.to_string(), "1 GiB");
+        assert_eq!((TEBIBYTE + GIBIBYTE * 512).to_string(), "1.5 TiB");
+        assert_eq!(NumBytes::new(16318412 * 1024).to_string(), "15.56 GiB");
+        assert_eq!(NumBytes::new(512).to_string(), "512 B");
+        for size in ["16 GiB", "1.25 MiB", "3 TiB", "100 B"] {
+            assert_eq!(parse(size).to_string(), size);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(GIBIBYTE.checked_add(GIBIBYTE), Some(GIBIBYTE * 2));
+        assert_eq!(NumBytes::new(u64::MAX).checked_add(KIBIBYTE), None);
+        assert_eq!(MEBIBYTE.checked_sub(GIBIBYTE), None);
+        assert_eq!(MEBIBYTE.saturating_sub(GIBIBYTE), NumBytes::new(0));
+        assert_eq!(TEBIBYTE.checked_mul(1 << 30), None);
+        assert_eq!(
+            [GIBIBYTE, MEBIBYTE].into_iter().sum::<NumBytes>().get(),
+            (1 << 30) + (1 << 20)
+        );
+    }
+
+    #[test]
+    fn test_serde() {
+        assert_eq!(serde_json::to_string(&KIBIBYTE).unwrap(), "1024");
+        assert_eq!(serde_json::from_str::<NumBytes>("1024").unwrap(), KIBIBYTE);
+        assert_eq!(
+            serde_json::from_str::<NumBytes>("\"1 KiB\"").unwrap(),
+            KIBIBYTE
+        );
+        assert!(serde_json::from_str::<NumBytes>("\"1 KiBi\"").is_err());
+    }
+}
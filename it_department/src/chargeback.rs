@@ -0,0 +1,376 @@
+//! Hardware costs and their allocation to the owners of the PCs.
+//!
+//! The purchase price of a PC is spread over several years by its
+//! [Depreciation]. The [Chargeback] for a year allocates what the PCs cost in
+//! that year to their owners and sums it up per contractor company and per
+//! employee. Costs are in rappen, and the costs of all years add up to the
+//! purchase price exactly.
+use std::collections::BTreeMap;
+
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    pc::PcHardware,
+    pc_directory::PcDirectory,
+    person::{Affiliation, ChfAmout, EmailAddr, Person},
+    report::{Report, Table},
+};
+
+/// How the purchase price of a PC is spread over the years, starting with the
+/// year of the purchase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Depreciation {
+    /// The same amount every year.
+    StraightLine { years: u16 },
+    /// A fixed percentage of the remaining value every year. What remains in
+    /// the last year is written off completely.
+    DecliningBalance { percent: u8, years: u16 },
+}
+
+/// Office PCs are replaced after three years.
+impl Default for Depreciation {
+    fn default() -> Self {
+        Self::StraightLine { years: 3 }
+    }
+}
+
+impl Depreciation {
+    /// The cost in each year, starting with the year of the purchase. The
+    /// costs add up to `price` exactly.
+    pub fn schedule(&self, price: ChfAmout) -> Vec<ChfAmout> {
+        match *self {
+            Self::StraightLine { years } => price.split(years.max(1).into()),
+            Self::DecliningBalance { percent, years } => {
+                let mut remaining = price.minor();
+                let mut schedule = vec![];
+                for _ in 1..years.max(1) {
+                    // Cannot overflow, as the percentage is at most 100.
+                    let cost = (remaining as u128 * percent.min(100) as u128 / 100) as u64;
+                    schedule.push(ChfAmout::from_minor(cost));
+                    remaining -= cost;
+                }
+                schedule.push(ChfAmout::from_minor(remaining));
+                schedule
+            }
+        }
+    }
+}
+
+/// What `hardware` costs in `year`, if its purchase price and date are known.
+/// Before the purchase and once it is written off, it costs nothing.
+pub fn cost_in_year(hardware: &PcHardware, year: i32) -> Option<ChfAmout> {
+    let (price, date) = (hardware.purchase_price?, hardware.purchase_date?);
+    let schedule = hardware.depreciation.unwrap_or_default().schedule(price);
+    let cost = usize::try_from(year - date.year())
+        .ok()
+        .and_then(|i| schedule.get(i).copied());
+    Some(cost.unwrap_or_default())
+}
+
+#[derive(Debug, Error)]
+pub enum ChargebackError {
+    #[error("The costs of {what} are too large.")]
+    Overflow { what: String },
+}
+
+/// The hardware costs of a year, allocated to the owners of the PCs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Chargeback {
+    pub year: i32,
+    pub total: ChfAmout,
+    pub owners: Vec<OwnerCost>,
+    pub companies: Vec<CompanyCost>,
+    pub employees: Vec<EmployeeCost>,
+    /// The costs of PCs without an owner.
+    pub unallocated: ChfAmout,
+    /// PCs whose purchase price or date is unknown. They are not charged.
+    pub unpriced: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OwnerCost {
+    pub email: EmailAddr,
+    pub name: String,
+    pub pcs: Vec<usize>,
+    pub cost: ChfAmout,
+}
+
+/// The costs of all contractors working for a company.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CompanyCost {
+    pub company: String,
+    pub contractors: usize,
+    pub cost: ChfAmout,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EmployeeCost {
+    pub email: EmailAddr,
+    pub name: String,
+    pub cost: ChfAmout,
+    /// The cost relative to the annual income, unless there is no income.
+    pub income_share: Option<f64>,
+}
+
+fn add(sum: &mut ChfAmout, cost: ChfAmout, what: &str) -> Result<(), ChargebackError> {
+    *sum = sum
+        .checked_add(cost)
+        .ok_or_else(|| ChargebackError::Overflow { what: what.into() })?;
+    Ok(())
+}
+
+impl Chargeback {
+    pub fn new(dir: &PcDirectory, year: i32) -> Result<Self, ChargebackError> {
+        let mut total = ChfAmout::zero();
+        let mut unallocated = ChfAmout::zero();
+        let mut unpriced = vec![];
+        let mut owners: BTreeMap<&EmailAddr, (&Person, OwnerCost)> = BTreeMap::new();
+        for pc in dir.iter_pcs() {
+            let Some(cost) = cost_in_year(&pc.hardware, year) else {
+                unpriced.push(pc.id());
+                continue;
+            };
+            add(&mut total, cost, "the fleet")?;
+            let Some(owner) = pc.owner.as_deref() else {
+                add(&mut unallocated, cost, "PCs without owner")?;
+                continue;
+            };
+            let (_, owner_cost) = owners.entry(&owner.email).or_insert_with(|| {
+                let owner_cost = OwnerCost {
+                    email: owner.email.clone(),
+                    name: format!("{} {}", owner.first, owner.last),
+                    pcs: vec![],
+                    cost: ChfAmout::zero(),
+                };
+                (owner, owner_cost)
+            });
+            owner_cost.pcs.push(pc.id());
+            add(&mut owner_cost.cost, cost, owner.email.as_ref())?;
+        }
+
+        let mut companies: BTreeMap<&str, CompanyCost> = BTreeMap::new();
+        let mut employees = vec![];
+        for (owner, cost) in owners.values() {
+            match &owner.affiliation {
+                Affiliation::Contractor { company_name, .. } => {
+                    let company = companies
+                        .entry(company_name)
+                        .or_insert_with(|| CompanyCost {
+                            company: company_name.clone(),
+                            contractors: 0,
+                            cost: ChfAmout::zero(),
+                        });
+                    company.contractors += 1;
+                    add(&mut company.cost, cost.cost, company_name)?;
+                }
+                Affiliation::Employee { annual_income } => employees.push(EmployeeCost {
+                    email: cost.email.clone(),
+                    name: cost.name.clone(),
+                    cost: cost.cost,
+                    income_share: (!annual_income.is_zero())
+                        .then(|| cost.cost.minor() as f64 / annual_income.minor() as f64),
+                }),
+                Affiliation::Intern { .. } => (),
+            }
+        }
+
+        Ok(Self {
+            year,
+            total,
+            owners: owners.into_values().map(|(_, cost)| cost).collect(),
+            companies: companies.into_values().collect(),
+            employees,
+            unallocated,
+            unpriced,
+        })
+    }
+}
+
+impl Report for Chargeback {
+    fn summary(&self) -> String {
+        format!(
+            "Hardware costs in {}: {}, of which {} for PCs without owner",
+            self.year, self.total, self.unallocated
+        )
+    }
+
+    fn tables(&self) -> Vec<Table> {
+        vec![
+            Table {
+                name: "owners",
+                title: "Costs per owner",
+                columns: vec!["owner", "name", "PCs", "cost"],
+                rows: self
+                    .owners
+                    .iter()
+                    .map(|o| {
+                        let pcs: Vec<_> = o.pcs.iter().map(|id| id.to_string()).collect();
+                        vec![
+                            o.email.as_ref().into(),
+                            o.name.clone(),
+                            pcs.join(" "),
+                            o.cost.to_string(),
+                        ]
+                    })
+                    .collect(),
+            },
+            Table {
+                name: "companies",
+                title: "Costs per contractor company",
+                columns: vec!["company", "contractors", "cost"],
+                rows: self
+                    .companies
+                    .iter()
+                    .map(|c| {
+                        vec![
+                            c.company.clone(),
+                            c.contractors.to_string(),
+                            c.cost.to_string(),
+                        ]
+                    })
+                    .collect(),
+            },
+            Table {
+                name: "employees",
+                title: "Costs per employee",
+                columns: vec!["employee", "name", "cost", "share of income"],
+                rows: self
+                    .employees
+                    .iter()
+                    .map(|e| {
+                        let share = e
+                            .income_share
+                            .map_or("-".into(), |s| format!("{:.1}%", s * 100.0));
+                        vec![
+                            e.email.as_ref().into(),
+                            e.name.clone(),
+                            e.cost.to_string(),
+                            share,
+                        ]
+                    })
+                    .collect(),
+            },
+            Table {
+                name: "unpriced",
+                title: "PCs without purchase price or date",
+                columns: vec!["PC"],
+                rows: self
+                    .unpriced
+                    .iter()
+                    .map(|id| vec![id.to_string()])
+                    .collect(),
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::{os::OperatingSystem, pc::PcBuilder, person::PersonBuilder, report::ReportFormat};
+
+    use super::*;
+
+    fn chf(amounts: &[&str]) -> Vec<ChfAmout> {
+        amounts.iter().map(|a| a.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_schedules() {
+        let price = ChfAmout::from_major(1000);
+        assert_eq!(
+            Depreciation::default().schedule(price),
+            chf(&["333.34", "333.33", "333.33"])
+        );
+        assert_eq!(
+            Depreciation::DecliningBalance {
+                percent: 40,
+                years: 4
+            }
+            .schedule(price),
+            chf(&["400", "240", "144", "216"])
+        );
+        let zero_years = Depreciation::StraightLine { years: 0 };
+        assert_eq!(zero_years.schedule(price), chf(&["1000"]));
+    }
+
+    fn pc(owner: Option<(&str, Affiliation)>, price: Option<ChfAmout>) -> PcBuilder {
+        let mut hardware =
+            PcHardware::normal().with_purchase_date(NaiveDate::from_ymd_opt(2023, 5, 1).unwrap());
+        hardware.purchase_price = price;
+        PcBuilder {
+            owner: owner.map(|(email, affiliation)| {
+                PersonBuilder::new()
+                    .with_first_name("Pat")
+                    .with_last_name(email.split('@').next().unwrap())
+                    .with_email_address(email)
+                    .with_affiliation(affiliation)
+                    .build()
+                    .unwrap()
+            }),
+            hardware: Some(hardware),
+            os: Some(OperatingSystem::linux(6, 1)),
+        }
+    }
+
+    #[test]
+    fn test_chargeback() {
+        let employee = Affiliation::Employee {
+            annual_income: ChfAmout::from_major(100_000),
+        };
+        let contractor = |company: &str| Affiliation::Contractor {
+            company_name: company.into(),
+            engagement: None,
+        };
+        let price = |francs| Some(ChfAmout::from_major(francs));
+        let dir = PcDirectory::try_from([
+            pc(Some(("a@x.ch", employee.clone())), price(3000)),
+            pc(Some(("a@x.ch", employee)), price(1500)),
+            pc(Some(("b@minisoft.com", contractor("minisoft"))), price(900)),
+            pc(Some(("c@minisoft.com", contractor("minisoft"))), price(900)),
+            pc(
+                Some(("d@x.ch", Affiliation::Intern { engagement: None })),
+                None,
+            ),
+            pc(None, price(600)),
+        ])
+        .unwrap();
+
+        let chargeback = Chargeback::new(&dir, 2024).unwrap();
+        assert_eq!(chargeback.total, ChfAmout::from_major(2300));
+        assert_eq!(chargeback.unallocated, ChfAmout::from_major(200));
+        assert_eq!(chargeback.unpriced, vec![4]);
+        assert_eq!(chargeback.owners.len(), 3);
+        assert_eq!(chargeback.owners[0].pcs, vec![0, 1]);
+        assert_eq!(chargeback.companies.len(), 1);
+        assert_eq!(chargeback.companies[0].contractors, 2);
+        assert_eq!(chargeback.companies[0].cost, ChfAmout::from_major(600));
+        assert_eq!(chargeback.employees[0].cost, ChfAmout::from_major(1500));
+        assert_eq!(chargeback.employees[0].income_share, Some(0.015));
+
+        // Written off after three years.
+        assert_eq!(Chargeback::new(&dir, 2026).unwrap().total, ChfAmout::zero());
+        assert_eq!(Chargeback::new(&dir, 2022).unwrap().total, ChfAmout::zero());
+
+        let text = chargeback.render(ReportFormat::Text);
+        assert!(text.starts_with("Hardware costs in 2024: CHF 2'300.00, of which CHF 200.00"));
+        assert!(text.contains("  a@x.ch    Pat a  CHF 1'500.00  1.5%\n"));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut expensive = pc(None, Some(ChfAmout::from_minor(u64::MAX)));
+        expensive.hardware = expensive
+            .hardware
+            .map(|hw| hw.with_depreciation(Depreciation::StraightLine { years: 1 }));
+        let dir = PcDirectory::try_from([expensive.clone(), expensive]).unwrap();
+        assert!(matches!(
+            Chargeback::new(&dir, 2023),
+            Err(ChargebackError::Overflow { .. })
+        ));
+    }
+}
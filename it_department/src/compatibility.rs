@@ -0,0 +1,225 @@
+//! Which hardware an operating system needs.
+//!
+//! The [CompatibilityMatrix] maps releases of each operating system family to
+//! the minimum amount of RAM and the CPU features they require. A requirement
+//! applies to its release and all later releases of the same family, until a
+//! later requirement takes over.
+use std::fmt;
+
+use once_cell::sync::Lazy;
+use thiserror::Error;
+
+use crate::{
+    os::{OperatingSystem, WindowsRelease},
+    pc::{CpuFlag, CpuFlags, NumBytes, PcHardware, GIBIBYTE, MEBIBYTE},
+    pc_directory::{PcDirectory, PcDirectoryEntry},
+};
+
+/// The hardware needed to run an operating system.
+#[derive(Debug, Clone)]
+pub struct Requirements {
+    pub min_ram: NumBytes,
+    pub flags: CpuFlags,
+}
+
+impl Requirements {
+    pub fn new(min_ram: NumBytes, flags: impl IntoIterator<Item = CpuFlag>) -> Self {
+        Self {
+            min_ram,
+            flags: flags.into_iter().collect(),
+        }
+    }
+}
+
+pub struct CompatibilityMatrix {
+    // Sorted by release within each family.
+    entries: Vec<(OperatingSystem, Requirements)>,
+}
+
+// Like the email regex, the built-in matrix is built once, on first use.
+static BUILTIN: Lazy<CompatibilityMatrix> = Lazy::new(|| {
+    use CpuFlag::*;
+    use WindowsRelease::*;
+    CompatibilityMatrix::new([
+        (
+            OperatingSystem::windows(Xp),
+            Requirements::new(MEBIBYTE * 128, [MMX]),
+        ),
+        (
+            OperatingSystem::windows(Vista),
+            Requirements::new(GIBIBYTE, [MMX, SSE]),
+        ),
+        (
+            OperatingSystem::windows(Win10),
+            Requirements::new(GIBIBYTE * 2, [MMX, SSE]),
+        ),
+        // SEV stands in for the hardware security features Windows 11 insists on.
+        (
+            OperatingSystem::windows(Win11),
+            Requirements::new(GIBIBYTE * 4, [MMX, SSE, SEV]),
+        ),
+        (
+            OperatingSystem::macos(10, 0),
+            Requirements::new(GIBIBYTE * 2, [MMX, SSE]),
+        ),
+        (
+            OperatingSystem::macos(11, 0),
+            Requirements::new(GIBIBYTE * 4, [MMX, SSE]),
+        ),
+        (
+            OperatingSystem::linux(2, 0),
+            Requirements::new(MEBIBYTE * 64, []),
+        ),
+        (
+            OperatingSystem::linux(5, 0),
+            Requirements::new(MEBIBYTE * 512, [MMX, SSE]),
+        ),
+    ])
+});
+
+impl CompatibilityMatrix {
+    pub fn new(entries: impl IntoIterator<Item = (OperatingSystem, Requirements)>) -> Self {
+        let mut entries: Vec<_> = entries.into_iter().collect();
+        entries.sort_by_key(|(os, _)| (os.family(), os.version, os.build));
+        Self { entries }
+    }
+
+    /// The matrix that is enforced when PCs are added to a [PcDirectory] or
+    /// their operating system is updated.
+    pub fn builtin() -> &'static Self {
+        &BUILTIN
+    }
+
+    /// The requirements of `os`, or `None` if the matrix knows nothing about
+    /// it.
+    pub fn requirements(&self, os: &OperatingSystem) -> Option<&Requirements> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(release, _)| os.cmp_release(release).is_some_and(|o| o.is_ge()))
+            .map(|(_, requirements)| requirements)
+    }
+
+    /// Check whether `hardware` can run `os`.
+    pub fn check(
+        &self,
+        os: &OperatingSystem,
+        hardware: &PcHardware,
+    ) -> Result<(), Incompatibility> {
+        let Some(requirements) = self.requirements(os) else {
+            return Ok(());
+        };
+        let missing_flags: Vec<_> = requirements
+            .flags
+            .difference(&hardware.flags)
+            .iter()
+            .collect();
+        let insufficient_ram = hardware.ram < requirements.min_ram;
+
+        if missing_flags.is_empty() && !insufficient_ram {
+            return Ok(());
+        }
+        Err(Incompatibility {
+            os: os.clone(),
+            required_ram: insufficient_ram.then_some(requirements.min_ram),
+            available_ram: hardware.ram,
+            missing_flags,
+        })
+    }
+
+    /// All PCs of the directory that cannot be upgraded to `target`.
+    pub fn incompatible_pcs<'a>(
+        &self,
+        dir: &'a PcDirectory,
+        target: &OperatingSystem,
+    ) -> Vec<(&'a PcDirectoryEntry, Incompatibility)> {
+        dir.iter_pcs()
+            .filter_map(|pc| self.check(target, &pc.hardware).err().map(|e| (pc, e)))
+            .collect()
+    }
+}
+
+/// Why some hardware cannot run an operating system.
+#[derive(Debug, Clone, Error)]
+pub struct Incompatibility {
+    pub os: OperatingSystem,
+    /// The RAM needed, if there is not enough.
+    pub required_ram: Option<NumBytes>,
+    pub available_ram: NumBytes,
+    pub missing_flags: Vec<CpuFlag>,
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} needs", self.os)?;
+        if let Some(required) = self.required_ram {
+            write!(
+                f,
+                " at least {required} of RAM (available: {})",
+                self.available_ram
+            )?;
+            if !self.missing_flags.is_empty() {
+                write!(f, " and")?;
+            }
+        }
+        if !self.missing_flags.is_empty() {
+            write!(f, " the CPU features {:?}", self.missing_flags)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pc_directory::get_directory;
+
+    use super::*;
+
+    #[test]
+    fn test_requirements_apply_to_later_releases() {
+        let matrix = CompatibilityMatrix::builtin();
+        let win7 = matrix
+            .requirements(&OperatingSystem::windows(WindowsRelease::Win7))
+            .unwrap();
+        assert_eq!(win7.min_ram, GIBIBYTE);
+        let linux = matrix.requirements(&OperatingSystem::linux(6, 22)).unwrap();
+        assert_eq!(linux.min_ram, MEBIBYTE * 512);
+        assert!(matrix.requirements(&OperatingSystem::linux(1, 0)).is_none());
+    }
+
+    #[test]
+    fn test_check() {
+        let matrix = CompatibilityMatrix::builtin();
+        let win11 = OperatingSystem::windows(WindowsRelease::Win11);
+        assert!(matrix
+            .check(&win11, &PcHardware::beefy_workstation())
+            .is_ok());
+
+        let err = matrix.check(&win11, &PcHardware::normal()).unwrap_err();
+        assert_eq!(err.missing_flags, vec![CpuFlag::SEV]);
+        assert!(err.required_ram.is_none());
+
+        let tiny = PcHardware {
+            ram: GIBIBYTE * 2,
+            ..PcHardware::normal()
+        };
+        let err = matrix.check(&win11, &tiny).unwrap_err();
+        assert_eq!(err.required_ram, Some(GIBIBYTE * 4));
+        assert_eq!(
+            err.to_string(),
+            "Windows 11 needs at least 4 GiB of RAM (available: 2 GiB) and the CPU features [SEV]"
+        );
+    }
+
+    #[test]
+    fn test_incompatible_pcs() {
+        let dir = get_directory();
+        let ids: Vec<_> = CompatibilityMatrix::builtin()
+            .incompatible_pcs(&dir, &OperatingSystem::windows(WindowsRelease::Win11))
+            .into_iter()
+            .map(|(pc, _)| pc.id())
+            .collect();
+        // Don and Lex only have normal PCs.
+        assert_eq!(ids, vec![3, 4]);
+    }
+}
@@ -0,0 +1,274 @@
+//! Operating system lifecycle policy.
+//!
+//! Which operating system versions are acceptable changes over time, so it is
+//! not hard-coded but declared in a policy file (TOML). The policy consists of
+//! a list of rules; the first rule that covers an operating system determines
+//! its lifecycle:
+//!
+//! ```toml
+//! [[rule]]
+//! family = "windows"
+//! from = "Windows Vista"
+//! before = "Windows 7"
+//! deprecated = 2012-04-10
+//! end_of_life = 2017-04-11
+//! upgrade_to = "Windows 11"
+//! ```
+//!
+//! `from` (inclusive) and `before` (exclusive) are optional and narrow the rule
+//! down to a range of releases within the family. Editions, distributions and
+//! architectures are not taken into account.
+use std::{fmt, fs, path::Path};
+
+use chrono::NaiveDate;
+use serde::{de::Error as _, Deserialize, Deserializer};
+use thiserror::Error;
+
+use crate::{
+    os::{OperatingSystem, OsFamily},
+    pc_directory::{PcDirectory, PcDirectoryEntry},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompliancePolicy {
+    #[serde(rename = "rule", default)]
+    pub rules: Vec<LifecycleRule>,
+}
+
+/// The lifecycle of a range of operating system versions of one family.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LifecycleRule {
+    pub family: OsFamily,
+    /// Lowest version covered by this rule (inclusive).
+    pub from: Option<OperatingSystem>,
+    /// Versions covered by this rule are lower than this one.
+    pub before: Option<OperatingSystem>,
+    /// From this day on, the versions should be replaced.
+    #[serde(default, deserialize_with = "toml_date")]
+    pub deprecated: Option<NaiveDate>,
+    /// From this day on, the versions must no longer be used.
+    #[serde(default, deserialize_with = "toml_date")]
+    pub end_of_life: Option<NaiveDate>,
+    /// What to upgrade to once the versions are deprecated.
+    pub upgrade_to: Option<OperatingSystem>,
+}
+
+// TOML has its own date type, which does not deserialize into a chrono date
+// directly.
+fn toml_date<'de, D: Deserializer<'de>>(d: D) -> Result<Option<NaiveDate>, D::Error> {
+    let Some(datetime) = Option::<toml::value::Datetime>::deserialize(d)? else {
+        return Ok(None);
+    };
+    let Some(date) = datetime.date else {
+        return Err(D::Error::custom("expected a date"));
+    };
+    NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())
+        .map(Some)
+        .ok_or_else(|| D::Error::custom(format!("invalid date {date}")))
+}
+
+impl LifecycleRule {
+    fn covers(&self, os: &OperatingSystem) -> bool {
+        use std::cmp::Ordering::*;
+        os.family() == self.family
+            && self
+                .from
+                .as_ref()
+                .map(|from| matches!(os.cmp_release(from), Some(Greater | Equal)))
+                .unwrap_or(true)
+            && self
+                .before
+                .as_ref()
+                .map(|before| os.cmp_release(before) == Some(Less))
+                .unwrap_or(true)
+    }
+}
+
+/// The classification of an operating system on a given day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComplianceStatus {
+    Supported,
+    Deprecated {
+        since: NaiveDate,
+        upgrade_to: Option<OperatingSystem>,
+    },
+    EndOfLife {
+        since: NaiveDate,
+        upgrade_to: Option<OperatingSystem>,
+    },
+    /// None of the rules covers the operating system.
+    Unknown,
+}
+
+impl ComplianceStatus {
+    pub fn is_compliant(&self) -> bool {
+        matches!(self, Self::Supported)
+    }
+
+    pub fn upgrade_to(&self) -> Option<&OperatingSystem> {
+        match self {
+            Self::Deprecated { upgrade_to, .. } | Self::EndOfLife { upgrade_to, .. } => {
+                upgrade_to.as_ref()
+            }
+            Self::Supported | Self::Unknown => None,
+        }
+    }
+}
+
+impl fmt::Display for ComplianceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Supported => write!(f, "supported"),
+            Self::Deprecated { since, .. } => write!(f, "deprecated since {since}"),
+            Self::EndOfLife { since, .. } => write!(f, "end of life since {since}"),
+            Self::Unknown => write!(f, "not covered by the policy"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ComplianceError {
+    #[error("Could not read the policy file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The policy is malformed: {0}")]
+    Format(#[from] toml::de::Error),
+}
+
+impl CompliancePolicy {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ComplianceError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(s: &str) -> Result<Self, ComplianceError> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Classify `os` as of `today`.
+    pub fn evaluate(&self, os: &OperatingSystem, today: NaiveDate) -> ComplianceStatus {
+        let Some(rule) = self.rules.iter().find(|r| r.covers(os)) else {
+            return ComplianceStatus::Unknown;
+        };
+        let upgrade_to = rule.upgrade_to.clone();
+        match (rule.deprecated, rule.end_of_life) {
+            (_, Some(since)) if since <= today => ComplianceStatus::EndOfLife { since, upgrade_to },
+            (Some(since), _) if since <= today => {
+                ComplianceStatus::Deprecated { since, upgrade_to }
+            }
+            _ => ComplianceStatus::Supported,
+        }
+    }
+
+    /// Classify every PC of the directory as of `today`.
+    pub fn classify<'a>(
+        &self,
+        dir: &'a PcDirectory,
+        today: NaiveDate,
+    ) -> Vec<(&'a PcDirectoryEntry, ComplianceStatus)> {
+        dir.iter_pcs()
+            .map(|pc| (pc, self.evaluate(&pc.os(), today)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        os::{WindowsEdition, WindowsRelease},
+        pc_directory::get_directory,
+    };
+
+    use super::*;
+
+    const POLICY: &str = r#"
+        [[rule]]
+        family = "windows"
+        before = "Windows 7"
+        end_of_life = 2017-04-11
+        upgrade_to = "Windows 11"
+
+        [[rule]]
+        family = "windows"
+        from = "Windows 7"
+        before = "Windows 11"
+        deprecated = 2015-01-13
+        end_of_life = 2020-01-14
+        upgrade_to = "Windows 11"
+
+        [[rule]]
+        family = "windows"
+
+        [[rule]]
+        family = "linux"
+        before = "Linux 5.0"
+        deprecated = 2023-01-01
+        upgrade_to = "Linux 6.22"
+
+        [[rule]]
+        family = "linux"
+    "#;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        use WindowsRelease::*;
+        let policy = CompliancePolicy::parse(POLICY).unwrap();
+        let today = date(2019, 6, 1);
+
+        assert!(matches!(
+            policy.evaluate(&OperatingSystem::windows(Xp), today),
+            ComplianceStatus::EndOfLife {
+                upgrade_to: Some(os),
+                ..
+            } if os == OperatingSystem::windows(Win11)
+        ));
+        assert!(matches!(
+            policy.evaluate(&OperatingSystem::windows(Win7), today),
+            ComplianceStatus::Deprecated { .. }
+        ));
+        assert!(matches!(
+            policy.evaluate(&OperatingSystem::windows(Win7), date(2020, 1, 14)),
+            ComplianceStatus::EndOfLife { .. }
+        ));
+        // Editions do not matter.
+        assert_eq!(
+            policy.evaluate(
+                &OperatingSystem::windows(Win11).with_edition(WindowsEdition::Pro),
+                today
+            ),
+            ComplianceStatus::Supported
+        );
+        assert_eq!(
+            policy.evaluate(&OperatingSystem::linux(4, 19), today),
+            ComplianceStatus::Supported
+        );
+        assert_eq!(
+            policy.evaluate(&OperatingSystem::macos(10, 14), today),
+            ComplianceStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn test_classify_directory() {
+        let policy = CompliancePolicy::parse(POLICY).unwrap();
+        let dir = get_directory();
+        let non_compliant: Vec<_> = policy
+            .classify(&dir, date(2024, 1, 1))
+            .into_iter()
+            .filter(|(_, status)| !status.is_compliant())
+            .map(|(pc, _)| pc.id())
+            .collect();
+        // Sue's Mac is not covered, Don and Lex run Vista.
+        assert_eq!(non_compliant, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_malformed_policy() {
+        assert!(matches!(
+            CompliancePolicy::parse("[[rule]]\nfamily = \"beos\""),
+            Err(ComplianceError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,379 @@
+//! CPU features.
+//!
+//! [CpuFlag] knows the features we care about by the names the Linux kernel
+//! uses for them in `/proc/cpuinfo`. Any other feature is kept as
+//! [CpuFlag::Unknown], so inventory data does not lose information. Sets of
+//! features are stored in [CpuFlags], a bitset for the known features.
+use std::{collections::BTreeSet, convert::Infallible, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Who makes the CPUs that support a feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CpuVendor {
+    Intel,
+    Amd,
+    Arm,
+}
+
+// Each feature is listed once, together with its name in /proc/cpuinfo and its
+// vendor. `None` means the feature is available from several vendors.
+macro_rules! cpu_flags {
+    ($($flag:ident = $name:literal, $vendor:expr;)*) => {
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        #[serde(from = "String", into = "String")]
+        pub enum CpuFlag {
+            $($flag,)*
+            /// A feature without a variant of its own, by its name.
+            Unknown(String),
+        }
+
+        // The position of each known feature in the bitset of [CpuFlags].
+        #[allow(clippy::upper_case_acronyms)]
+        enum Bit {
+            $($flag,)*
+        }
+
+        impl CpuFlag {
+            /// All features with a variant of their own.
+            pub const KNOWN: &'static [CpuFlag] = &[$(CpuFlag::$flag,)*];
+
+            /// The name of the feature in `/proc/cpuinfo`.
+            pub fn name(&self) -> &str {
+                match self {
+                    $(Self::$flag => $name,)*
+                    Self::Unknown(name) => name,
+                }
+            }
+
+            pub fn vendor(&self) -> Option<CpuVendor> {
+                use CpuVendor::*;
+                match self {
+                    $(Self::$flag => $vendor,)*
+                    Self::Unknown(name) => Self::known(name).and_then(|flag| flag.vendor()),
+                }
+            }
+
+            fn bit(&self) -> Option<u32> {
+                match self {
+                    $(Self::$flag => Some(Bit::$flag as u32),)*
+                    Self::Unknown(name) => Self::known(name).and_then(|flag| flag.bit()),
+                }
+            }
+
+            /// The feature called `name`. Names are case-insensitive.
+            pub fn from_name(name: &str) -> Self {
+                let name = name.trim().to_lowercase();
+                Self::known(&name).unwrap_or(Self::Unknown(name))
+            }
+
+            // The known feature called `name`, by its name in /proc/cpuinfo or,
+            // as directories saved before the catalogue grew did, its variant.
+            fn known(name: &str) -> Option<Self> {
+                let name = name.trim();
+                match name.to_lowercase().as_str() {
+                    $($name => Some(Self::$flag),)*
+                    $(_ if name.eq_ignore_ascii_case(stringify!($flag)) => Some(Self::$flag),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+cpu_flags! {
+    MMX = "mmx", None;
+    SSE = "sse", None;
+    SSE2 = "sse2", None;
+    // The kernel calls SSE3 "Prescott New Instructions".
+    SSE3 = "pni", None;
+    SSSE3 = "ssse3", None;
+    SSE4_1 = "sse4_1", None;
+    SSE4_2 = "sse4_2", None;
+    POPCNT = "popcnt", None;
+    AVX = "avx", None;
+    AVX2 = "avx2", None;
+    FMA = "fma", None;
+    F16C = "f16c", None;
+    BMI1 = "bmi1", None;
+    BMI2 = "bmi2", None;
+    AVX512F = "avx512f", None;
+    AVX512CD = "avx512cd", None;
+    AVX512BW = "avx512bw", None;
+    AVX512DQ = "avx512dq", None;
+    AVX512VL = "avx512vl", None;
+    AVX512VNNI = "avx512_vnni", None;
+    // ARM calls its AES instructions the same.
+    AES = "aes", None;
+    PCLMULQDQ = "pclmulqdq", None;
+    SHA = "sha_ni", None;
+    RDRAND = "rdrand", None;
+    RDSEED = "rdseed", None;
+    NX = "nx", None;
+    LM = "lm", None;
+    VMX = "vmx", Some(Intel);
+    SMX = "smx", Some(Intel);
+    SGX = "sgx", Some(Intel);
+    // Together HLE and RTM make up TSX.
+    HLE = "hle", Some(Intel);
+    RTM = "rtm", Some(Intel);
+    SVM = "svm", Some(Amd);
+    SME = "sme", Some(Amd);
+    SEV = "sev", Some(Amd);
+    SEVES = "sev_es", Some(Amd);
+    SSE4A = "sse4a", Some(Amd);
+    FMA4 = "fma4", Some(Amd);
+    XOP = "xop", Some(Amd);
+    NEON = "asimd", Some(Arm);
+    SHA1 = "sha1", Some(Arm);
+    SHA2 = "sha2", Some(Arm);
+    CRC32 = "crc32", Some(Arm);
+    ATOMICS = "atomics", Some(Arm);
+    SVE = "sve", Some(Arm);
+}
+
+// All known features must fit into the bitset.
+const _: () = assert!(CpuFlag::KNOWN.len() <= u64::BITS as usize);
+
+impl fmt::Display for CpuFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CpuFlag {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from_name(s))
+    }
+}
+
+impl From<String> for CpuFlag {
+    fn from(value: String) -> Self {
+        Self::from_name(&value)
+    }
+}
+
+impl From<CpuFlag> for String {
+    fn from(value: CpuFlag) -> Self {
+        value.name().to_owned()
+    }
+}
+
+/// A set of CPU features.
+///
+/// Set operations on known features are cheap bit operations, unknown
+/// features are kept by name.
+#[derive(Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "Vec<CpuFlag>", into = "Vec<CpuFlag>")]
+pub struct CpuFlags {
+    known: u64,
+    unknown: BTreeSet<String>,
+}
+
+impl CpuFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `flag`, returning whether it was not in the set yet.
+    pub fn insert(&mut self, flag: CpuFlag) -> bool {
+        match flag.bit() {
+            Some(bit) => {
+                let absent = self.known & 1 << bit == 0;
+                self.known |= 1 << bit;
+                absent
+            }
+            None => self.unknown.insert(flag.name().to_owned()),
+        }
+    }
+
+    /// Remove `flag`, returning whether it was in the set.
+    pub fn remove(&mut self, flag: &CpuFlag) -> bool {
+        match flag.bit() {
+            Some(bit) => {
+                let present = self.known & 1 << bit != 0;
+                self.known &= !(1 << bit);
+                present
+            }
+            None => self.unknown.remove(flag.name()),
+        }
+    }
+
+    pub fn contains(&self, flag: &CpuFlag) -> bool {
+        match flag.bit() {
+            Some(bit) => self.known & 1 << bit != 0,
+            None => self.unknown.contains(flag.name()),
+        }
+    }
+
+    /// Whether all flags of `other` are in this set as well.
+    pub fn is_superset(&self, other: &CpuFlags) -> bool {
+        self.known & other.known == other.known && self.unknown.is_superset(&other.unknown)
+    }
+
+    pub fn union(&self, other: &CpuFlags) -> CpuFlags {
+        Self {
+            known: self.known | other.known,
+            unknown: self.unknown.union(&other.unknown).cloned().collect(),
+        }
+    }
+
+    pub fn intersection(&self, other: &CpuFlags) -> CpuFlags {
+        Self {
+            known: self.known & other.known,
+            unknown: self.unknown.intersection(&other.unknown).cloned().collect(),
+        }
+    }
+
+    /// The flags of this set that are not in `other`.
+    pub fn difference(&self, other: &CpuFlags) -> CpuFlags {
+        Self {
+            known: self.known & !other.known,
+            unknown: self.unknown.difference(&other.unknown).cloned().collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.known.count_ones() as usize + self.unknown.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.known == 0 && self.unknown.is_empty()
+    }
+
+    /// The flags in the set, known ones first in the order of [CpuFlag::KNOWN],
+    /// followed by the unknown ones in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = CpuFlag> + '_ {
+        CpuFlag::KNOWN
+            .iter()
+            .filter(|flag| self.contains(flag))
+            .cloned()
+            .chain(self.unknown.iter().cloned().map(CpuFlag::Unknown))
+    }
+}
+
+impl FromIterator<CpuFlag> for CpuFlags {
+    fn from_iter<T: IntoIterator<Item = CpuFlag>>(iter: T) -> Self {
+        let mut flags = Self::new();
+        flags.extend(iter);
+        flags
+    }
+}
+
+impl Extend<CpuFlag> for CpuFlags {
+    fn extend<T: IntoIterator<Item = CpuFlag>>(&mut self, iter: T) {
+        for flag in iter {
+            self.insert(flag);
+        }
+    }
+}
+
+impl From<Vec<CpuFlag>> for CpuFlags {
+    fn from(value: Vec<CpuFlag>) -> Self {
+        value.into_iter().collect()
+    }
+}
+
+impl From<CpuFlags> for Vec<CpuFlag> {
+    fn from(value: CpuFlags) -> Self {
+        value.iter().collect()
+    }
+}
+
+impl fmt::Debug for CpuFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// The names of the flags, separated by spaces like in `/proc/cpuinfo`.
+impl fmt::Display for CpuFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, flag) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{flag}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(names: &str) -> CpuFlags {
+        names.split_whitespace().map(CpuFlag::from_name).collect()
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(CpuFlag::from_name("pni"), CpuFlag::SSE3);
+        // Directories saved before the catalogue grew used the variant names.
+        assert_eq!(CpuFlag::from_name("MMX"), CpuFlag::MMX);
+        assert_eq!(CpuFlag::from_name("SSE3"), CpuFlag::SSE3);
+        assert_eq!(CpuFlag::from_name("AVX512VNNI"), CpuFlag::AVX512VNNI);
+        assert_eq!(CpuFlag::from_name(" AVX2 "), CpuFlag::AVX2);
+        for flag in CpuFlag::KNOWN {
+            assert_eq!(&CpuFlag::from_name(&format!("{flag:?}")), flag);
+        }
+        assert_eq!(
+            CpuFlag::from_name("fancy_new_thing"),
+            CpuFlag::Unknown("fancy_new_thing".into())
+        );
+        for flag in CpuFlag::KNOWN {
+            assert_eq!(&CpuFlag::from_name(flag.name()), flag);
+        }
+        assert_eq!(CpuFlag::RTM.vendor(), Some(CpuVendor::Intel));
+        assert_eq!(CpuFlag::AVX2.vendor(), None);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let pc = flags("fpu mmx sse sse2 avx avx2 aes fancy");
+        let wanted = flags("avx2 aes");
+        assert!(pc.is_superset(&wanted));
+        assert!(!wanted.is_superset(&pc));
+        assert!(!pc.is_superset(&flags("avx2 aes sha_ni")));
+        assert!(pc.is_superset(&flags("fancy")));
+        assert!(!pc.is_superset(&flags("other")));
+
+        assert_eq!(
+            pc.intersection(&flags("aes sha_ni fancy")),
+            flags("aes fancy")
+        );
+        assert_eq!(wanted.union(&flags("sha_ni")), flags("avx2 aes sha_ni"));
+        assert_eq!(
+            pc.difference(&flags("mmx sse fpu")),
+            flags("sse2 avx avx2 aes fancy")
+        );
+        assert_eq!(pc.len(), 8);
+
+        let mut set = CpuFlags::new();
+        assert!(set.insert(CpuFlag::SSE));
+        assert!(!set.insert(CpuFlag::SSE));
+        assert!(set.remove(&CpuFlag::SSE));
+        assert!(set.is_empty());
+
+        // A known feature spelled as unknown is still the known one.
+        assert!(set.insert(CpuFlag::Unknown("avx2".into())));
+        assert_eq!(set, flags("avx2"));
+        assert!(set.contains(&CpuFlag::Unknown("avx2".into())));
+        assert_eq!(
+            CpuFlag::Unknown("vmx".into()).vendor(),
+            Some(CpuVendor::Intel)
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let pc = flags("mmx sse4_2 avx512_vnni fancy");
+        let json = serde_json::to_string(&pc).unwrap();
+        assert_eq!(json, r#"["mmx","sse4_2","avx512_vnni","fancy"]"#);
+        assert_eq!(serde_json::from_str::<CpuFlags>(&json).unwrap(), pc);
+        assert_eq!(pc.to_string(), "mmx sse4_2 avx512_vnni fancy");
+    }
+}
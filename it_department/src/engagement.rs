@@ -0,0 +1,437 @@
+//! The end of the engagements of contractors and interns.
+//!
+//! An [EngagementSweep] is meant to run once a day. It reminds owners whose
+//! engagement ends soon and handles the PCs of owners whose engagement has
+//! ended, according to its [ExpiryAction].
+use std::{collections::BTreeMap, str::FromStr};
+
+use chrono::NaiveDate;
+use thiserror::Error;
+
+use crate::{
+    access::{Caller, Permission},
+    pc_directory::{PcDirectory, PcDirectoryError},
+    person::{EmailAddr, Engagement, PersonId},
+};
+
+/// What happens to the PCs of owners whose engagement has ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExpiryAction {
+    /// Only report the PCs.
+    #[default]
+    Flag,
+    /// Turn the PCs off.
+    PowerOff,
+    /// Take the PCs back, see [PcDirectory::reclaim_as].
+    Reclaim,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Unknown action {0:?}; use flag, power-off or reclaim.")]
+pub struct UnknownAction(String);
+
+impl FromStr for ExpiryAction {
+    type Err = UnknownAction;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "flag" => Ok(Self::Flag),
+            "power-off" => Ok(Self::PowerOff),
+            "reclaim" => Ok(Self::Reclaim),
+            _ => Err(UnknownAction(s.into())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub pc: usize,
+    pub owner: EmailAddr,
+    /// The last day of the owner's engagement.
+    pub end: NaiveDate,
+    pub status: FindingStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingStatus {
+    /// The engagement ends within the notice period. The owner is notified
+    /// once, by the first sweep within the notice period; `notified` tells
+    /// whether it was this one.
+    Expiring { days_left: i64, notified: bool },
+    /// The engagement has ended and the action was taken.
+    Ended { action: ExpiryAction },
+    /// The engagement has ended, but the action could not be taken, e.g.
+    /// because the PC is being maintained. The next sweep tries again.
+    Skipped {
+        action: ExpiryAction,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EngagementSweep {
+    notice_days: u32,
+    action: ExpiryAction,
+}
+
+impl EngagementSweep {
+    /// A sweep that notifies owners `notice_days` before their engagement
+    /// ends, and only flags the PCs of owners whose engagement has ended.
+    pub fn new(notice_days: u32) -> Self {
+        Self {
+            notice_days,
+            action: ExpiryAction::Flag,
+        }
+    }
+
+    pub fn with_action(self, action: ExpiryAction) -> Self {
+        Self { action, ..self }
+    }
+
+    /// Check the engagements of all owners on `today` on behalf of `caller`,
+    /// ordered by PC. The caller must be allowed to send the reminders; PCs
+    /// they may not turn off or take back are reported as skipped.
+    pub fn run(
+        &self,
+        caller: &Caller,
+        dir: &mut PcDirectory,
+        today: NaiveDate,
+    ) -> Result<Vec<Finding>, PcDirectoryError> {
+        dir.authorize(caller, Permission::SendEmail, None)?;
+        // The engagement and PCs of every owner whose engagement has an end.
+        let mut owners: BTreeMap<PersonId, (EmailAddr, Engagement, Vec<usize>)> = BTreeMap::new();
+        for pc in dir.iter_pcs() {
+            let (Some(owner), Some(id)) = (pc.owner.as_deref(), pc.owner_id()) else {
+                continue;
+            };
+            match owner.affiliation.engagement() {
+                Some(engagement) if engagement.end.is_some() => owners
+                    .entry(id)
+                    .or_insert((owner.email.clone(), *engagement, vec![]))
+                    .2
+                    .push(pc.id()),
+                _ => (),
+            }
+        }
+
+        let mut findings = vec![];
+        for (id, (owner, engagement, pcs)) in owners {
+            let end = engagement.end.expect("only engagements with an end");
+            let days_left = (end - today).num_days();
+            if days_left < 0 {
+                for &pc in &pcs {
+                    let result = match self.action {
+                        ExpiryAction::Flag => Ok(()),
+                        ExpiryAction::PowerOff => dir.power_off_as(caller, pc),
+                        ExpiryAction::Reclaim => dir.reclaim_as(caller, pc).map(|_| ()),
+                    };
+                    let action = self.action;
+                    findings.push(Finding {
+                        pc,
+                        owner: owner.clone(),
+                        end,
+                        status: match result {
+                            Ok(()) => FindingStatus::Ended { action },
+                            Err(e) => FindingStatus::Skipped {
+                                action,
+                                reason: e.to_string(),
+                            },
+                        },
+                    });
+                }
+            } else if days_left <= self.notice_days.into() {
+                // An earlier reminder only counts if it was sent within the
+                // notice period, in case the engagement was extended since.
+                let notified = dir
+                    .last_reminded(id)
+                    .map_or(true, |r| (end - r).num_days() > self.notice_days.into());
+                if notified {
+                    dir.set_reminded(id, today);
+                    let ids: Vec<_> = pcs.iter().map(|id| id.to_string()).collect();
+                    dir.deliver_or_defer(
+                        &owner,
+                        format!(
+                            "Your engagement ends on {end}. Please back up your data on PC {} \
+                             before then.",
+                            ids.join(", ")
+                        ),
+                    );
+                }
+                findings.extend(pcs.into_iter().map(|pc| Finding {
+                    pc,
+                    owner: owner.clone(),
+                    end,
+                    status: FindingStatus::Expiring {
+                        days_left,
+                        notified,
+                    },
+                }));
+            }
+        }
+        findings.sort_by_key(|f| f.pc);
+        Ok(findings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        access::Role,
+        pc::PcBuilder,
+        pc_directory::OperationalState,
+        person::{Affiliation, ChfAmout, Engagement, PersonBuilder},
+    };
+
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn pc(email: &str, affiliation: Affiliation) -> PcBuilder {
+        PcBuilder {
+            owner: Some(
+                PersonBuilder::new()
+                    .with_first_name("Pat")
+                    .with_last_name("Doe")
+                    .with_email_address(email)
+                    .with_affiliation(affiliation)
+                    .build()
+                    .unwrap(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn admin() -> Caller {
+        Caller::new(EmailAddr::try_from("it@x.ch").unwrap(), [Role::Admin])
+    }
+
+    fn directory() -> PcDirectory {
+        let engagement = |end| Some(Engagement::new(date(1, 1), end));
+        let contractor = Affiliation::Contractor {
+            company_name: "minisoft".into(),
+            engagement: engagement(Some(date(6, 30))),
+        };
+        PcDirectory::try_from([
+            pc("con@minisoft.com", contractor.clone()),
+            pc("con@minisoft.com", contractor),
+            pc(
+                "intern@x.ch",
+                Affiliation::Intern {
+                    engagement: engagement(Some(date(5, 31))),
+                },
+            ),
+            pc(
+                "open@x.ch",
+                Affiliation::Intern {
+                    engagement: engagement(None),
+                },
+            ),
+            pc(
+                "employee@x.ch",
+                Affiliation::Employee {
+                    annual_income: ChfAmout::from_major(100_000),
+                },
+            ),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_notify_before_expiry() {
+        let mut dir = directory();
+        let findings = EngagementSweep::new(30)
+            .run(&admin(), &mut dir, date(5, 31))
+            .unwrap();
+        assert_eq!(findings.len(), 3);
+        assert_eq!(
+            findings[0].status,
+            FindingStatus::Expiring {
+                days_left: 30,
+                notified: true
+            }
+        );
+        assert_eq!(
+            dir.get_pc(0).unwrap().mailbox(),
+            vec!["Your engagement ends on 2024-06-30. Please back up your data on PC 0, 1 before then."]
+        );
+        // The intern's last day is today, but they were not reminded yet.
+        assert_eq!(
+            findings[2].status,
+            FindingStatus::Expiring {
+                days_left: 0,
+                notified: true
+            }
+        );
+
+        // Only one reminder.
+        let findings = EngagementSweep::new(30)
+            .run(&admin(), &mut dir, date(6, 1))
+            .unwrap();
+        assert!(findings.iter().all(|f| f.status
+            != FindingStatus::Expiring {
+                days_left: 29,
+                notified: true
+            }));
+        assert_eq!(dir.get_pc(0).unwrap().mailbox().len(), 1);
+    }
+
+    #[test]
+    fn test_reminder_after_missed_sweeps() {
+        let path =
+            PathBuf::from(std::env::var("TMPDIR").unwrap()).join("engagement_reminders.json");
+        let mut dir = directory();
+        // No sweep ran on the day the notice period started.
+        let findings = EngagementSweep::new(30)
+            .run(&admin(), &mut dir, date(6, 10))
+            .unwrap();
+        assert_eq!(
+            findings[0].status,
+            FindingStatus::Expiring {
+                days_left: 20,
+                notified: true
+            }
+        );
+        dir.save(&path).unwrap();
+
+        // The reminder is remembered across runs of the program.
+        let mut dir = PcDirectory::load(&path).unwrap();
+        let findings = EngagementSweep::new(30)
+            .run(&admin(), &mut dir, date(6, 11))
+            .unwrap();
+        let expiring = FindingStatus::Expiring {
+            days_left: 19,
+            notified: false,
+        };
+        assert_eq!(
+            (&findings[0].status, &findings[1].status),
+            (&expiring, &expiring)
+        );
+        // The reminder does not change the owner, so they can get another PC.
+        let contractor = Affiliation::Contractor {
+            company_name: "minisoft".into(),
+            engagement: Some(Engagement::new(date(1, 1), Some(date(6, 30)))),
+        };
+        dir.add_pc(pc("con@minisoft.com", contractor)).unwrap();
+        assert_eq!(
+            dir.get_pc(5).unwrap().owner_id(),
+            dir.get_pc(0).unwrap().owner_id()
+        );
+
+        // An extended engagement is reminded of again.
+        let contractor = dir.get_pc(0).unwrap().owner_id().unwrap();
+        dir.update_person(contractor, |p| {
+            p.affiliation.engagement_mut().unwrap().end = Some(date(8, 31))
+        })
+        .unwrap();
+        // Only the intern's PC is reported, as their engagement has ended.
+        let findings = EngagementSweep::new(30)
+            .run(&admin(), &mut dir, date(7, 15))
+            .unwrap();
+        assert_eq!(findings.iter().map(|f| f.pc).collect::<Vec<_>>(), vec![2]);
+        let findings = EngagementSweep::new(30)
+            .run(&admin(), &mut dir, date(8, 15))
+            .unwrap();
+        assert_eq!(
+            findings[0].status,
+            FindingStatus::Expiring {
+                days_left: 16,
+                notified: true
+            }
+        );
+        assert_eq!(dir.get_pc(0).unwrap().mailbox().len(), 2);
+    }
+
+    #[test]
+    fn test_expired() {
+        let mut dir = directory();
+        let flagged = EngagementSweep::new(30)
+            .run(&admin(), &mut dir, date(7, 1))
+            .unwrap();
+        assert_eq!(
+            flagged.iter().map(|f| f.pc).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert!(dir.get_pc(0).unwrap().operational_state().is_on());
+
+        let sweep = EngagementSweep::new(30).with_action(ExpiryAction::PowerOff);
+        let findings = sweep.run(&admin(), &mut dir, date(7, 1)).unwrap();
+        assert_eq!(
+            findings[0].status,
+            FindingStatus::Ended {
+                action: ExpiryAction::PowerOff
+            }
+        );
+        assert!(!dir.get_pc(1).unwrap().operational_state().is_on());
+        assert!(dir.get_pc(3).unwrap().operational_state().is_on());
+
+        let sweep = sweep.with_action(ExpiryAction::Reclaim);
+        assert_eq!(sweep.run(&admin(), &mut dir, date(7, 1)).unwrap().len(), 3);
+        assert!(dir.get_pc(2).unwrap().owner.is_none());
+        // Reclaimed PCs have no owner whose engagement could end.
+        assert!(sweep
+            .run(&admin(), &mut dir, date(7, 1))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_expired_during_maintenance() {
+        let mut dir = directory();
+        let lease = dir.acquire_maintenance_lock(0, "fix").unwrap().into_lease();
+        let sweep = EngagementSweep::new(30).with_action(ExpiryAction::PowerOff);
+        let findings = sweep.run(&admin(), &mut dir, date(7, 1)).unwrap();
+        assert!(matches!(
+            findings[0].status,
+            FindingStatus::Skipped {
+                action: ExpiryAction::PowerOff,
+                ..
+            }
+        ));
+        assert_eq!(
+            findings[1].status,
+            FindingStatus::Ended {
+                action: ExpiryAction::PowerOff
+            }
+        );
+        assert!(matches!(
+            dir.get_pc(0).unwrap().operational_state(),
+            OperationalState::BeingMaintained { .. }
+        ));
+
+        drop(dir.resume_maintenance(lease).unwrap());
+        let sweep = sweep.with_action(ExpiryAction::Reclaim);
+        let findings = sweep.run(&admin(), &mut dir, date(7, 1)).unwrap();
+        assert!(findings
+            .iter()
+            .all(|f| matches!(f.status, FindingStatus::Ended { .. })));
+        assert!(dir.get_pc(0).unwrap().owner.is_none());
+    }
+
+    #[test]
+    fn test_permissions() {
+        let mut dir = directory();
+        let email = |s: &str| EmailAddr::try_from(s).unwrap();
+        let owner = Caller::new(email("con@minisoft.com"), []);
+        let sweep = EngagementSweep::new(30).with_action(ExpiryAction::Reclaim);
+        assert!(matches!(
+            sweep.run(&owner, &mut dir, date(7, 1)),
+            Err(PcDirectoryError::PermissionDenied { .. })
+        ));
+
+        // The helpdesk may remind owners, but not take their PCs back.
+        let helpdesk = Caller::new(email("help@x.ch"), [Role::Helpdesk]);
+        let findings = sweep.run(&helpdesk, &mut dir, date(7, 1)).unwrap();
+        assert_eq!(
+            findings[0].status,
+            FindingStatus::Skipped {
+                action: ExpiryAction::Reclaim,
+                reason: "help@x.ch is not allowed to transfer PC 0.".into()
+            }
+        );
+        assert!(dir.get_pc(0).unwrap().owner.is_some());
+    }
+}
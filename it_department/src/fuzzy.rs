@@ -0,0 +1,212 @@
+//! Fuzzy search for owners by name.
+//!
+//! Names are compared in three ways, and the best one determines the
+//! similarity score between 0 and 1:
+//!
+//! - by edit distance, which catches typos like "Overkil",
+//! - by prefix, for names that were not typed completely, and
+//! - by the "Kölner Phonetik", a phonetic code for German names, which matches
+//!   e.g. "Meyer", "Maier" and "Mayr".
+use std::cmp::Ordering;
+
+use crate::pc_directory::{PcDirectory, PcDirectoryEntry};
+
+/// The number of characters to insert, delete, substitute or swap to get from
+/// `a` to `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // d[i][j] is the distance between the first i characters of a and the
+    // first j characters of b.
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=b.len() {
+        d[0][j] = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+// Umlauts and accents are written without them; what remains are the
+// uppercase letters A to Z.
+fn normalize(s: &str) -> Vec<char> {
+    s.chars()
+        .flat_map(|c| match c.to_lowercase().next().unwrap_or(c) {
+            'ä' | 'à' | 'á' | 'â' => vec!['A'],
+            'ö' | 'ò' | 'ó' | 'ô' => vec!['O'],
+            'ü' | 'ù' | 'ú' | 'û' => vec!['U'],
+            'é' | 'è' | 'ê' | 'ë' => vec!['E'],
+            'ß' => vec!['S', 'S'],
+            c if c.is_ascii_alphabetic() => vec![c.to_ascii_uppercase()],
+            _ => vec![],
+        })
+        .collect()
+}
+
+/// The "Kölner Phonetik" of a name, a code of digits that is the same for
+/// names that sound alike in German.
+pub fn cologne_phonetics(name: &str) -> String {
+    let letters = normalize(name);
+    let mut codes = String::new();
+    for (i, &c) in letters.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|p| letters[p]);
+        let next = letters.get(i + 1).copied();
+        let next_in = |set: &str| next.is_some_and(|n| set.contains(n));
+        let prev_in = |set: &str| prev.is_some_and(|p| set.contains(p));
+        let code = match c {
+            'A' | 'E' | 'I' | 'J' | 'O' | 'U' | 'Y' => "0",
+            'H' => "",
+            'B' => "1",
+            'P' if next_in("H") => "3",
+            'P' => "1",
+            'D' | 'T' if next_in("CSZ") => "8",
+            'D' | 'T' => "2",
+            'F' | 'V' | 'W' => "3",
+            'G' | 'K' | 'Q' => "4",
+            'C' if i == 0 && next_in("AHKLOQRUX") => "4",
+            'C' if i > 0 && next_in("AHKOQUX") && !prev_in("SZ") => "4",
+            'C' => "8",
+            'X' if prev_in("CKQ") => "8",
+            'X' => "48",
+            'L' => "5",
+            'M' | 'N' => "6",
+            'R' => "7",
+            'S' | 'Z' => "8",
+            _ => "",
+        };
+        codes.push_str(code);
+    }
+
+    let mut result = String::new();
+    let mut last = None;
+    for (i, c) in codes.chars().enumerate() {
+        if last != Some(c) && (c != '0' || i == 0) {
+            result.push(c);
+        }
+        last = Some(c);
+    }
+    result
+}
+
+/// How similar `name` is to what was searched for, between 0 (not at all) and
+/// 1 (equal, ignoring case).
+pub fn similarity(query: &str, name: &str) -> f64 {
+    let (query, name) = (query.trim().to_lowercase(), name.trim().to_lowercase());
+    if query.is_empty() || name.is_empty() {
+        return 0.0;
+    }
+    if query == name {
+        return 1.0;
+    }
+    let (query_len, name_len) = (query.chars().count(), name.chars().count());
+
+    let edit = 1.0 - edit_distance(&query, &name) as f64 / query_len.max(name_len) as f64;
+    // A prefix of at least two letters, the longer the better.
+    let prefix = match query_len >= 2 && name.starts_with(&query) {
+        true => 0.8 + 0.15 * query_len as f64 / name_len as f64,
+        false => 0.0,
+    };
+    let phonetics = cologne_phonetics(&query);
+    let phonetic = match !phonetics.is_empty() && phonetics == cologne_phonetics(&name) {
+        true => 0.9,
+        false => 0.0,
+    };
+    edit.max(prefix).max(phonetic)
+}
+
+/// A PC whose owner's name is similar to the one searched for.
+#[derive(Clone)]
+pub struct OwnerMatch<'a> {
+    pub pc: &'a PcDirectoryEntry,
+    pub score: f64,
+}
+
+/// All PCs whose owner's first and last name match `first` and `last` with a
+/// score of at least `min_score`, best matches first. If both names are given,
+/// the score is the average of both. Without names, all PCs match with a score
+/// of 1.
+pub fn search_owners<'a>(
+    dir: &'a PcDirectory,
+    first: Option<&str>,
+    last: Option<&str>,
+    min_score: f64,
+) -> Vec<OwnerMatch<'a>> {
+    let mut matches: Vec<_> = dir
+        .iter_pcs()
+        .filter_map(|pc| {
+            let score = match (first, last, pc.owner.as_deref()) {
+                (None, None, _) => 1.0,
+                (_, _, None) => return None,
+                (Some(first), None, Some(owner)) => similarity(first, &owner.first),
+                (None, Some(last), Some(owner)) => similarity(last, &owner.last),
+                (Some(first), Some(last), Some(owner)) => {
+                    (similarity(first, &owner.first) + similarity(last, &owner.last)) / 2.0
+                }
+            };
+            (score >= min_score).then_some(OwnerMatch { pc, score })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(Ordering::Equal)
+            .then(a.pc.id().cmp(&b.pc.id()))
+    });
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pc_directory::get_directory;
+
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("overkil", "overkill"), 1);
+        assert_eq!(edit_distance("drumph", "drumpf"), 1);
+        // Swapped letters count once.
+        assert_eq!(edit_distance("hnas", "hans"), 1);
+        assert_eq!(edit_distance("", "sue"), 3);
+    }
+
+    #[test]
+    fn test_cologne_phonetics() {
+        assert_eq!(cologne_phonetics("Müller-Lüdenscheidt"), "65752682");
+        assert_eq!(cologne_phonetics("Müller"), cologne_phonetics("Mueller"));
+        assert_eq!(cologne_phonetics("Meyer"), "67");
+        assert_eq!(cologne_phonetics("Maier"), cologne_phonetics("Mayr"));
+        assert_eq!(cologne_phonetics("Schmidt"), cologne_phonetics("Schmitt"));
+        assert_eq!(cologne_phonetics("Wikipedia"), "3412");
+    }
+
+    #[test]
+    fn test_search_owners() {
+        let dir = get_directory();
+        let found = search_owners(&dir, None, Some("Drumph"), 0.7);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].pc.id(), 3);
+
+        let found = search_owners(&dir, Some("Hans"), Some("Overkil"), 0.7);
+        assert_eq!(found[0].pc.id(), 1);
+        assert!(found[0].score > 0.9 && found[0].score < 1.0);
+
+        // Prefixes rank below exact matches.
+        let found = search_owners(&dir, Some("Kar"), None, 0.7);
+        assert_eq!(found[0].pc.id(), 5);
+        assert_eq!(search_owners(&dir, Some("Karl"), None, 0.7)[0].score, 1.0);
+
+        assert!(search_owners(&dir, Some("Xaver"), None, 0.7).is_empty());
+    }
+}
@@ -0,0 +1,537 @@
+//! Helpdesk tickets about PCs.
+//!
+//! A [Ticket] is about one PC and is opened by its owner or by someone on
+//! their behalf. It is assigned to a technician, collects comments and moves
+//! through the [TicketStatus]es until it is resolved:
+//!
+//! ```text
+//! Open -> InProgress <-> Waiting
+//!   \         |            /
+//!    `---> Resolved <-----'
+//! ```
+//!
+//! Maintenance for a ticket is started with [Helpdesk::start_maintenance] or
+//! booked with [Helpdesk::book_maintenance]. Its reason refers to the ticket,
+//! e.g. "T-3: Screen flickers". Like a [crate::rollout::Rollout], the tickets
+//! are plain data that can be saved to a file.
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    access::{Caller, Permission},
+    pc_directory::{MaintenanceHandle, PcDirectory, PcDirectoryError},
+    person::{EmailAddr, PersonId},
+    scheduler::{BookingId, MaintenanceScheduler, SchedulerError, Timestamp},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TicketId(pub u32);
+
+impl fmt::Display for TicketId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "T-{}", self.0)
+    }
+}
+
+impl FromStr for TicketId {
+    type Err = std::num::ParseIntError;
+
+    /// Parse "T-3" or just "3".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let n = s.strip_prefix("T-").unwrap_or(s);
+        n.parse().map(Self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TicketStatus {
+    /// Nobody works on the ticket yet.
+    Open,
+    /// The assigned technician works on the ticket.
+    InProgress,
+    /// Work cannot continue until something happens, e.g. the owner answers
+    /// or a part is delivered.
+    Waiting {
+        on: String,
+    },
+    Resolved {
+        resolution: String,
+        at: Timestamp,
+    },
+}
+
+impl TicketStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::InProgress => "in progress",
+            Self::Waiting { .. } => "waiting",
+            Self::Resolved { .. } => "resolved",
+        }
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        matches!(self, Self::Resolved { .. })
+    }
+}
+
+impl fmt::Display for TicketStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Waiting { on } => write!(f, "waiting on {on}"),
+            Self::Resolved { resolution, at } => write!(f, "resolved at {at}: {resolution}"),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Comment {
+    pub author: EmailAddr,
+    pub at: Timestamp,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ticket {
+    pub id: TicketId,
+    pub pc: usize,
+    /// The owner of the PC when the ticket was opened.
+    pub owner: Option<PersonId>,
+    pub opened_by: EmailAddr,
+    pub opened_at: Timestamp,
+    pub title: String,
+    pub status: TicketStatus,
+    /// The technician working on the ticket.
+    pub assignee: Option<EmailAddr>,
+    pub comments: Vec<Comment>,
+}
+
+impl Ticket {
+    /// The reason of maintenance done for this ticket.
+    pub fn maintenance_reason(&self) -> String {
+        format!("{}: {}", self.id, self.title)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TicketError {
+    #[error("There is no ticket {id}.")]
+    TicketNotFound { id: TicketId },
+    #[error("There is no PC with id {id}.")]
+    PcNotFound { id: usize },
+    #[error("Ticket {id} cannot go from {from} to {to}.")]
+    InvalidTransition {
+        id: TicketId,
+        from: &'static str,
+        to: &'static str,
+    },
+    #[error("Ticket {id} has to be assigned to a technician first.")]
+    Unassigned { id: TicketId },
+    #[error("Ticket {id} is resolved.")]
+    Resolved { id: TicketId },
+    #[error("Ticket {id} cannot be assigned to {}, who may not maintain its PC.", technician.as_ref())]
+    NotATechnician { id: TicketId, technician: EmailAddr },
+    #[error("Could not start the maintenance: {0}")]
+    Maintenance(#[from] PcDirectoryError),
+    #[error("Could not book the maintenance: {0}")]
+    Booking(#[from] SchedulerError),
+    #[error("Could not access the ticket file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The ticket file is malformed: {0}")]
+    Format(#[from] serde_json::Error),
+}
+
+/// All tickets, past and present.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Helpdesk {
+    tickets: BTreeMap<TicketId, Ticket>,
+}
+
+impl Helpdesk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a ticket about the PC with the given id. The owner of the PC is
+    /// recorded with the ticket.
+    pub fn open<S: ToString>(
+        &mut self,
+        dir: &PcDirectory,
+        pc: usize,
+        opened_by: EmailAddr,
+        title: S,
+        at: Timestamp,
+    ) -> Result<TicketId, TicketError> {
+        let entry = dir.get_pc(pc).ok_or(TicketError::PcNotFound { id: pc })?;
+        let id = TicketId(self.tickets.keys().next_back().map_or(1, |id| id.0 + 1));
+        self.tickets.insert(
+            id,
+            Ticket {
+                id,
+                pc,
+                owner: entry.owner_id(),
+                opened_by,
+                opened_at: at,
+                title: title.to_string(),
+                status: TicketStatus::Open,
+                assignee: None,
+                comments: vec![],
+            },
+        );
+        Ok(id)
+    }
+
+    pub fn get(&self, id: TicketId) -> Option<&Ticket> {
+        self.tickets.get(&id)
+    }
+
+    /// All tickets, oldest first.
+    pub fn tickets(&self) -> impl Iterator<Item = &Ticket> {
+        self.tickets.values()
+    }
+
+    /// The tickets that are not resolved yet, oldest first.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Ticket> {
+        self.tickets().filter(|t| !t.status.is_resolved())
+    }
+
+    pub fn for_pc(&self, pc: usize) -> impl Iterator<Item = &Ticket> {
+        self.tickets().filter(move |t| t.pc == pc)
+    }
+
+    pub fn for_owner(&self, owner: PersonId) -> impl Iterator<Item = &Ticket> {
+        self.tickets().filter(move |t| t.owner == Some(owner))
+    }
+
+    pub fn assigned_to<'a>(
+        &'a self,
+        technician: &'a EmailAddr,
+    ) -> impl Iterator<Item = &'a Ticket> {
+        self.tickets()
+            .filter(move |t| t.assignee.as_ref() == Some(technician))
+    }
+
+    /// Assign the ticket to a technician, replacing the previous one. The
+    /// technician must be allowed to maintain the ticket's PC.
+    pub fn assign(
+        &mut self,
+        dir: &PcDirectory,
+        id: TicketId,
+        technician: &Caller,
+    ) -> Result<(), TicketError> {
+        let ticket = self.unresolved_mut(id)?;
+        match dir.authorize(technician, Permission::Maintain, Some(ticket.pc)) {
+            Ok(()) => (),
+            Err(PcDirectoryError::PermissionDenied { .. }) => {
+                return Err(TicketError::NotATechnician {
+                    id,
+                    technician: technician.email.clone(),
+                })
+            }
+            Err(e) => return Err(e.into()),
+        }
+        ticket.assignee = Some(technician.email.clone());
+        Ok(())
+    }
+
+    pub fn comment<S: ToString>(
+        &mut self,
+        id: TicketId,
+        author: EmailAddr,
+        text: S,
+        at: Timestamp,
+    ) -> Result<(), TicketError> {
+        let ticket = self.get_mut(id)?;
+        ticket.comments.push(Comment {
+            author,
+            at,
+            text: text.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Start or resume work on the ticket. It has to be assigned.
+    pub fn start(&mut self, id: TicketId) -> Result<(), TicketError> {
+        let ticket = self.get_mut(id)?;
+        if ticket.assignee.is_none() {
+            return Err(TicketError::Unassigned { id });
+        }
+        transition(ticket, TicketStatus::InProgress, |from| {
+            matches!(from, TicketStatus::Open | TicketStatus::Waiting { .. })
+        })
+    }
+
+    /// Put the ticket on hold until something happens.
+    pub fn wait<S: ToString>(&mut self, id: TicketId, on: S) -> Result<(), TicketError> {
+        let ticket = self.get_mut(id)?;
+        let waiting = TicketStatus::Waiting { on: on.to_string() };
+        transition(ticket, waiting, |from| {
+            matches!(from, TicketStatus::Open | TicketStatus::InProgress)
+        })
+    }
+
+    pub fn resolve<S: ToString>(
+        &mut self,
+        id: TicketId,
+        resolution: S,
+        at: Timestamp,
+    ) -> Result<(), TicketError> {
+        let ticket = self.get_mut(id)?;
+        let resolved = TicketStatus::Resolved {
+            resolution: resolution.to_string(),
+            at,
+        };
+        transition(ticket, resolved, |from| !from.is_resolved())
+    }
+
+    /// Open a resolved ticket again, e.g. because the problem came back.
+    pub fn reopen(&mut self, id: TicketId) -> Result<(), TicketError> {
+        let ticket = self.get_mut(id)?;
+        transition(ticket, TicketStatus::Open, TicketStatus::is_resolved)
+    }
+
+    /// Start maintenance of the ticket's PC on behalf of `caller`, who must
+    /// be allowed to maintain it. The ticket is assigned to the caller, unless
+    /// it is assigned already, and is in progress afterwards.
+    pub fn start_maintenance<'a>(
+        &mut self,
+        dir: &'a PcDirectory,
+        caller: &Caller,
+        id: TicketId,
+        at: Timestamp,
+    ) -> Result<MaintenanceHandle<'a>, TicketError> {
+        let ticket = self.unresolved_mut(id)?;
+        let handle =
+            dir.acquire_maintenance_lock_as(caller, ticket.pc, ticket.maintenance_reason())?;
+        ticket.assignee.get_or_insert_with(|| caller.email.clone());
+        ticket.status = TicketStatus::InProgress;
+        ticket.comments.push(Comment {
+            author: caller.email.clone(),
+            at,
+            text: "Maintenance started.".into(),
+        });
+        Ok(handle)
+    }
+
+    /// Book a maintenance window for the ticket's PC on behalf of `caller`,
+    /// who must be allowed to maintain it.
+    pub fn book_maintenance(
+        &self,
+        scheduler: &mut MaintenanceScheduler,
+        caller: &Caller,
+        id: TicketId,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<BookingId, TicketError> {
+        let ticket = self.get(id).ok_or(TicketError::TicketNotFound { id })?;
+        if ticket.status.is_resolved() {
+            return Err(TicketError::Resolved { id });
+        }
+        let reason = ticket.maintenance_reason();
+        Ok(scheduler.book_as(caller, [ticket.pc], start, end, reason)?)
+    }
+
+    /// Write the tickets to a JSON file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TicketError> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Read tickets previously written by [Helpdesk::save].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TicketError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    fn get_mut(&mut self, id: TicketId) -> Result<&mut Ticket, TicketError> {
+        self.tickets
+            .get_mut(&id)
+            .ok_or(TicketError::TicketNotFound { id })
+    }
+
+    fn unresolved_mut(&mut self, id: TicketId) -> Result<&mut Ticket, TicketError> {
+        let ticket = self.get_mut(id)?;
+        match ticket.status.is_resolved() {
+            true => Err(TicketError::Resolved { id }),
+            false => Ok(ticket),
+        }
+    }
+}
+
+// Move the ticket to `to` if `allowed` accepts its current status.
+fn transition(
+    ticket: &mut Ticket,
+    to: TicketStatus,
+    allowed: impl FnOnce(&TicketStatus) -> bool,
+) -> Result<(), TicketError> {
+    if !allowed(&ticket.status) {
+        return Err(TicketError::InvalidTransition {
+            id: ticket.id,
+            from: ticket.status.name(),
+            to: to.name(),
+        });
+    }
+    ticket.status = to;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::{
+        access::{Caller, Role},
+        pc_directory::{get_directory, OperationalState},
+    };
+
+    use super::*;
+
+    fn at(hour: u32) -> Timestamp {
+        NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn email(s: &str) -> EmailAddr {
+        EmailAddr::try_from(s).unwrap()
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let dir = get_directory();
+        let mut helpdesk = Helpdesk::new();
+        let id = helpdesk
+            .open(&dir, 3, email("don@drumpf.com"), "Screen flickers", at(8))
+            .unwrap();
+        assert_eq!(id.to_string(), "T-1");
+        assert_eq!(helpdesk.get(id).unwrap().owner, Some(PersonId(3)));
+        assert!(matches!(
+            helpdesk.open(&dir, 9, email("don@drumpf.com"), "?", at(8)),
+            Err(TicketError::PcNotFound { id: 9 })
+        ));
+
+        assert!(matches!(
+            helpdesk.start(id),
+            Err(TicketError::Unassigned { .. })
+        ));
+        let hans = Caller::new(email("hans@overkill.com"), [Role::Helpdesk]);
+        assert!(matches!(
+            helpdesk.assign(&dir, id, &Caller::new(email("sue@whatever.com"), [])),
+            Err(TicketError::NotATechnician { .. })
+        ));
+        assert_eq!(helpdesk.get(id).unwrap().assignee, None);
+        helpdesk.assign(&dir, id, &hans).unwrap();
+        helpdesk.start(id).unwrap();
+        helpdesk.wait(id, "a new cable").unwrap();
+        assert_eq!(
+            helpdesk.get(id).unwrap().status.to_string(),
+            "waiting on a new cable"
+        );
+        helpdesk
+            .comment(id, email("hans@overkill.com"), "Ordered.", at(9))
+            .unwrap();
+        helpdesk.resolve(id, "Replaced the cable", at(10)).unwrap();
+        assert!(matches!(
+            helpdesk.start(id),
+            Err(TicketError::InvalidTransition {
+                from: "resolved",
+                to: "in progress",
+                ..
+            })
+        ));
+        let karl = Caller::new(email("karl@keule.com"), [Role::Helpdesk]);
+        assert!(matches!(
+            helpdesk.assign(&dir, id, &karl),
+            Err(TicketError::Resolved { .. })
+        ));
+        helpdesk.reopen(id).unwrap();
+
+        assert_eq!(helpdesk.assigned_to(&hans.email).count(), 1);
+        assert_eq!(helpdesk.for_owner(PersonId(3)).count(), 1);
+        assert_eq!(helpdesk.get(id).unwrap().comments.len(), 1);
+    }
+
+    #[test]
+    fn test_maintenance_from_ticket() {
+        let dir = get_directory();
+        let mut helpdesk = Helpdesk::new();
+        let id = helpdesk
+            .open(
+                &dir,
+                3,
+                email("don@drumpf.com"),
+                "Upgrade to Windows 11",
+                at(8),
+            )
+            .unwrap();
+
+        let don = Caller::new(email("don@drumpf.com"), []);
+        assert!(matches!(
+            helpdesk.start_maintenance(&dir, &don, id, at(9)),
+            Err(TicketError::Maintenance(
+                PcDirectoryError::PermissionDenied { .. }
+            ))
+        ));
+
+        let karl = Caller::new(email("karl@keule.com"), [Role::Helpdesk]);
+        let handle = helpdesk.start_maintenance(&dir, &karl, id, at(9)).unwrap();
+        assert!(matches!(
+            dir.get_pc(3).unwrap().operational_state(),
+            OperationalState::BeingMaintained { reason } if reason == "T-1: Upgrade to Windows 11"
+        ));
+        let ticket = helpdesk.get(id).unwrap();
+        assert_eq!(ticket.status, TicketStatus::InProgress);
+        assert_eq!(ticket.assignee, Some(karl.email.clone()));
+        drop(handle);
+
+        let mut scheduler = MaintenanceScheduler::new(&dir);
+        assert!(matches!(
+            helpdesk.book_maintenance(&mut scheduler, &don, id, at(12), at(14)),
+            Err(TicketError::Booking(SchedulerError::Directory(
+                PcDirectoryError::PermissionDenied { .. }
+            )))
+        ));
+        let booking = helpdesk
+            .book_maintenance(&mut scheduler, &karl, id, at(12), at(14))
+            .unwrap();
+        let booked = scheduler.bookings().find(|b| b.id == booking).unwrap();
+        assert_eq!(booked.reason, "T-1: Upgrade to Windows 11");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::path::PathBuf::from(std::env::var("TMPDIR").unwrap()).join("tickets.json");
+        let dir = get_directory();
+        let mut helpdesk = Helpdesk::new();
+        helpdesk
+            .open(&dir, 0, email("maria@dingong.com"), "Slow", at(8))
+            .unwrap();
+        let id = helpdesk
+            .open(&dir, 1, email("hans@overkill.com"), "Noisy fan", at(9))
+            .unwrap();
+        helpdesk.resolve(id, "Cleaned", at(10)).unwrap();
+        helpdesk.save(&path).unwrap();
+
+        let helpdesk = Helpdesk::load(&path).unwrap();
+        assert_eq!(
+            helpdesk.unresolved().map(|t| t.id).collect::<Vec<_>>(),
+            vec![TicketId(1)]
+        );
+        assert!(helpdesk.get(id).unwrap().status.is_resolved());
+        assert_eq!("T-2".parse(), Ok(id));
+        assert_eq!("2".parse(), Ok(id));
+    }
+}
@@ -11,4 +11,26 @@
 /// +------------+              +----+         +--------+
 pub mod pc_directory;
 pub mod person;
-pub mod pc;
\ No newline at end of file
+pub mod pc;
+pub mod cpu;
+pub mod bytes;
+pub mod os;
+pub mod rollout;
+pub mod scheduler;
+pub mod compliance;
+pub mod compatibility;
+pub mod probe;
+pub mod profiles;
+pub mod query;
+pub mod fuzzy;
+pub mod report;
+pub mod chargeback;
+pub mod money;
+pub mod engagement;
+pub mod org;
+pub mod location;
+pub mod access;
+pub mod helpdesk;
+pub mod software;
+pub mod licenses;
+pub mod server;
\ No newline at end of file
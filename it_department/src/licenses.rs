@@ -0,0 +1,532 @@
+//! License pools for commercial software and who holds their seats.
+//!
+//! A [LicensePool] has a number of seats for a product, i.e. a [Package]
+//! name, and may expire. A seat is held either by a PC or by an owner, whose
+//! seat covers all of their PCs. Seats are only handed out while the pool has
+//! some left and has not expired, but pools can shrink when they are renewed.
+//! The [LicenseAudit] finds pools with more seats assigned than available,
+//! expired pools that are still in use, and PCs running a licensed product
+//! without holding a seat for it.
+//!
+//! Like the [crate::helpdesk::Helpdesk], the pools are plain data that can be
+//! saved to a file.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    pc_directory::{PcDirectory, PcDirectoryEntry, PcDirectoryError},
+    person::PersonId,
+    report::{Report, Table},
+    software::Package,
+};
+
+/// Who holds a seat of a pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Seat {
+    Pc(usize),
+    /// A seat for a person, covering all of their PCs.
+    Owner(PersonId),
+}
+
+impl fmt::Display for Seat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pc(id) => write!(f, "PC {id}"),
+            Self::Owner(id) => write!(f, "owner {id}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LicensePool {
+    /// The name of the package the pool licenses.
+    pub product: String,
+    pub vendor: String,
+    pub seats: u32,
+    /// The last day the licenses are valid.
+    pub expires: Option<NaiveDate>,
+    assigned: BTreeSet<Seat>,
+}
+
+impl LicensePool {
+    pub fn new<S: ToString, T: ToString>(product: S, vendor: T, seats: u32) -> Self {
+        Self {
+            product: product.to_string(),
+            vendor: vendor.to_string(),
+            seats,
+            expires: None,
+            assigned: BTreeSet::new(),
+        }
+    }
+
+    pub fn with_expiry(mut self, expires: NaiveDate) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// The seats handed out, PCs first.
+    pub fn assigned(&self) -> &BTreeSet<Seat> {
+        &self.assigned
+    }
+
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        self.expires.is_some_and(|expires| expires < today)
+    }
+
+    /// Whether the pool licenses `package`, i.e. whether it is the pool's
+    /// product by the pool's vendor.
+    pub fn licenses(&self, package: &Package) -> bool {
+        package.name.eq_ignore_ascii_case(&self.product)
+            && package.vendor.eq_ignore_ascii_case(&self.vendor)
+    }
+
+    /// Whether one of the seats covers the PC.
+    pub fn covers(&self, pc: &PcDirectoryEntry) -> bool {
+        self.assigned.contains(&Seat::Pc(pc.id()))
+            || pc
+                .owner_id()
+                .is_some_and(|owner| self.assigned.contains(&Seat::Owner(owner)))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LicenseError {
+    #[error("There is no license pool {name:?}.")]
+    UnknownPool { name: String },
+    #[error("A license pool {name:?} already exists.")]
+    DuplicatePool { name: String },
+    #[error("All {seats} seats of {pool:?} are assigned.")]
+    PoolFull { pool: String, seats: u32 },
+    #[error("The licenses of {pool:?} expired on {expired}.")]
+    Expired { pool: String, expired: NaiveDate },
+    #[error("{seat} holds no seat of {pool:?}.")]
+    NotAssigned { pool: String, seat: Seat },
+    #[error("Could not assign the seat: {0}")]
+    Directory(#[from] PcDirectoryError),
+    #[error("Could not access the license file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The license file is malformed: {0}")]
+    Format(#[from] serde_json::Error),
+}
+
+/// All license pools by name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Licenses {
+    pools: BTreeMap<String, LicensePool>,
+}
+
+impl Licenses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_pool<S: ToString>(
+        &mut self,
+        name: S,
+        pool: LicensePool,
+    ) -> Result<(), LicenseError> {
+        let name = name.to_string();
+        if self.pools.contains_key(&name) {
+            return Err(LicenseError::DuplicatePool { name });
+        }
+        self.pools.insert(name, pool);
+        Ok(())
+    }
+
+    pub fn pool(&self, name: &str) -> Option<&LicensePool> {
+        self.pools.get(name)
+    }
+
+    pub fn pools(&self) -> impl Iterator<Item = (&str, &LicensePool)> {
+        self.pools.iter().map(|(name, pool)| (name.as_str(), pool))
+    }
+
+    /// Change the number of seats and the expiry of a pool, e.g. when it is
+    /// renewed. Seats that are assigned already are kept, even if there are
+    /// fewer seats now.
+    pub fn renew(
+        &mut self,
+        name: &str,
+        seats: u32,
+        expires: Option<NaiveDate>,
+    ) -> Result<(), LicenseError> {
+        let pool = self.pool_mut(name)?;
+        pool.seats = seats;
+        pool.expires = expires;
+        Ok(())
+    }
+
+    /// Give a seat of the pool to a PC or owner of the directory. Holding a
+    /// seat already is fine.
+    pub fn assign(
+        &mut self,
+        dir: &PcDirectory,
+        name: &str,
+        seat: Seat,
+        today: NaiveDate,
+    ) -> Result<(), LicenseError> {
+        match seat {
+            Seat::Pc(id) if dir.get_pc(id).is_none() => {
+                return Err(PcDirectoryError::PcNotFound { id }.into())
+            }
+            Seat::Owner(id) if dir.person(id).is_none() => {
+                return Err(PcDirectoryError::PersonNotFound { id }.into())
+            }
+            _ => (),
+        }
+        let pool = self.pool_mut(name)?;
+        if let Some(expired) = pool.expires.filter(|_| pool.is_expired(today)) {
+            return Err(LicenseError::Expired {
+                pool: name.into(),
+                expired,
+            });
+        }
+        if pool.assigned.contains(&seat) {
+            return Ok(());
+        }
+        if pool.assigned.len() >= pool.seats as usize {
+            return Err(LicenseError::PoolFull {
+                pool: name.into(),
+                seats: pool.seats,
+            });
+        }
+        pool.assigned.insert(seat);
+        Ok(())
+    }
+
+    /// Take a seat of the pool back.
+    pub fn release(&mut self, name: &str, seat: Seat) -> Result<(), LicenseError> {
+        let pool = self.pool_mut(name)?;
+        match pool.assigned.remove(&seat) {
+            true => Ok(()),
+            false => Err(LicenseError::NotAssigned {
+                pool: name.into(),
+                seat,
+            }),
+        }
+    }
+
+    /// Write the pools to a JSON file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), LicenseError> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Read pools previously written by [Licenses::save].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LicenseError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    fn pool_mut(&mut self, name: &str) -> Result<&mut LicensePool, LicenseError> {
+        self.pools
+            .get_mut(name)
+            .ok_or_else(|| LicenseError::UnknownPool { name: name.into() })
+    }
+}
+
+/// The usage of the license pools and everything that is wrong with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LicenseAudit {
+    pub date: NaiveDate,
+    pub pools: Vec<PoolUsage>,
+    pub issues: Vec<LicenseIssue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PoolUsage {
+    pub name: String,
+    pub product: String,
+    pub seats: u32,
+    pub assigned: usize,
+    /// PCs running the product, whether they hold a seat or not.
+    pub installed: usize,
+    pub expires: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "issue", rename_all = "snake_case")]
+pub enum LicenseIssue {
+    /// More seats are assigned than the pool has.
+    OverAllocated {
+        pool: String,
+        seats: u32,
+        assigned: usize,
+    },
+    /// Seats of an expired pool are still assigned.
+    Expired {
+        pool: String,
+        expired: NaiveDate,
+        assigned: usize,
+    },
+    /// A PC runs a licensed product without a seat of a valid pool for it.
+    Unlicensed { pc: usize, package: Package },
+}
+
+impl fmt::Display for LicenseIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OverAllocated {
+                pool,
+                seats,
+                assigned,
+            } => write!(
+                f,
+                "{pool}: {assigned} seats assigned, but only {seats} available"
+            ),
+            Self::Expired {
+                pool,
+                expired,
+                assigned,
+            } => write!(
+                f,
+                "{pool}: expired on {expired}, but {assigned} seats are assigned"
+            ),
+            Self::Unlicensed { pc, package } => write!(f, "PC {pc}: {package} is not licensed"),
+        }
+    }
+}
+
+impl LicenseAudit {
+    pub fn new(dir: &PcDirectory, licenses: &Licenses, today: NaiveDate) -> Self {
+        let mut pools = vec![];
+        let mut issues = vec![];
+        for (name, pool) in licenses.pools() {
+            let assigned = pool.assigned.len();
+            pools.push(PoolUsage {
+                name: name.into(),
+                product: pool.product.clone(),
+                seats: pool.seats,
+                assigned,
+                installed: dir
+                    .iter_pcs()
+                    .filter(|pc| pc.software().iter().any(|p| pool.licenses(p)))
+                    .count(),
+                expires: pool.expires,
+            });
+            if assigned > pool.seats as usize {
+                issues.push(LicenseIssue::OverAllocated {
+                    pool: name.into(),
+                    seats: pool.seats,
+                    assigned,
+                });
+            }
+            if let Some(expired) = pool.expires.filter(|_| pool.is_expired(today)) {
+                if assigned > 0 {
+                    issues.push(LicenseIssue::Expired {
+                        pool: name.into(),
+                        expired,
+                        assigned,
+                    });
+                }
+            }
+        }
+
+        // Software without any pool is free to use.
+        for pc in dir.iter_pcs() {
+            for package in pc.software() {
+                let pools: Vec<_> = licenses
+                    .pools()
+                    .map(|(_, pool)| pool)
+                    .filter(|pool| pool.licenses(&package))
+                    .collect();
+                let licensed = pools.iter().any(|p| !p.is_expired(today) && p.covers(pc));
+                if !pools.is_empty() && !licensed {
+                    issues.push(LicenseIssue::Unlicensed {
+                        pc: pc.id(),
+                        package,
+                    });
+                }
+            }
+        }
+        Self {
+            date: today,
+            pools,
+            issues,
+        }
+    }
+}
+
+impl Report for LicenseAudit {
+    fn summary(&self) -> String {
+        format!(
+            "{} license pools as of {}, {} issues",
+            self.pools.len(),
+            self.date,
+            self.issues.len()
+        )
+    }
+
+    fn tables(&self) -> Vec<Table> {
+        vec![
+            Table {
+                name: "pools",
+                title: "License pools",
+                columns: vec![
+                    "pool",
+                    "product",
+                    "seats",
+                    "assigned",
+                    "installed",
+                    "expires",
+                ],
+                rows: self
+                    .pools
+                    .iter()
+                    .map(|p| {
+                        vec![
+                            p.name.clone(),
+                            p.product.clone(),
+                            p.seats.to_string(),
+                            p.assigned.to_string(),
+                            p.installed.to_string(),
+                            p.expires.map_or("-".into(), |d| d.to_string()),
+                        ]
+                    })
+                    .collect(),
+            },
+            Table {
+                name: "issues",
+                title: "Issues",
+                columns: vec!["issue"],
+                rows: self.issues.iter().map(|i| vec![i.to_string()]).collect(),
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pc_directory::get_directory, report::ReportFormat};
+
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn office() -> Licenses {
+        let mut licenses = Licenses::new();
+        let pool = LicensePool::new("Office", "Microsoft", 2).with_expiry(date(6, 30));
+        licenses.add_pool("office-2024", pool).unwrap();
+        licenses
+    }
+
+    #[test]
+    fn test_assign() {
+        let dir = get_directory();
+        let mut licenses = office();
+        licenses
+            .assign(&dir, "office-2024", Seat::Pc(0), date(3, 1))
+            .unwrap();
+        licenses
+            .assign(&dir, "office-2024", Seat::Owner(PersonId(3)), date(3, 1))
+            .unwrap();
+        // Assigning a seat twice does not use up another one.
+        licenses
+            .assign(&dir, "office-2024", Seat::Pc(0), date(3, 1))
+            .unwrap();
+        assert!(matches!(
+            licenses.assign(&dir, "office-2024", Seat::Pc(4), date(3, 1)),
+            Err(LicenseError::PoolFull { seats: 2, .. })
+        ));
+        assert!(matches!(
+            licenses.assign(&dir, "office-2024", Seat::Pc(9), date(3, 1)),
+            Err(LicenseError::Directory(PcDirectoryError::PcNotFound {
+                id: 9
+            }))
+        ));
+
+        licenses.release("office-2024", Seat::Pc(0)).unwrap();
+        assert!(matches!(
+            licenses.release("office-2024", Seat::Pc(0)),
+            Err(LicenseError::NotAssigned { .. })
+        ));
+        assert!(matches!(
+            licenses.assign(&dir, "office-2024", Seat::Pc(4), date(7, 1)),
+            Err(LicenseError::Expired { .. })
+        ));
+        assert!(matches!(
+            licenses.assign(&dir, "visio", Seat::Pc(4), date(3, 1)),
+            Err(LicenseError::UnknownPool { .. })
+        ));
+    }
+
+    #[test]
+    fn test_licenses_product_of_vendor() {
+        let pool = LicensePool::new("Office", "Microsoft", 2);
+        let package = |name, vendor| Package::new(name, "2021".parse().unwrap(), vendor);
+        assert!(pool.licenses(&package("office", "microsoft")));
+        assert!(!pool.licenses(&package("Office", "Libre")));
+        assert!(!pool.licenses(&package("Visio", "Microsoft")));
+    }
+
+    #[test]
+    fn test_audit() {
+        let dir = get_directory();
+        let mut licenses = office();
+        for seat in [Seat::Pc(0), Seat::Owner(PersonId(3))] {
+            licenses
+                .assign(&dir, "office-2024", seat, date(3, 1))
+                .unwrap();
+        }
+        let audit = LicenseAudit::new(&dir, &licenses, date(3, 1));
+        // Lex runs Office without a seat.
+        assert_eq!(audit.issues.len(), 1);
+        assert!(matches!(
+            audit.issues[0],
+            LicenseIssue::Unlicensed { pc: 4, .. }
+        ));
+        assert_eq!(audit.pools[0].installed, 3);
+
+        licenses.renew("office-2024", 1, Some(date(6, 30))).unwrap();
+        let audit = LicenseAudit::new(&dir, &licenses, date(7, 1));
+        assert_eq!(
+            audit.issues[..2],
+            [
+                LicenseIssue::OverAllocated {
+                    pool: "office-2024".into(),
+                    seats: 1,
+                    assigned: 2
+                },
+                LicenseIssue::Expired {
+                    pool: "office-2024".into(),
+                    expired: date(6, 30),
+                    assigned: 2
+                },
+            ]
+        );
+        // Once the pool expired, nobody is licensed anymore.
+        assert_eq!(audit.issues.len(), 5);
+        assert!(audit
+            .render(ReportFormat::Csv)
+            .contains("pools,office-2024,Office,1,2,3,2024-06-30\n"));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::path::PathBuf::from(std::env::var("TMPDIR").unwrap()).join("licenses.json");
+        let dir = get_directory();
+        let mut licenses = office();
+        licenses
+            .assign(&dir, "office-2024", Seat::Owner(PersonId(0)), date(3, 1))
+            .unwrap();
+        licenses.save(&path).unwrap();
+
+        let licenses = Licenses::load(&path).unwrap();
+        let pool = licenses.pool("office-2024").unwrap();
+        assert_eq!(pool.assigned(), &BTreeSet::from([Seat::Owner(PersonId(0))]));
+        assert!(pool.covers(dir.get_pc(0).unwrap()));
+    }
+}
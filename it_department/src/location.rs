@@ -0,0 +1,364 @@
+//! Where PCs are: sites, buildings, floors, rooms and desks.
+//!
+//! Locations are written from the outside in, separated by slashes, e.g.
+//! `Zurich/HQ/3/3.14/D2` for desk D2 in room 3.14 on the third floor. A
+//! location can stop at any level and then [contains](Location::contains)
+//! everything below it, so `Zurich/HQ` selects all PCs in that building.
+//!
+//! PCs are moved with [PcDirectory::move_pc], which keeps a history of
+//! [Move]s. A [LocationReport] lists the PCs in the order technicians walk
+//! past them.
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    pc_directory::{OperationalState, PcDirectory},
+    person::EmailAddr,
+    report::{Report, Table},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Location {
+    site: String,
+    building: Option<String>,
+    // Floors are numbers, so that they are ordered from the basement up.
+    floor: Option<i16>,
+    room: Option<String>,
+    desk: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LocationError {
+    #[error("A location needs at least a site.")]
+    Empty,
+    #[error("The {level} of the location is empty.")]
+    EmptyLevel { level: &'static str },
+    #[error("Invalid floor {floor:?}; floors are numbers, e.g. -1 for the basement.")]
+    InvalidFloor { floor: String },
+    #[error("A location has at most five levels: site/building/floor/room/desk.")]
+    TooManyLevels,
+}
+
+impl Location {
+    pub fn site(&self) -> &str {
+        &self.site
+    }
+
+    pub fn building(&self) -> Option<&str> {
+        self.building.as_deref()
+    }
+
+    pub fn floor(&self) -> Option<i16> {
+        self.floor
+    }
+
+    pub fn room(&self) -> Option<&str> {
+        self.room.as_deref()
+    }
+
+    pub fn desk(&self) -> Option<&str> {
+        self.desk.as_deref()
+    }
+
+    /// Whether `other` is this location or lies within it.
+    pub fn contains(&self, other: &Location) -> bool {
+        fn within<T: PartialEq>(outer: &Option<T>, inner: &Option<T>) -> bool {
+            outer.is_none() || outer == inner
+        }
+        self.site == other.site
+            && within(&self.building, &other.building)
+            && within(&self.floor, &other.floor)
+            && within(&self.room, &other.room)
+            && within(&self.desk, &other.desk)
+    }
+}
+
+impl FromStr for Location {
+    type Err = LocationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const LEVELS: [&str; 5] = ["site", "building", "floor", "room", "desk"];
+        if s.trim().is_empty() {
+            return Err(LocationError::Empty);
+        }
+        let parts: Vec<_> = s.split('/').map(str::trim).collect();
+        if parts.len() > LEVELS.len() {
+            return Err(LocationError::TooManyLevels);
+        }
+        if let Some(i) = parts.iter().position(|p| p.is_empty()) {
+            return Err(LocationError::EmptyLevel { level: LEVELS[i] });
+        }
+        let part = |i: usize| parts.get(i).map(|p| p.to_string());
+        let floor = match parts.get(2) {
+            Some(floor) => Some(floor.parse().map_err(|_| LocationError::InvalidFloor {
+                floor: floor.to_string(),
+            })?),
+            None => None,
+        };
+        Ok(Self {
+            site: parts[0].into(),
+            building: part(1),
+            floor,
+            room: part(3),
+            desk: part(4),
+        })
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.site)?;
+        let floor = self.floor.map(|floor| floor.to_string());
+        for level in [&self.building, &floor, &self.room, &self.desk] {
+            match level {
+                Some(level) => write!(f, "/{level}")?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Location {
+    type Error = LocationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Location> for String {
+    fn from(value: Location) -> Self {
+        value.to_string()
+    }
+}
+
+/// A PC that was moved, set up somewhere or taken away.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub pc: usize,
+    pub date: NaiveDate,
+    pub from: Option<Location>,
+    pub to: Option<Location>,
+}
+
+/// The PCs at a location, ordered by where they are.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LocationReport {
+    /// Where the report is for; all locations if none.
+    pub within: Option<Location>,
+    pub sites: Vec<SiteCount>,
+    pub pcs: Vec<LocatedPc>,
+    /// PCs whose location is unknown. Only listed for reports of all
+    /// locations.
+    pub unlocated: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SiteCount {
+    pub site: String,
+    pub pcs: usize,
+    /// PCs that are off or being maintained.
+    pub unavailable: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LocatedPc {
+    pub location: Location,
+    pub id: usize,
+    pub state: String,
+    pub owner: Option<EmailAddr>,
+}
+
+impl LocationReport {
+    pub fn new(dir: &PcDirectory, within: Option<&Location>) -> Self {
+        let mut pcs: Vec<_> = dir
+            .iter_pcs()
+            .filter_map(|pc| {
+                let location = pc.location()?;
+                if within.is_some_and(|w| !w.contains(location)) {
+                    return None;
+                }
+                Some(LocatedPc {
+                    location: location.clone(),
+                    id: pc.id(),
+                    state: match pc.operational_state() {
+                        OperationalState::On => "on",
+                        OperationalState::Off => "off",
+                        OperationalState::BeingMaintained { .. } => "maintenance",
+                    }
+                    .into(),
+                    owner: pc.owner.as_ref().map(|p| p.email.clone()),
+                })
+            })
+            .collect();
+        pcs.sort_by(|a, b| (&a.location, a.id).cmp(&(&b.location, b.id)));
+
+        let mut sites: BTreeMap<&str, SiteCount> = BTreeMap::new();
+        for pc in &pcs {
+            let site = sites
+                .entry(pc.location.site())
+                .or_insert_with(|| SiteCount {
+                    site: pc.location.site().into(),
+                    pcs: 0,
+                    unavailable: 0,
+                });
+            site.pcs += 1;
+            site.unavailable += usize::from(pc.state != "on");
+        }
+        let sites = sites.into_values().collect();
+
+        let unlocated = match within {
+            Some(_) => vec![],
+            None => dir
+                .iter_pcs()
+                .filter(|pc| pc.location().is_none())
+                .map(|pc| pc.id())
+                .collect(),
+        };
+        Self {
+            within: within.cloned(),
+            sites,
+            pcs,
+            unlocated,
+        }
+    }
+}
+
+impl Report for LocationReport {
+    fn summary(&self) -> String {
+        let place = match &self.within {
+            Some(location) => format!("at {location}"),
+            None => format!("at {} sites", self.sites.len()),
+        };
+        let mut summary = format!("{} PCs {place}", self.pcs.len());
+        if !self.unlocated.is_empty() {
+            summary += &format!(", {} PCs without location", self.unlocated.len());
+        }
+        summary
+    }
+
+    fn tables(&self) -> Vec<Table> {
+        vec![
+            Table {
+                name: "sites",
+                title: "PCs per site",
+                columns: vec!["site", "PCs", "unavailable"],
+                rows: self
+                    .sites
+                    .iter()
+                    .map(|s| vec![s.site.clone(), s.pcs.to_string(), s.unavailable.to_string()])
+                    .collect(),
+            },
+            Table {
+                name: "pcs",
+                title: "PCs by location",
+                columns: vec!["location", "PC", "state", "owner"],
+                rows: self
+                    .pcs
+                    .iter()
+                    .map(|pc| {
+                        vec![
+                            pc.location.to_string(),
+                            pc.id.to_string(),
+                            pc.state.clone(),
+                            pc.owner.as_ref().map_or("-".into(), |e| e.as_ref().into()),
+                        ]
+                    })
+                    .collect(),
+            },
+            Table {
+                name: "unlocated",
+                title: "PCs without location",
+                columns: vec!["PC"],
+                rows: self
+                    .unlocated
+                    .iter()
+                    .map(|id| vec![id.to_string()])
+                    .collect(),
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pc_directory::get_directory, report::ReportFormat};
+
+    use super::*;
+
+    fn loc(s: &str) -> Location {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let desk = loc("Zurich / HQ / -1 / U.04 / D2");
+        assert_eq!(desk.to_string(), "Zurich/HQ/-1/U.04/D2");
+        assert_eq!(desk.floor(), Some(-1));
+        assert_eq!(desk.desk(), Some("D2"));
+        assert_eq!(loc("Bern").building(), None);
+
+        let parse = |s: &str| s.parse::<Location>();
+        assert_eq!(parse(""), Err(LocationError::Empty));
+        assert_eq!(
+            parse("Zurich//3"),
+            Err(LocationError::EmptyLevel { level: "building" })
+        );
+        assert!(matches!(
+            parse("Zurich/HQ/third"),
+            Err(LocationError::InvalidFloor { .. })
+        ));
+        assert_eq!(parse("a/b/1/c/d/e"), Err(LocationError::TooManyLevels));
+    }
+
+    #[test]
+    fn test_contains_and_order() {
+        let building = loc("Zurich/HQ");
+        assert!(building.contains(&loc("Zurich/HQ/3/3.14")));
+        assert!(building.contains(&building));
+        assert!(!building.contains(&loc("Zurich/Annex/3")));
+        assert!(!loc("Zurich/HQ/3").contains(&building));
+        // Floors are ordered as numbers.
+        assert!(loc("Zurich/HQ/2") < loc("Zurich/HQ/10"));
+    }
+
+    #[test]
+    fn test_report() {
+        let mut dir = get_directory();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        for (pc, to) in [
+            (0, "Zurich/HQ/3/3.14"),
+            (1, "Zurich/HQ/1"),
+            (2, "Bern/Main"),
+        ] {
+            dir.move_pc(pc, Some(loc(to)), date).unwrap();
+        }
+        dir.power_off(0).unwrap();
+
+        let report = LocationReport::new(&dir, None);
+        assert_eq!(
+            report.pcs.iter().map(|pc| pc.id).collect::<Vec<_>>(),
+            vec![2, 1, 0]
+        );
+        assert_eq!(report.unlocated, vec![3, 4, 5]);
+        assert_eq!(
+            report.sites[1],
+            SiteCount {
+                site: "Zurich".into(),
+                pcs: 2,
+                unavailable: 1
+            }
+        );
+
+        let report = LocationReport::new(&dir, Some(&loc("Zurich/HQ/3")));
+        assert_eq!(report.summary(), "1 PCs at Zurich/HQ/3");
+        assert!(report
+            .render(ReportFormat::Csv)
+            .contains("pcs,Zurich/HQ/3/3.14,0,off,maria@dingong.com\n"));
+    }
+}
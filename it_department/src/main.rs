@@ -1,13 +1,50 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Datelike, NaiveDate, SubsecRound};
 use clap::{Parser, Subcommand};
-use it_company::{pc_directory::get_directory, person::{EmailAddr, EmailParseError}};
+use it_company::{
+    access::{Caller, RoleConfig},
+    chargeback::Chargeback,
+    compatibility::CompatibilityMatrix,
+    compliance::CompliancePolicy,
+    engagement::{EngagementSweep, ExpiryAction, FindingStatus},
+    location::{Location, LocationReport},
+    org::OrgUnit,
+    fuzzy::{self, OwnerMatch},
+    helpdesk::{Helpdesk, TicketId},
+    licenses::{LicenseAudit, LicensePool, Licenses, Seat},
+    os::OperatingSystem,
+    pc::{CpuFlag, CpuFlags, MacAddr, NumBytes, PcBuilder},
+    pc_directory::{get_directory, PcDirectory, PcDirectoryEntry},
+    person::{EmailAddr, EmailParseError, Person, PersonId},
+    probe,
+    profiles::Profiles,
+    query::{Field, Query},
+    report::{FleetReport, Report, ReportFormat},
+    software::{AdvisoryReport, Advisories, Package, Version},
+};
 
 #[derive(Parser)]
 #[command(about, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
-    command: Command
+    command: Command,
+
+    /// The hardware and operating system profiles for new PCs (defaults to
+    /// the built-in ones).
+    #[arg(long, global = true)]
+    profiles: Option<PathBuf>,
+
+    /// Who has which roles, for commands acting on behalf of someone.
+    #[arg(long, global = true, default_value = "roles.toml")]
+    roles: PathBuf,
 }
 
+// The command is parsed once, so its size does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Command {
     SendEmail {
@@ -15,33 +52,926 @@ enum Command {
         to: Option<EmailAddr>,
     },
     Search {
+        /// Only list PCs whose owner has a similar first name. Typos, prefixes
+        /// and names that sound alike are found as well; the best matches are
+        /// listed first.
         #[arg(long)]
         first: Option<String>,
 
+        /// Only list PCs whose owner has a similar last name, like `--first`.
         #[arg(long)]
         last: Option<String>,
-    }
+
+        /// How similar the names of owners must be, from 0 to 1 (equal).
+        #[arg(long, default_value_t = 0.7)]
+        min_score: f64,
+
+        /// Only list PCs running this release of an operating system, e.g.
+        /// "Windows 11" or "Linux 6.22". Editions, distributions and
+        /// architectures are ignored.
+        #[arg(long)]
+        os: Option<OperatingSystem>,
+
+        /// Only list PCs whose CPU supports this feature, e.g. "avx2". Can be
+        /// given several times.
+        #[arg(long = "cpu-flag")]
+        cpu_flags: Vec<CpuFlag>,
+
+        /// Only list PCs whose CPU model contains this text (ignoring case).
+        #[arg(long)]
+        cpu_model: Option<String>,
+
+        #[arg(long)]
+        serial: Option<String>,
+
+        /// Only list PCs with a network interface with this MAC address.
+        #[arg(long)]
+        mac: Option<MacAddr>,
+
+        /// Only list PCs with at least this much RAM, e.g. "32GiB".
+        #[arg(long)]
+        min_ram: Option<NumBytes>,
+
+        /// Only list PCs with at least this much storage, e.g. "2 TB".
+        #[arg(long)]
+        min_storage: Option<NumBytes>,
+
+        /// Only list PCs at this location or within it, e.g. "Zurich/HQ/3".
+        #[arg(long)]
+        location: Option<Location>,
+
+        /// Only list PCs whose owner is a member of this team.
+        #[arg(long)]
+        team: Option<String>,
+
+        /// Only list PCs whose owner is in a team of this department.
+        #[arg(long)]
+        department: Option<String>,
+    },
+    /// List the PCs by location, e.g. to plan the rounds of a technician.
+    Locations {
+        /// Only list PCs at this location or within it, e.g. "Zurich/HQ".
+        #[arg(long)]
+        within: Option<Location>,
+
+        /// The directory file to report on (defaults to the built-in
+        /// directory).
+        #[arg(long)]
+        directory: Option<PathBuf>,
+
+        /// "text", "csv" or "json".
+        #[arg(long, default_value = "text")]
+        format: ReportFormat,
+    },
+    /// Move a PC in a directory file to another location.
+    MovePc {
+        #[arg(long)]
+        directory: PathBuf,
+
+        pc: usize,
+
+        /// Where the PC is moved, e.g. "Zurich/HQ/3/3.14/D2". Without it, the
+        /// PC is taken away from its location.
+        #[arg(long)]
+        to: Option<Location>,
+
+        /// The day of the move (defaults to today).
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+    /// Print the mailbox of a PC, if you may read it.
+    Mailbox {
+        pc: usize,
+
+        /// Your email address.
+        #[arg(long = "as", value_parser = parse_email)]
+        caller: EmailAddr,
+    },
+    /// Give a PC in a directory file to someone else. Only admins may do so.
+    TransferPc {
+        #[arg(long)]
+        directory: PathBuf,
+
+        pc: usize,
+
+        /// The email address of the new owner, who must own or have owned
+        /// a PC in the directory. Without it, the PC has no owner anymore.
+        #[arg(long, value_parser = parse_email)]
+        to: Option<EmailAddr>,
+
+        /// Your email address.
+        #[arg(long = "as", value_parser = parse_email)]
+        caller: EmailAddr,
+    },
+    /// List the software installed on the PCs.
+    Software {
+        /// Only list the software of this PC.
+        pc: Option<usize>,
+
+        /// The directory file to list (defaults to the built-in directory).
+        #[arg(long)]
+        directory: Option<PathBuf>,
+    },
+    /// Install a package on a PC in a directory file, replacing any other
+    /// version of it. You must be allowed to maintain the PC.
+    Install {
+        #[arg(long)]
+        directory: PathBuf,
+
+        pc: usize,
+
+        name: String,
+
+        version: Version,
+
+        #[arg(long)]
+        vendor: String,
+
+        /// Your email address.
+        #[arg(long = "as", value_parser = parse_email)]
+        caller: EmailAddr,
+    },
+    /// Remove a package from a PC in a directory file. You must be allowed to
+    /// maintain the PC.
+    Uninstall {
+        #[arg(long)]
+        directory: PathBuf,
+
+        pc: usize,
+
+        name: String,
+
+        /// Your email address.
+        #[arg(long = "as", value_parser = parse_email)]
+        caller: EmailAddr,
+    },
+    /// List the PCs running software with known-bad versions.
+    Advisories {
+        /// The advisories to check against.
+        #[arg(long, default_value = "advisories.toml")]
+        advisories: PathBuf,
+
+        /// The directory file to check (defaults to the built-in directory).
+        #[arg(long)]
+        directory: Option<PathBuf>,
+
+        /// "text", "csv" or "json".
+        #[arg(long, default_value = "text")]
+        format: ReportFormat,
+    },
+    /// Manage the license pools and their seats.
+    License {
+        /// The file the pools are kept in. It is created if it does not exist.
+        #[arg(long, default_value = "licenses.json")]
+        licenses: PathBuf,
+
+        /// The directory file the seats refer to (defaults to the built-in
+        /// directory).
+        #[arg(long)]
+        directory: Option<PathBuf>,
+
+        #[command(subcommand)]
+        action: LicenseAction,
+    },
+    /// Work with the helpdesk tickets.
+    Ticket {
+        /// The file the tickets are kept in. It is created if it does not
+        /// exist.
+        #[arg(long, default_value = "tickets.json")]
+        tickets: PathBuf,
+
+        #[command(subcommand)]
+        action: TicketAction,
+    },
+    /// Send a message to everyone in a team or department.
+    Broadcast {
+        message: String,
+
+        #[arg(long, conflicts_with = "department", required_unless_present = "department")]
+        team: Option<String>,
+
+        #[arg(long)]
+        department: Option<String>,
+
+        /// Your email address.
+        #[arg(long = "as", value_parser = parse_email)]
+        caller: EmailAddr,
+    },
+    /// Print the hardware inventory of all PCs.
+    Inventory,
+    /// Summarize the fleet: operating systems, RAM, CPU features, owners and
+    /// unavailable PCs.
+    Report {
+        /// "text", "csv" or "json".
+        #[arg(long, default_value = "text")]
+        format: ReportFormat,
+    },
+    /// Allocate the hardware costs of a year to the owners of the PCs.
+    Chargeback {
+        /// The year to allocate the costs of (defaults to the current year).
+        #[arg(long)]
+        year: Option<i32>,
+
+        /// "text", "csv" or "json".
+        #[arg(long, default_value = "text")]
+        format: ReportFormat,
+    },
+    /// Remind contractors and interns whose engagement ends soon and handle
+    /// the PCs of those whose engagement has ended.
+    SweepEngagements {
+        /// How many days before the end of an engagement the owner is reminded.
+        #[arg(long, default_value_t = 14)]
+        notice_days: u32,
+
+        /// What happens to the PCs of ended engagements: "flag", "power-off"
+        /// or "reclaim".
+        #[arg(long, default_value = "flag")]
+        action: ExpiryAction,
+
+        /// The day to run the sweep for (defaults to today).
+        #[arg(long)]
+        date: Option<NaiveDate>,
+
+        /// The directory file to sweep and save again (defaults to the
+        /// built-in directory, which is not saved).
+        #[arg(long)]
+        directory: Option<PathBuf>,
+
+        /// Your email address.
+        #[arg(long = "as", value_parser = parse_email)]
+        caller: EmailAddr,
+    },
+    /// List all PCs whose operating system is not supported by the policy.
+    Compliance {
+        /// The lifecycle policy to check against.
+        #[arg(long, default_value = "compliance.toml")]
+        policy: PathBuf,
+
+        /// The day to evaluate the policy for (defaults to today).
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+    /// List all PCs whose hardware cannot run the given operating system.
+    UpgradeCheck {
+        /// The operating system to upgrade to, e.g. "Windows 11".
+        #[arg(long)]
+        to: OperatingSystem,
+    },
+    /// Add the PC this command runs on to a directory file.
+    RegisterSelf {
+        /// The directory to add the PC to. It is created if it does not exist.
+        #[arg(long)]
+        directory: PathBuf,
+
+        /// The email address of the owner, who must already own another PC in
+        /// the directory.
+        #[arg(long, value_parser = parse_email)]
+        owner: Option<EmailAddr>,
+
+        /// Your email address.
+        #[arg(long = "as", value_parser = parse_email)]
+        caller: EmailAddr,
+    },
+    /// Add a PC to a directory file.
+    AddPc {
+        /// The directory to add the PC to. It is created if it does not exist.
+        #[arg(long)]
+        directory: PathBuf,
+
+        /// The profile of the PC. Defaults to the profile for the owner's
+        /// affiliation.
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// The email address of the owner, who must already own another PC in
+        /// the directory.
+        #[arg(long, value_parser = parse_email)]
+        owner: Option<EmailAddr>,
+
+        /// Your email address.
+        #[arg(long = "as", value_parser = parse_email)]
+        caller: EmailAddr,
+    },
+    /// Change the name or email address of an owner in a directory file. Mail
+    /// to the old address is still delivered.
+    UpdatePerson {
+        /// The directory the owner is in.
+        #[arg(long)]
+        directory: PathBuf,
+
+        /// The current or a previous email address of the owner.
+        #[arg(value_parser = parse_email)]
+        email: EmailAddr,
+
+        #[arg(long)]
+        first: Option<String>,
+
+        #[arg(long)]
+        last: Option<String>,
+
+        /// The new email address.
+        #[arg(long, value_parser = parse_email)]
+        new_email: Option<EmailAddr>,
+    },
+    /// List the profiles for new PCs.
+    Profiles,
+    /// List the PCs matching a query, e.g.
+    /// 'os < "Windows 11" and ram >= 32GiB and state = on'.
+    Query {
+        query: String,
+
+        /// The field to sort the PCs by.
+        #[arg(long, value_parser = parse_field)]
+        sort: Option<Field>,
+
+        /// Sort in descending order.
+        #[arg(long, requires = "sort")]
+        desc: bool,
+
+        /// The fields to print, separated by commas, e.g. "id,owner.email,os".
+        #[arg(long, value_delimiter = ',', value_parser = parse_field)]
+        fields: Vec<Field>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TicketAction {
+    /// Open a ticket about a PC.
+    Open {
+        pc: usize,
+
+        title: String,
+
+        /// Your email address.
+        #[arg(long = "as", value_parser = parse_email)]
+        caller: EmailAddr,
+    },
+    /// List the unresolved tickets.
+    List {
+        /// Only list the tickets about this PC.
+        #[arg(long)]
+        pc: Option<usize>,
+
+        /// Only list the tickets assigned to this technician.
+        #[arg(long, value_parser = parse_email)]
+        assignee: Option<EmailAddr>,
+
+        /// List resolved tickets as well.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Show a ticket with its comments.
+    Show { id: TicketId },
+    /// Assign a ticket to a technician.
+    Assign {
+        id: TicketId,
+
+        /// The email address of the technician, who must be allowed to
+        /// maintain the PC.
+        #[arg(long, value_parser = parse_email)]
+        to: EmailAddr,
+    },
+    Comment {
+        id: TicketId,
+
+        text: String,
+
+        /// Your email address.
+        #[arg(long = "as", value_parser = parse_email)]
+        caller: EmailAddr,
+    },
+    /// Start or resume work on an assigned ticket.
+    Start { id: TicketId },
+    /// Put a ticket on hold until something happens.
+    Wait {
+        id: TicketId,
+
+        /// What the ticket waits on, e.g. "a new power supply".
+        #[arg(long)]
+        on: String,
+    },
+    Resolve {
+        id: TicketId,
+
+        /// How the problem was solved.
+        resolution: String,
+    },
+    Reopen { id: TicketId },
+}
+
+#[derive(Subcommand)]
+enum LicenseAction {
+    /// Add a pool of licenses for a product.
+    AddPool {
+        name: String,
+
+        /// The name of the licensed package.
+        #[arg(long)]
+        product: String,
+
+        #[arg(long)]
+        vendor: String,
+
+        #[arg(long)]
+        seats: u32,
+
+        /// The last day the licenses are valid.
+        #[arg(long)]
+        expires: Option<NaiveDate>,
+    },
+    /// Change the seats and expiry of a pool.
+    Renew {
+        name: String,
+
+        #[arg(long)]
+        seats: u32,
+
+        #[arg(long)]
+        expires: Option<NaiveDate>,
+    },
+    /// Give a seat of a pool to a PC or an owner.
+    Assign {
+        pool: String,
+
+        #[arg(long, conflicts_with = "owner", required_unless_present = "owner")]
+        pc: Option<usize>,
+
+        /// The email address of the owner, whose seat covers all their PCs.
+        #[arg(long, value_parser = parse_email)]
+        owner: Option<EmailAddr>,
+
+        /// The day of the assignment (defaults to today).
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+    /// Take a seat of a pool back.
+    Release {
+        pool: String,
+
+        #[arg(long, conflicts_with = "owner", required_unless_present = "owner")]
+        pc: Option<usize>,
+
+        #[arg(long, value_parser = parse_email)]
+        owner: Option<EmailAddr>,
+    },
+    /// List the pools with over-allocated seats, expired pools in use and
+    /// PCs running licensed software without a seat.
+    Audit {
+        /// The day to audit for (defaults to today).
+        #[arg(long)]
+        date: Option<NaiveDate>,
+
+        /// "text", "csv" or "json".
+        #[arg(long, default_value = "text")]
+        format: ReportFormat,
+    },
 }
 
 fn parse_email(s: &str) -> Result<EmailAddr, EmailParseError> {
     EmailAddr::try_from(s)
 }
 
+fn parse_field(s: &str) -> Result<Field, String> {
+    s.parse()
+        .map_err(|_| format!("unknown field; known fields are: {}", Field::ALL.map(|f| f.name()).join(", ")))
+}
+
+fn describe_owner(pc: &PcDirectoryEntry) -> String {
+    pc.owner
+        .as_deref()
+        .map(|p| format!("{} {} <{}>", p.first, p.last, p.email.as_ref()))
+        .unwrap_or_else(|| "no owner".into())
+}
+
+fn load_roles(path: &Path) -> RoleConfig {
+    RoleConfig::load(path).unwrap_or_else(|e| fail(format!("{}: {e}", path.display())))
+}
+
+/// The members of the team or department, if any is given.
+fn org_members(dir: &PcDirectory, team: Option<String>, department: Option<String>) -> Vec<BTreeSet<PersonId>> {
+    let units = team.map(OrgUnit::Team).into_iter().chain(department.map(OrgUnit::Department));
+    units.map(|unit| dir.org().members(&unit).unwrap_or_else(|e| fail(e))).collect()
+}
+
+/// The seat of either the PC or the owner with the given email address.
+fn seat(dir: &PcDirectory, pc: Option<usize>, owner: Option<EmailAddr>) -> Seat {
+    match (pc, owner) {
+        (Some(pc), _) => Seat::Pc(pc),
+        (None, Some(email)) => match dir.find_person(&email) {
+            Some((id, _)) => Seat::Owner(id),
+            None => fail(format!("{} does not own any PC.", email.as_ref())),
+        },
+        (None, None) => unreachable!("clap requires either"),
+    }
+}
+
+fn fail(e: impl std::fmt::Display) -> ! {
+    eprintln!("{e}");
+    std::process::exit(1);
+}
+
+/// Add `pc` to the directory stored at `path` on behalf of `caller` and save it
+/// again.
+fn add_to_directory(path: &Path, caller: &Caller, mut pc: PcBuilder, owner: Option<EmailAddr>, profiles: Profiles) {
+    let mut target = if path.exists() {
+        PcDirectory::load(path).unwrap_or_else(|e| fail(e))
+    } else {
+        PcDirectory::default()
+    };
+    target.set_profiles(profiles);
+    if let Some(email) = owner {
+        let person = target.find_person(&email).map(|(_, p)| Person::clone(p));
+        let Some(person) = person else {
+            fail(format!("{} does not own any PC yet.", email.as_ref()));
+        };
+        pc.owner = Some(person);
+    }
+    target.add_pc_as(caller, pc).unwrap_or_else(|e| fail(e));
+    target.save(path).unwrap_or_else(|e| fail(e));
+
+    let pc = target.iter_pcs().last().expect("just added");
+    println!(
+        "Registered PC {}: {}, {} of RAM, CPU features: {}; owner: {}",
+        pc.id(),
+        pc.os(),
+        pc.hardware.ram,
+        pc.hardware.flags,
+        describe_owner(pc)
+    );
+}
+
 fn main() {
     let cli = Cli::parse();
+    let mut dir = get_directory();
+    let profiles = match &cli.profiles {
+        Some(path) => Profiles::load(path).unwrap_or_else(|e| fail(format!("{}: {e}", path.display()))),
+        None => Profiles::default(),
+    };
 
     match cli.command {
         Command::SendEmail { to } => {
             println!("You want to send an email to {to:?}");
         },
-        Command::Search { first, last } => {
-            let (first, last) = (first.unwrap_or_default(), last.unwrap_or_default());
-            println!("You want to list all computers of {first} {last}");
+        Command::Search { first, last, min_score, os, cpu_flags, cpu_model, serial, mac, min_ram, min_storage, location, team, department } => {
+            let cpu_flags: CpuFlags = cpu_flags.into_iter().collect();
+            let units = org_members(&dir, team, department);
+            let cpu_model = cpu_model.map(|m| m.to_lowercase());
+            let matches = |pc: &PcDirectoryEntry| {
+                os.as_ref().map_or(true, |os| pc.os().cmp_release(os).is_some_and(|o| o.is_eq()))
+                    && pc.hardware.flags.is_superset(&cpu_flags)
+                    && cpu_model.as_ref().map_or(true, |m| pc.hardware.cpu.as_ref().is_some_and(|c| c.model.to_lowercase().contains(m)))
+                    && serial.as_ref().map_or(true, |s| pc.hardware.serial_number.as_ref() == Some(s))
+                    && mac.as_ref().map_or(true, |mac| pc.hardware.has_mac(mac))
+                    && min_ram.map_or(true, |min| pc.hardware.ram >= min)
+                    && min_storage.map_or(true, |min| pc.hardware.storage() >= min)
+                    && location.as_ref().map_or(true, |at| pc.location().is_some_and(|l| at.contains(l)))
+                    && units.iter().all(|members| pc.owner_id().is_some_and(|id| members.contains(&id)))
+            };
+            let by_name = first.is_some() || last.is_some();
+            let found = fuzzy::search_owners(&dir, first.as_deref(), last.as_deref(), min_score);
+            for OwnerMatch { pc, score } in found.into_iter().filter(|m| matches(m.pc)) {
+                print!("PC {}: {}; owner: {}", pc.id(), pc.os(), describe_owner(pc));
+                match by_name {
+                    true => println!(" (score {score:.2})"),
+                    false => println!(),
+                }
+            }
         },
-    }
-
-    let _dir = get_directory();
-
+        Command::Locations { within, directory, format } => {
+            if let Some(path) = &directory {
+                dir = PcDirectory::load(path).unwrap_or_else(|e| fail(e));
+            }
+            print!("{}", LocationReport::new(&dir, within.as_ref()).render(format));
+        },
+        Command::MovePc { directory, pc, to, date } => {
+            let mut target = PcDirectory::load(&directory).unwrap_or_else(|e| fail(e));
+            let date = date.unwrap_or_else(|| chrono::Local::now().date_naive());
+            let from = target.get_pc(pc).and_then(|pc| pc.location().cloned());
+            target.move_pc(pc, to.clone(), date).unwrap_or_else(|e| fail(e));
+            target.save(&directory).unwrap_or_else(|e| fail(e));
+            let describe = |l: Option<Location>| l.map_or("nowhere".into(), |l| l.to_string());
+            println!("Moved PC {pc} from {} to {}", describe(from), describe(to));
+        },
+        Command::Mailbox { pc, caller } => {
+            let caller = load_roles(&cli.roles).caller(caller);
+            for message in dir.read_mailbox_as(&caller, pc).unwrap_or_else(|e| fail(e)) {
+                println!("{message}");
+            }
+        },
+        Command::TransferPc { directory, pc, to, caller } => {
+            let caller = load_roles(&cli.roles).caller(caller);
+            let mut target = PcDirectory::load(&directory).unwrap_or_else(|e| fail(e));
+            let to = to.map(|email| match target.find_person(&email) {
+                Some((id, _)) => id,
+                None => fail(format!("{} does not own any PC.", email.as_ref())),
+            });
+            target.transfer_pc_as(&caller, pc, to).unwrap_or_else(|e| fail(e));
+            target.save(&directory).unwrap_or_else(|e| fail(e));
+            println!("PC {pc}: owner: {}", describe_owner(target.get_pc(pc).expect("just transferred")));
+        },
+        Command::Software { pc, directory } => {
+            if let Some(path) = &directory {
+                dir = PcDirectory::load(path).unwrap_or_else(|e| fail(e));
+            }
+            for entry in dir.iter_pcs().filter(|entry| pc.map_or(true, |pc| entry.id() == pc)) {
+                let software: Vec<_> = entry.software().iter().map(|p| p.to_string()).collect();
+                println!("PC {}: {}", entry.id(), if software.is_empty() { "none".into() } else { software.join(", ") });
+            }
+        },
+        Command::Install { directory, pc, name, version, vendor, caller } => {
+            let caller = load_roles(&cli.roles).caller(caller);
+            let target = PcDirectory::load(&directory).unwrap_or_else(|e| fail(e));
+            let package = Package::new(name, version, vendor);
+            let replaced = {
+                let handle = target.acquire_maintenance_lock_as(&caller, pc, format!("install {package}")).unwrap_or_else(|e| fail(e));
+                handle.install(package.clone())
+            };
+            target.save(&directory).unwrap_or_else(|e| fail(e));
+            match replaced {
+                Some(old) => println!("PC {pc}: replaced {old} with {package}"),
+                None => println!("PC {pc}: installed {package}"),
+            }
+        },
+        Command::Uninstall { directory, pc, name, caller } => {
+            let caller = load_roles(&cli.roles).caller(caller);
+            let target = PcDirectory::load(&directory).unwrap_or_else(|e| fail(e));
+            let removed = {
+                let handle = target.acquire_maintenance_lock_as(&caller, pc, format!("remove {name}")).unwrap_or_else(|e| fail(e));
+                handle.remove(&name).unwrap_or_else(|e| fail(e))
+            };
+            target.save(&directory).unwrap_or_else(|e| fail(e));
+            println!("PC {pc}: removed {removed}");
+        },
+        Command::Advisories { advisories, directory, format } => {
+            let advisories = Advisories::load(&advisories).unwrap_or_else(|e| fail(format!("{}: {e}", advisories.display())));
+            if let Some(path) = &directory {
+                dir = PcDirectory::load(path).unwrap_or_else(|e| fail(e));
+            }
+            print!("{}", AdvisoryReport::new(&dir, &advisories).render(format));
+        },
+        Command::License { licenses: path, directory, action } => {
+            let mut licenses = match path.exists() {
+                true => Licenses::load(&path).unwrap_or_else(|e| fail(e)),
+                false => Licenses::new(),
+            };
+            if let Some(path) = &directory {
+                dir = PcDirectory::load(path).unwrap_or_else(|e| fail(e));
+            }
+            let today = chrono::Local::now().date_naive();
+            match action {
+                LicenseAction::AddPool { name, product, vendor, seats, expires } => {
+                    let mut pool = LicensePool::new(product, vendor, seats);
+                    pool.expires = expires;
+                    licenses.add_pool(&name, pool).unwrap_or_else(|e| fail(e));
+                    println!("Added pool {name} with {seats} seats");
+                },
+                LicenseAction::Renew { name, seats, expires } => {
+                    licenses.renew(&name, seats, expires).unwrap_or_else(|e| fail(e));
+                    println!("Renewed pool {name}: {seats} seats");
+                },
+                LicenseAction::Assign { pool, pc, owner, date } => {
+                    let seat = seat(&dir, pc, owner);
+                    licenses.assign(&dir, &pool, seat, date.unwrap_or(today)).unwrap_or_else(|e| fail(e));
+                    println!("Assigned a seat of {pool} to {seat}");
+                },
+                LicenseAction::Release { pool, pc, owner } => {
+                    let seat = seat(&dir, pc, owner);
+                    licenses.release(&pool, seat).unwrap_or_else(|e| fail(e));
+                    println!("Released the seat of {seat} in {pool}");
+                },
+                LicenseAction::Audit { date, format } => {
+                    print!("{}", LicenseAudit::new(&dir, &licenses, date.unwrap_or(today)).render(format));
+                    return;
+                },
+            }
+            licenses.save(&path).unwrap_or_else(|e| fail(e));
+        },
+        Command::Ticket { tickets, action } => {
+            let mut helpdesk = match tickets.exists() {
+                true => Helpdesk::load(&tickets).unwrap_or_else(|e| fail(e)),
+                false => Helpdesk::new(),
+            };
+            let now = chrono::Local::now().naive_local().trunc_subsecs(0);
+            let changed = match action {
+                TicketAction::Open { pc, title, caller } => {
+                    Some(helpdesk.open(&dir, pc, caller, title, now).unwrap_or_else(|e| fail(e)))
+                },
+                TicketAction::List { pc, assignee, all } => {
+                    let listed = helpdesk.tickets().filter(|t| {
+                        (all || !t.status.is_resolved())
+                            && pc.map_or(true, |pc| t.pc == pc)
+                            && assignee.as_ref().map_or(true, |a| t.assignee.as_ref() == Some(a))
+                    });
+                    for t in listed {
+                        let assignee = t.assignee.as_ref().map_or("unassigned", |a| a.as_ref());
+                        println!("{}: PC {}, {}; {}; {assignee}", t.id, t.pc, t.title, t.status.name());
+                    }
+                    None
+                },
+                TicketAction::Show { id } => {
+                    let Some(t) = helpdesk.get(id) else {
+                        fail(format!("There is no ticket {id}."));
+                    };
+                    println!("{}: {}", t.id, t.title);
+                    println!("  PC:       {}", t.pc);
+                    println!("  owner:    {}", t.owner.and_then(|id| dir.person(id)).map_or("none".into(), |p| p.email.as_ref().to_string()));
+                    println!("  opened:   {} by {}", t.opened_at, t.opened_by.as_ref());
+                    println!("  assignee: {}", t.assignee.as_ref().map_or("none", |a| a.as_ref()));
+                    println!("  status:   {}", t.status);
+                    for c in &t.comments {
+                        println!("  {} {}: {}", c.at, c.author.as_ref(), c.text);
+                    }
+                    None
+                },
+                TicketAction::Assign { id, to } => {
+                    let technician = load_roles(&cli.roles).caller(to);
+                    helpdesk.assign(&dir, id, &technician).map(|_| Some(id)).unwrap_or_else(|e| fail(e))
+                },
+                TicketAction::Comment { id, text, caller } => helpdesk.comment(id, caller, text, now).map(|_| Some(id)).unwrap_or_else(|e| fail(e)),
+                TicketAction::Start { id } => helpdesk.start(id).map(|_| Some(id)).unwrap_or_else(|e| fail(e)),
+                TicketAction::Wait { id, on } => helpdesk.wait(id, on).map(|_| Some(id)).unwrap_or_else(|e| fail(e)),
+                TicketAction::Resolve { id, resolution } => helpdesk.resolve(id, resolution, now).map(|_| Some(id)).unwrap_or_else(|e| fail(e)),
+                TicketAction::Reopen { id } => helpdesk.reopen(id).map(|_| Some(id)).unwrap_or_else(|e| fail(e)),
+            };
+            if let Some(id) = changed {
+                helpdesk.save(&tickets).unwrap_or_else(|e| fail(e));
+                let t = helpdesk.get(id).expect("just changed");
+                println!("{}: {}", t.id, t.status);
+            }
+        },
+        Command::Broadcast { message, team, department, caller } => {
+            let caller = load_roles(&cli.roles).caller(caller);
+            let members = org_members(&dir, team, department).into_iter().flatten().collect();
+            for id in dir.broadcast_as(&caller, &members, &message).unwrap_or_else(|e| fail(e)) {
+                let person = dir.person(id).expect("recipients are owners");
+                println!("Sent to {} {} <{}>", person.first, person.last, person.email.as_ref());
+            }
+            for to in dir.deferred_recipients() {
+                println!("Deferred for {}: no PC is available", to.as_ref());
+            }
+        },
+        Command::Compliance { policy, date } => {
+            let policy = match CompliancePolicy::load(&policy) {
+                Ok(policy) => policy,
+                Err(e) => {
+                    eprintln!("{}: {e}", policy.display());
+                    std::process::exit(1);
+                }
+            };
+            let today = date.unwrap_or_else(|| chrono::Local::now().date_naive());
+            for (pc, status) in policy.classify(&dir, today) {
+                if status.is_compliant() {
+                    continue;
+                }
+                let owner = describe_owner(pc);
+                print!("PC {}: {}, {status}; owner: {owner}", pc.id(), pc.os());
+                match status.upgrade_to() {
+                    Some(target) => println!("; upgrade to {target}"),
+                    None => println!(),
+                }
+            }
+        },
+        Command::Inventory => {
+            for pc in dir.iter_pcs() {
+                let hw = &pc.hardware;
+                let list = |items: Vec<String>| match items.is_empty() {
+                    true => "none".to_string(),
+                    false => items.join(", "),
+                };
+                println!("PC {}: owner: {}", pc.id(), describe_owner(pc));
+                println!("  serial number: {}", hw.serial_number.as_deref().unwrap_or("unknown"));
+                println!("  purchased:     {}", hw.purchase_date.map_or("unknown".into(), |d| d.to_string()));
+                println!("  price:         {}", hw.purchase_price.map_or("unknown".into(), |p| p.to_string()));
+                println!("  CPU:           {}", hw.cpu.as_ref().map_or("unknown".into(), |c| c.to_string()));
+                println!("  RAM:           {}", hw.ram);
+                println!("  storage:       {}", hw.storage());
+                println!("  disks:         {}", list(hw.disks.iter().map(|d| d.to_string()).collect()));
+                println!("  GPUs:          {}", list(hw.gpus.iter().map(|g| g.to_string()).collect()));
+                println!("  NICs:          {}", list(hw.nics.iter().map(|n| n.to_string()).collect()));
+            }
+        },
+        Command::Report { format } => {
+            print!("{}", FleetReport::new(&dir).render(format));
+        },
+        Command::Chargeback { year, format } => {
+            let year = year.unwrap_or_else(|| chrono::Local::now().year());
+            let chargeback = Chargeback::new(&dir, year).unwrap_or_else(|e| fail(e));
+            print!("{}", chargeback.render(format));
+        },
+        Command::SweepEngagements { notice_days, action, date, directory, caller } => {
+            let caller = load_roles(&cli.roles).caller(caller);
+            if let Some(path) = &directory {
+                dir = PcDirectory::load(path).unwrap_or_else(|e| fail(e));
+            }
+            let today = date.unwrap_or_else(|| chrono::Local::now().date_naive());
+            let sweep = EngagementSweep::new(notice_days).with_action(action);
+            for finding in sweep.run(&caller, &mut dir, today).unwrap_or_else(|e| fail(e)) {
+                let owner = finding.owner.as_ref();
+                match finding.status {
+                    FindingStatus::Expiring { days_left, notified } => {
+                        print!("PC {}: engagement of {owner} ends on {} ({days_left} days left)", finding.pc, finding.end);
+                        match notified {
+                            true => println!("; reminder sent"),
+                            false => println!(),
+                        }
+                    },
+                    FindingStatus::Ended { action } => {
+                        let done = match action {
+                            ExpiryAction::Flag => "flagged",
+                            ExpiryAction::PowerOff => "turned off",
+                            ExpiryAction::Reclaim => "reclaimed",
+                        };
+                        println!("PC {}: engagement of {owner} ended on {}; {done}", finding.pc, finding.end);
+                    },
+                    FindingStatus::Skipped { action, reason } => {
+                        let undone = match action {
+                            ExpiryAction::Flag => "flag",
+                            ExpiryAction::PowerOff => "turn off",
+                            ExpiryAction::Reclaim => "reclaim",
+                        };
+                        println!("PC {}: engagement of {owner} ended on {}; could not {undone} it: {reason}", finding.pc, finding.end);
+                    },
+                }
+            }
+            if let Some(path) = &directory {
+                dir.save(path).unwrap_or_else(|e| fail(e));
+            }
+        },
+        Command::UpgradeCheck { to } => {
+            for (pc, reason) in CompatibilityMatrix::builtin().incompatible_pcs(&dir, &to) {
+                println!("PC {}: {reason}; owner: {}", pc.id(), describe_owner(pc));
+            }
+        },
+        Command::RegisterSelf { directory, owner, caller } => {
+            let caller = load_roles(&cli.roles).caller(caller);
+            let pc = probe::probe().unwrap_or_else(|e| fail(e));
+            add_to_directory(&directory, &caller, pc, owner, profiles);
+        },
+        Command::AddPc { directory, profile, owner, caller } => {
+            let caller = load_roles(&cli.roles).caller(caller);
+            let pc = match profile {
+                Some(name) => profiles.builder(&name).unwrap_or_else(|e| fail(e)),
+                None => PcBuilder::default(),
+            };
+            add_to_directory(&directory, &caller, pc, owner, profiles);
+        },
+        Command::UpdatePerson { directory, email, first, last, new_email } => {
+            let mut target = PcDirectory::load(&directory).unwrap_or_else(|e| fail(e));
+            let Some((id, _)) = target.find_person(&email) else {
+                fail(format!("{} does not own any PC.", email.as_ref()));
+            };
+            target
+                .update_person(id, |p| {
+                    p.first = first.unwrap_or_else(|| p.first.clone());
+                    p.last = last.unwrap_or_else(|| p.last.clone());
+                    p.email = new_email.unwrap_or_else(|| p.email.clone());
+                })
+                .unwrap_or_else(|e| fail(e));
+            target.save(&directory).unwrap_or_else(|e| fail(e));
 
-    println!("Hello, world!");
+            let p = target.person(id).expect("just updated");
+            print!("Updated {id}: {} {} <{}>", p.first, p.last, p.email.as_ref());
+            match p.previous_emails.is_empty() {
+                true => println!(),
+                false => {
+                    let previous: Vec<_> = p.previous_emails.iter().map(|e| e.as_ref()).collect();
+                    println!(", previously {}", previous.join(", "));
+                },
+            }
+        },
+        Command::Profiles => {
+            for (name, profile) in profiles.iter() {
+                let hardware = profile.hardware.build();
+                let cpu = hardware.cpu.as_ref().map_or("unknown CPU".into(), |c| c.to_string());
+                println!("{name}: {}, {cpu}, {} of RAM", profile.os, hardware.ram);
+            }
+            println!("default: {}", profiles.default_name(None));
+        },
+        Command::Query { query, sort, desc, fields } => {
+            let parsed = Query::parse(&query).unwrap_or_else(|e| fail(e.show(&query)));
+            let mut pcs: Vec<_> = parsed.filter(&dir).collect();
+            if let Some(field) = sort {
+                // PCs without a value for the field come last.
+                pcs.sort_by(|a, b| match (field.value(&dir, a), field.value(&dir, b)) {
+                    (Some(a), Some(b)) if desc => b.sort_cmp(&a),
+                    (Some(a), Some(b)) => a.sort_cmp(&b),
+                    (a, b) => a.is_none().cmp(&b.is_none()),
+                });
+            }
+            if fields.is_empty() {
+                for pc in pcs {
+                    println!("PC {}: {}; owner: {}", pc.id(), pc.os(), describe_owner(pc));
+                }
+                return;
+            }
+            println!("{}", fields.iter().map(|f| f.name()).collect::<Vec<_>>().join("\t"));
+            for pc in pcs {
+                let values: Vec<_> = fields
+                    .iter()
+                    .map(|f| f.value(&dir, pc).map_or("-".into(), |v| v.to_string()))
+                    .collect();
+                println!("{}", values.join("\t"));
+            }
+        },
+    }
 }
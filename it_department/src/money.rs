@@ -0,0 +1,544 @@
+//! Amounts of money in different currencies.
+//!
+//! [Money] is counted in the minor unit of its [Currency] (rappen, cents), and
+//! the currency is part of its type: adding francs to euros does not compile.
+//! Converting between currencies is explicit and needs [ExchangeRates].
+//!
+//! Amounts are written like "CHF 1'234.50", with an apostrophe between
+//! thousands as is customary in Switzerland.
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    iter,
+    marker::PhantomData,
+    str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+pub trait Currency {
+    /// The ISO 4217 code, e.g. "CHF".
+    const CODE: &'static str;
+    /// The number of decimals of the minor unit.
+    const DECIMALS: u32 = 2;
+    /// The smallest amount of cash, in minor units. Cash payments are rounded
+    /// to it, see [Money::round_cash].
+    const CASH_STEP: u64 = 1;
+}
+
+pub enum Chf {}
+pub enum Eur {}
+pub enum Usd {}
+
+impl Currency for Chf {
+    const CODE: &'static str = "CHF";
+    // There are no coins for less than 5 rappen.
+    const CASH_STEP: u64 = 5;
+}
+
+impl Currency for Eur {
+    const CODE: &'static str = "EUR";
+}
+
+impl Currency for Usd {
+    const CODE: &'static str = "USD";
+}
+
+pub type ChfAmout = Money<Chf>;
+pub type EurAmount = Money<Eur>;
+pub type UsdAmount = Money<Usd>;
+
+/// An amount of money in the currency `C`.
+///
+/// There are no arithmetic operators; the `checked_*` methods report overflows
+/// instead of wrapping around.
+pub struct Money<C> {
+    minor: u64,
+    currency: PhantomData<C>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MoneyError {
+    #[error("No amount given.")]
+    Empty,
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(String),
+    #[error("The amount {0} has more decimals than the currency.")]
+    TooManyDecimals(String),
+    #[error("Expected an amount in {expected}, got {found}.")]
+    CurrencyMismatch {
+        expected: &'static str,
+        found: String,
+    },
+    #[error("The amount is too large.")]
+    Overflow,
+    #[error("Invalid exchange rate: {0}")]
+    InvalidRate(String),
+    #[error("There is no exchange rate from {from} to {to}.")]
+    MissingRate {
+        from: &'static str,
+        to: &'static str,
+    },
+}
+
+impl<C: Currency> Money<C> {
+    const MINOR_PER_MAJOR: u64 = 10u64.pow(C::DECIMALS);
+
+    pub const fn zero() -> Self {
+        Self::from_minor(0)
+    }
+
+    /// An amount in minor units, e.g. rappen.
+    pub const fn from_minor(minor: u64) -> Self {
+        Self {
+            minor,
+            currency: PhantomData,
+        }
+    }
+
+    /// An amount of whole francs, euros, ...
+    ///
+    /// # Panics
+    ///
+    /// Panics if the amount does not fit.
+    pub const fn from_major(major: u64) -> Self {
+        match major.checked_mul(Self::MINOR_PER_MAJOR) {
+            Some(minor) => Self::from_minor(minor),
+            None => panic!("the amount is too large"),
+        }
+    }
+
+    pub const fn minor(&self) -> u64 {
+        self.minor
+    }
+
+    /// The whole francs, euros, ..., without the minor units.
+    pub const fn major(&self) -> u64 {
+        self.minor / Self::MINOR_PER_MAJOR
+    }
+
+    pub fn currency(&self) -> &'static str {
+        C::CODE
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.minor == 0
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.minor.checked_add(rhs.minor).map(Self::from_minor)
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.minor.checked_sub(rhs.minor).map(Self::from_minor)
+    }
+
+    pub fn checked_mul(self, rhs: u64) -> Option<Self> {
+        self.minor.checked_mul(rhs).map(Self::from_minor)
+    }
+
+    /// The sum of `amounts`, or `None` if it overflows.
+    pub fn checked_sum<I: IntoIterator<Item = Self>>(amounts: I) -> Option<Self> {
+        amounts
+            .into_iter()
+            .try_fold(Self::zero(), |sum, amount| sum.checked_add(amount))
+    }
+
+    /// Split the amount into `parts` amounts that add up to it exactly. The
+    /// parts differ by at most one minor unit, larger parts come first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parts` is zero.
+    pub fn split(self, parts: u64) -> Vec<Self> {
+        assert!(parts > 0, "cannot split an amount into zero parts");
+        let (each, remainder) = (self.minor / parts, self.minor % parts);
+        (0..parts)
+            .map(|i| Self::from_minor(each + u64::from(i < remainder)))
+            .collect()
+    }
+
+    /// The amount times `numerator / denominator`, rounded half up to the
+    /// minor unit.
+    pub fn mul_ratio(self, numerator: u64, denominator: u64) -> Option<Self> {
+        let product = self.minor as u128 * numerator as u128;
+        let rounded = round_half_up(product, denominator as u128)?;
+        u64::try_from(rounded).ok().map(Self::from_minor)
+    }
+
+    /// Round half up to the smallest amount that can be paid in cash, e.g. to
+    /// 5 rappen.
+    pub fn round_cash(self) -> Option<Self> {
+        let step = C::CASH_STEP as u128;
+        let steps = round_half_up(self.minor as u128, step)?;
+        u64::try_from(steps * step).ok().map(Self::from_minor)
+    }
+}
+
+// `n / d`, rounded half up. `None` if `d` is zero.
+fn round_half_up(n: u128, d: u128) -> Option<u128> {
+    (d != 0).then(|| (n + d / 2) / d)
+}
+
+// The derives would require the currency markers to implement the traits.
+impl<C> Clone for Money<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for Money<C> {}
+
+impl<C> PartialEq for Money<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.minor == other.minor
+    }
+}
+
+impl<C> Eq for Money<C> {}
+
+impl<C> PartialOrd for Money<C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<C> Ord for Money<C> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.minor.cmp(&other.minor)
+    }
+}
+
+impl<C> Hash for Money<C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.minor.hash(state);
+    }
+}
+
+impl<C: Currency> Default for Money<C> {
+    fn default() -> Self {
+        Self::zero()
+    }
+}
+
+impl<C: Currency> fmt::Debug for Money<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+/// Print the amount with its currency and all decimals, e.g. "CHF 1'234.50".
+impl<C: Currency> fmt::Display for Money<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.major().to_string();
+        let mut major = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                major.push('\'');
+            }
+            major.push(digit);
+        }
+        write!(f, "{} {major}", C::CODE)?;
+        if C::DECIMALS > 0 {
+            let minor = self.minor % Self::MINOR_PER_MAJOR;
+            write!(f, ".{minor:0width$}", width = C::DECIMALS as usize)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse amounts like "CHF 1'234.50", "1234.5", "12 EUR" or "CHF 12.-". The
+/// currency code is optional, but must match if it is given.
+impl<C: Currency> FromStr for Money<C> {
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(MoneyError::Empty);
+        }
+        let is_code = |c: char| c.is_ascii_alphabetic();
+        let code_len = s.len() - s.trim_start_matches(is_code).len();
+        let (code, amount) = match code_len {
+            0 => {
+                let amount = s.trim_end_matches(is_code);
+                (&s[amount.len()..], amount)
+            }
+            _ => s.split_at(code_len),
+        };
+        if !code.is_empty() && !code.eq_ignore_ascii_case(C::CODE) {
+            return Err(MoneyError::CurrencyMismatch {
+                expected: C::CODE,
+                found: code.into(),
+            });
+        }
+
+        let invalid = || MoneyError::InvalidAmount(s.into());
+        let amount: String = amount
+            .trim()
+            .trim_end_matches(".-")
+            .chars()
+            .filter(|c| !matches!(c, '\'' | '’'))
+            .collect();
+        let (major, minor) = amount.split_once('.').unwrap_or((&amount, ""));
+        let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if major.is_empty() || !all_digits(major) || !all_digits(minor) {
+            return Err(invalid());
+        }
+        if minor.len() > C::DECIMALS as usize {
+            return Err(MoneyError::TooManyDecimals(s.into()));
+        }
+        let major: u64 = major.parse().map_err(|_| MoneyError::Overflow)?;
+        let minor = format!("{minor:0<width$}", width = C::DECIMALS as usize);
+        let minor: u64 = match minor.is_empty() {
+            true => 0,
+            false => minor.parse().map_err(|_| invalid())?,
+        };
+        major
+            .checked_mul(Self::MINOR_PER_MAJOR)
+            .and_then(|m| m.checked_add(minor))
+            .map(Self::from_minor)
+            .ok_or(MoneyError::Overflow)
+    }
+}
+
+impl<C: Currency> Serialize for Money<C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// Amounts used to be stored as plain numbers of whole francs, which are still
+// understood.
+impl<'de, C: Currency> Deserialize<'de> for Money<C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Major(u64),
+            Text(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Major(major) => major
+                .checked_mul(Self::MINOR_PER_MAJOR)
+                .map(Self::from_minor)
+                .ok_or_else(|| de::Error::custom(MoneyError::Overflow)),
+            Repr::Text(s) => s.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// How much one unit of a currency is worth in another one, to six decimals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExchangeRate {
+    millionths: u64,
+}
+
+impl ExchangeRate {
+    const ONE: u64 = 1_000_000;
+}
+
+impl FromStr for ExchangeRate {
+    type Err = MoneyError;
+
+    /// Parse a positive decimal number like "0.9412".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MoneyError::InvalidRate(s.into());
+        // `parse` would accept a sign, so check for plain digits first.
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        let (int, frac) = match s.trim().split_once('.') {
+            Some((int, frac)) if digits(frac) => (int, frac),
+            Some(_) => return Err(invalid()),
+            None => (s.trim(), ""),
+        };
+        if !digits(int) || frac.len() > 6 {
+            return Err(invalid());
+        }
+        let int: u64 = int.parse().map_err(|_| invalid())?;
+        let frac: u64 = match frac.is_empty() {
+            true => 0,
+            false => format!("{frac:0<6}").parse().map_err(|_| invalid())?,
+        };
+        match int.checked_mul(Self::ONE).and_then(|i| i.checked_add(frac)) {
+            Some(0) | None => Err(invalid()),
+            Some(millionths) => Ok(Self { millionths }),
+        }
+    }
+}
+
+/// Exchange rates between currencies, for explicit conversions.
+///
+/// A rate from `A` to `B` is also used the other way around, unless a rate
+/// from `B` to `A` is given as well.
+#[derive(Debug, Clone, Default)]
+pub struct ExchangeRates {
+    rates: HashMap<(&'static str, &'static str), ExchangeRate>,
+}
+
+impl ExchangeRates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set what one unit of `From` is worth in `To`.
+    pub fn with_rate<From: Currency, To: Currency>(mut self, rate: ExchangeRate) -> Self {
+        self.rates.insert((From::CODE, To::CODE), rate);
+        self
+    }
+
+    /// Convert `amount` to `To`, rounding half up to the minor unit.
+    pub fn convert<From: Currency, To: Currency>(
+        &self,
+        amount: Money<From>,
+    ) -> Result<Money<To>, MoneyError> {
+        // Both currencies may have a different number of decimals.
+        let (scale_from, scale_to) = (
+            Money::<From>::MINOR_PER_MAJOR as u128,
+            Money::<To>::MINOR_PER_MAJOR as u128,
+        );
+        let (numerator, denominator) = if From::CODE == To::CODE {
+            (scale_to, scale_from)
+        } else if let Some(rate) = self.rates.get(&(From::CODE, To::CODE)) {
+            (
+                rate.millionths as u128 * scale_to,
+                ExchangeRate::ONE as u128 * scale_from,
+            )
+        } else if let Some(rate) = self.rates.get(&(To::CODE, From::CODE)) {
+            (
+                ExchangeRate::ONE as u128 * scale_to,
+                rate.millionths as u128 * scale_from,
+            )
+        } else {
+            return Err(MoneyError::MissingRate {
+                from: From::CODE,
+                to: To::CODE,
+            });
+        };
+        let converted = (amount.minor as u128)
+            .checked_mul(numerator)
+            .and_then(|n| round_half_up(n, denominator))
+            .ok_or(MoneyError::Overflow)?;
+        u64::try_from(converted)
+            .map(Money::from_minor)
+            .map_err(|_| MoneyError::Overflow)
+    }
+}
+
+impl<C: Currency> iter::Sum for Money<C> {
+    /// # Panics
+    ///
+    /// Panics on overflow; use [Money::checked_sum] to handle it.
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        Self::checked_sum(iter).expect("the sum of the amounts is too large")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chf(s: &str) -> ChfAmout {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(chf("CHF 1'234.50"), ChfAmout::from_minor(123450));
+        assert_eq!(chf("1234.5"), chf("CHF 1'234.50"));
+        assert_eq!(chf("12 chf"), ChfAmout::from_major(12));
+        assert_eq!(chf("CHF 12.-"), ChfAmout::from_major(12));
+        assert_eq!(chf("CHF 1’000"), ChfAmout::from_major(1000));
+
+        assert_eq!("".parse::<ChfAmout>(), Err(MoneyError::Empty));
+        assert!(matches!(
+            "EUR 12".parse::<ChfAmout>(),
+            Err(MoneyError::CurrencyMismatch {
+                expected: "CHF",
+                ..
+            })
+        ));
+        assert!(matches!(
+            "12.345".parse::<ChfAmout>(),
+            Err(MoneyError::TooManyDecimals(_))
+        ));
+        assert!(matches!(
+            "12,50".parse::<ChfAmout>(),
+            Err(MoneyError::InvalidAmount(_))
+        ));
+
+        assert_eq!(ChfAmout::from_minor(123450).to_string(), "CHF 1'234.50");
+        assert_eq!(UsdAmount::from_minor(5).to_string(), "USD 0.05");
+        assert_eq!(
+            EurAmount::from_major(1_000_000).to_string(),
+            "EUR 1'000'000.00"
+        );
+        assert_eq!(chf("999").to_string(), "CHF 999.00");
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let max = ChfAmout::from_minor(u64::MAX);
+        assert_eq!(max.checked_add(ChfAmout::from_minor(1)), None);
+        assert_eq!(chf("1").checked_sub(chf("2")), None);
+        assert_eq!(ChfAmout::checked_sum([max, chf("0.01")]), None);
+        assert_eq!(
+            chf("100").split(3),
+            vec![chf("33.34"), chf("33.33"), chf("33.33")]
+        );
+        assert_eq!(chf("10").mul_ratio(1, 3), Some(chf("3.33")));
+        assert_eq!(chf("10").mul_ratio(2, 3), Some(chf("6.67")));
+    }
+
+    #[test]
+    fn test_cash_rounding() {
+        assert_eq!(chf("1.02").round_cash(), Some(chf("1.00")));
+        assert_eq!(chf("1.03").round_cash(), Some(chf("1.05")));
+        assert_eq!(chf("1.07").round_cash(), Some(chf("1.05")));
+        assert_eq!(
+            EurAmount::from_minor(103).round_cash(),
+            Some(EurAmount::from_minor(103))
+        );
+    }
+
+    #[test]
+    fn test_convert() {
+        let rates = ExchangeRates::new()
+            .with_rate::<Eur, Chf>("0.9412".parse().unwrap())
+            .with_rate::<Usd, Chf>("0.8".parse().unwrap());
+        let eur = EurAmount::from_major(100);
+        assert_eq!(rates.convert::<Eur, Chf>(eur), Ok(chf("94.12")));
+        // The other way around, rounded half up: 100 / 0.9412 = 106.247...
+        assert_eq!(
+            rates.convert::<Chf, Eur>(chf("100")),
+            Ok(EurAmount::from_minor(10625))
+        );
+        assert_eq!(rates.convert::<Chf, Chf>(chf("1.23")), Ok(chf("1.23")));
+        assert_eq!(
+            rates.convert::<Eur, Usd>(eur),
+            Err(MoneyError::MissingRate {
+                from: "EUR",
+                to: "USD"
+            })
+        );
+
+        assert!("0".parse::<ExchangeRate>().is_err());
+        assert!("1.2345678".parse::<ExchangeRate>().is_err());
+        for invalid in ["1.+5", "1.-5", "+1.5", "1.", ".5", "1.5.1"] {
+            assert!(invalid.parse::<ExchangeRate>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_serde() {
+        let amount = chf("1234.5");
+        let json = serde_json::to_string(&amount).unwrap();
+        assert_eq!(json, r#""CHF 1'234.50""#);
+        assert_eq!(serde_json::from_str::<ChfAmout>(&json).unwrap(), amount);
+        // Whole francs, like before amounts had minor units.
+        assert_eq!(serde_json::from_str::<ChfAmout>("10").unwrap(), chf("10"));
+        assert!(serde_json::from_str::<ChfAmout>(r#""USD 10""#).is_err());
+    }
+}
@@ -0,0 +1,289 @@
+//! The organization: departments, their teams and who reports to whom.
+//!
+//! People are referred to by their [PersonId], so the [OrgChart] survives
+//! changes of names and email addresses. A [crate::pc_directory::PcDirectory]
+//! keeps the chart of its owners and stores it together with the PCs.
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::person::PersonId;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "OrgChartRepr")]
+pub struct OrgChart {
+    /// The teams of every department.
+    departments: BTreeMap<String, BTreeSet<String>>,
+    teams: BTreeMap<String, Team>,
+    /// The manager of everyone who has one.
+    managers: BTreeMap<PersonId, PersonId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Team {
+    pub department: String,
+    pub members: BTreeSet<PersonId>,
+}
+
+/// A part of the organization whose members can be looked up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrgUnit {
+    Department(String),
+    Team(String),
+    /// Everyone reporting to the manager, directly or indirectly.
+    ReportsOf(PersonId),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum OrgError {
+    #[error("There is no department named {name}.")]
+    UnknownDepartment { name: String },
+    #[error("There is no team named {name}.")]
+    UnknownTeam { name: String },
+    #[error("The team {name} already exists.")]
+    DuplicateTeam { name: String },
+    #[error("{person} cannot report to {manager}, who already reports to {person}.")]
+    ManagerCycle { person: PersonId, manager: PersonId },
+}
+
+impl OrgChart {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a department without teams. Adding an existing department does
+    /// nothing.
+    pub fn add_department<S: ToString>(&mut self, name: S) {
+        self.departments.entry(name.to_string()).or_default();
+    }
+
+    /// Add a team without members to an existing department.
+    pub fn add_team<S: ToString>(&mut self, name: S, department: &str) -> Result<(), OrgError> {
+        let name = name.to_string();
+        if self.teams.contains_key(&name) {
+            return Err(OrgError::DuplicateTeam { name });
+        }
+        let Some(teams) = self.departments.get_mut(department) else {
+            return Err(OrgError::UnknownDepartment {
+                name: department.into(),
+            });
+        };
+        teams.insert(name.clone());
+        self.teams.insert(
+            name,
+            Team {
+                department: department.into(),
+                members: BTreeSet::new(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Add `person` to a team. People can be members of several teams.
+    pub fn add_member(&mut self, team: &str, person: PersonId) -> Result<(), OrgError> {
+        self.teams
+            .get_mut(team)
+            .ok_or_else(|| OrgError::UnknownTeam { name: team.into() })?
+            .members
+            .insert(person);
+        Ok(())
+    }
+
+    pub fn remove_member(&mut self, team: &str, person: PersonId) -> Result<(), OrgError> {
+        self.teams
+            .get_mut(team)
+            .ok_or_else(|| OrgError::UnknownTeam { name: team.into() })?
+            .members
+            .remove(&person);
+        Ok(())
+    }
+
+    /// Let `person` report to `manager`, replacing their previous manager.
+    ///
+    /// # Returns
+    ///
+    /// An error if `manager` reports to `person`, directly or indirectly, or
+    /// if both are the same.
+    pub fn set_manager(&mut self, person: PersonId, manager: PersonId) -> Result<(), OrgError> {
+        if manager == person || self.chain_of_command(manager).contains(&person) {
+            return Err(OrgError::ManagerCycle { person, manager });
+        }
+        self.managers.insert(person, manager);
+        Ok(())
+    }
+
+    /// Let `person` report to nobody.
+    pub fn remove_manager(&mut self, person: PersonId) {
+        self.managers.remove(&person);
+    }
+
+    pub fn manager_of(&self, person: PersonId) -> Option<PersonId> {
+        self.managers.get(&person).copied()
+    }
+
+    /// The manager of `person`, their manager and so on, up to the top.
+    pub fn chain_of_command(&self, person: PersonId) -> Vec<PersonId> {
+        // The chart has no cycles, see `set_manager`.
+        std::iter::successors(self.manager_of(person), |&m| self.manager_of(m)).collect()
+    }
+
+    /// Everyone reporting directly to `manager`.
+    pub fn direct_reports(&self, manager: PersonId) -> BTreeSet<PersonId> {
+        self.managers
+            .iter()
+            .filter(|(_, &m)| m == manager)
+            .map(|(&p, _)| p)
+            .collect()
+    }
+
+    pub fn departments(&self) -> impl Iterator<Item = &str> {
+        self.departments.keys().map(String::as_str)
+    }
+
+    pub fn teams(&self) -> impl Iterator<Item = (&str, &Team)> {
+        self.teams.iter().map(|(name, team)| (name.as_str(), team))
+    }
+
+    /// The names of the teams `person` is a member of.
+    pub fn teams_of(&self, person: PersonId) -> Vec<&str> {
+        self.teams()
+            .filter(|(_, team)| team.members.contains(&person))
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// The members of a part of the organization.
+    pub fn members(&self, unit: &OrgUnit) -> Result<BTreeSet<PersonId>, OrgError> {
+        match unit {
+            OrgUnit::Team(name) => self
+                .teams
+                .get(name)
+                .map(|team| team.members.clone())
+                .ok_or_else(|| OrgError::UnknownTeam { name: name.clone() }),
+            OrgUnit::Department(name) => {
+                let teams = self
+                    .departments
+                    .get(name)
+                    .ok_or_else(|| OrgError::UnknownDepartment { name: name.clone() })?;
+                Ok(teams
+                    .iter()
+                    .flat_map(|team| &self.teams[team].members)
+                    .copied()
+                    .collect())
+            }
+            OrgUnit::ReportsOf(manager) => {
+                let mut reports = BTreeSet::new();
+                let mut pending = vec![*manager];
+                while let Some(manager) = pending.pop() {
+                    for report in self.direct_reports(manager) {
+                        if reports.insert(report) {
+                            pending.push(report);
+                        }
+                    }
+                }
+                Ok(reports)
+            }
+        }
+    }
+}
+
+// Charts are checked when loading, as they may have been edited by hand.
+#[derive(Deserialize)]
+struct OrgChartRepr {
+    #[serde(default)]
+    departments: BTreeMap<String, BTreeSet<String>>,
+    #[serde(default)]
+    teams: BTreeMap<String, Team>,
+    #[serde(default)]
+    managers: BTreeMap<PersonId, PersonId>,
+}
+
+impl TryFrom<OrgChartRepr> for OrgChart {
+    type Error = OrgError;
+
+    fn try_from(repr: OrgChartRepr) -> Result<Self, Self::Error> {
+        let mut chart = OrgChart::new();
+        // The teams of the departments follow from the teams.
+        repr.departments
+            .keys()
+            .for_each(|name| chart.add_department(name));
+        for (name, team) in repr.teams {
+            chart.add_department(&team.department);
+            chart.add_team(&name, &team.department)?;
+            chart.teams.get_mut(&name).expect("just added").members = team.members;
+        }
+        for (person, manager) in repr.managers {
+            chart.set_manager(person, manager)?;
+        }
+        Ok(chart)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANNA: PersonId = PersonId(0);
+    const BEN: PersonId = PersonId(1);
+    const CLARA: PersonId = PersonId(2);
+    const DAN: PersonId = PersonId(3);
+
+    fn chart() -> OrgChart {
+        let mut chart = OrgChart::new();
+        chart.add_department("Engineering");
+        chart.add_department("Sales");
+        chart.add_department("Legal");
+        chart.add_team("platform", "Engineering").unwrap();
+        chart.add_team("apps", "Engineering").unwrap();
+        chart.add_team("accounts", "Sales").unwrap();
+        chart.add_member("platform", BEN).unwrap();
+        chart.add_member("apps", CLARA).unwrap();
+        chart.add_member("accounts", DAN).unwrap();
+        chart.set_manager(BEN, ANNA).unwrap();
+        chart.set_manager(CLARA, BEN).unwrap();
+        chart
+    }
+
+    #[test]
+    fn test_members() {
+        let chart = chart();
+        let members = |unit| chart.members(&unit).unwrap();
+        assert_eq!(
+            members(OrgUnit::Team("apps".into())),
+            BTreeSet::from([CLARA])
+        );
+        assert_eq!(
+            members(OrgUnit::Department("Engineering".into())),
+            BTreeSet::from([BEN, CLARA])
+        );
+        assert_eq!(
+            members(OrgUnit::ReportsOf(ANNA)),
+            BTreeSet::from([BEN, CLARA])
+        );
+        assert_eq!(chart.chain_of_command(CLARA), vec![BEN, ANNA]);
+        assert_eq!(chart.teams_of(DAN), vec!["accounts"]);
+        assert_eq!(
+            chart.members(&OrgUnit::Team("ops".into())),
+            Err(OrgError::UnknownTeam { name: "ops".into() })
+        );
+    }
+
+    #[test]
+    fn test_cycles_are_rejected() {
+        let mut chart = chart();
+        assert!(matches!(
+            chart.set_manager(ANNA, CLARA),
+            Err(OrgError::ManagerCycle { .. })
+        ));
+        assert!(chart.set_manager(ANNA, ANNA).is_err());
+        assert_eq!(chart.manager_of(ANNA), None);
+
+        // A hand-edited chart is checked as well.
+        let json = r#"{"teams": {}, "managers": {"0": 1, "1": 0}}"#;
+        assert!(serde_json::from_str::<OrgChart>(json).is_err());
+
+        let json = serde_json::to_string(&chart).unwrap();
+        assert_eq!(serde_json::from_str::<OrgChart>(&json).unwrap(), chart);
+    }
+}
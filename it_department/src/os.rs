@@ -0,0 +1,908 @@
+//! Operating systems installed on PCs.
+//!
+//! An [OperatingSystem] consists of a family-specific [OsKind] (which carries
+//! the Windows edition or the Linux distribution and its release), a semantic
+//! version, an optional build number and an optional CPU architecture. For
+//! Linux, the version is the kernel's, so "Ubuntu 22.04 Linux 6.5" is release
+//! 22.04 of Ubuntu running kernel 6.5.
+//!
+//! Windows is versioned by its NT kernel version and build number, just like
+//! Windows does itself. The marketing name ("Windows 11") is derived from
+//! those, see [WindowsRelease].
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OsFamily {
+    Windows,
+    MacOs,
+    Linux,
+}
+
+/// The family of an operating system together with the information that only
+/// makes sense for that family.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OsKind {
+    Windows {
+        edition: Option<WindowsEdition>,
+    },
+    MacOs,
+    Linux {
+        distribution: Option<LinuxDistribution>,
+        /// The release of the distribution, e.g. "22.04" or "bookworm", which
+        /// is independent of the kernel version.
+        release: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WindowsEdition {
+    Home,
+    Pro,
+    Enterprise,
+    Education,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LinuxDistribution {
+    Ubuntu,
+    Debian,
+    Fedora,
+    Rhel,
+    Arch,
+    Other(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CpuArch {
+    X86,
+    X86_64,
+    Arm64,
+}
+
+/// A semantic version.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct OsVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl OsVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+/// The well-known Windows releases, in chronological order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WindowsRelease {
+    Xp,
+    Vista,
+    Win7,
+    Win8,
+    Win81,
+    Win10,
+    Win11,
+}
+
+impl WindowsRelease {
+    const ALL: [Self; 7] = [
+        Self::Xp,
+        Self::Vista,
+        Self::Win7,
+        Self::Win8,
+        Self::Win81,
+        Self::Win10,
+        Self::Win11,
+    ];
+
+    /// The NT kernel version of the release.
+    pub const fn version(self) -> OsVersion {
+        match self {
+            Self::Xp => OsVersion::new(5, 1, 0),
+            Self::Vista => OsVersion::new(6, 0, 0),
+            Self::Win7 => OsVersion::new(6, 1, 0),
+            Self::Win8 => OsVersion::new(6, 2, 0),
+            Self::Win81 => OsVersion::new(6, 3, 0),
+            Self::Win10 | Self::Win11 => OsVersion::new(10, 0, 0),
+        }
+    }
+
+    /// The build number of the first version of the release.
+    pub const fn base_build(self) -> u32 {
+        match self {
+            Self::Xp => 2600,
+            Self::Vista => 6000,
+            Self::Win7 => 7600,
+            Self::Win8 => 9200,
+            Self::Win81 => 9600,
+            Self::Win10 => 10240,
+            Self::Win11 => 22000,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Xp => "XP",
+            Self::Vista => "Vista",
+            Self::Win7 => "7",
+            Self::Win8 => "8",
+            Self::Win81 => "8.1",
+            Self::Win10 => "10",
+            Self::Win11 => "11",
+        }
+    }
+}
+
+/// An operating system installed on a PC.
+///
+/// Operating systems are only partially ordered: releases of the same family
+/// are ordered by version and build, while, e.g., Windows and Linux cannot be
+/// compared at all. Two operating systems of the same release that differ in
+/// edition, distribution or architecture are incomparable, too; use
+/// [OperatingSystem::cmp_release] to ignore those.
+// Serialized as its string representation, which is what humans write into
+// configuration files.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct OperatingSystem {
+    pub kind: OsKind,
+    pub version: OsVersion,
+    pub build: Option<u32>,
+    pub arch: Option<CpuArch>,
+}
+
+impl OperatingSystem {
+    pub fn windows(release: WindowsRelease) -> Self {
+        Self {
+            kind: OsKind::Windows { edition: None },
+            version: release.version(),
+            build: Some(release.base_build()),
+            arch: None,
+        }
+    }
+
+    pub fn macos(major: u16, minor: u16) -> Self {
+        Self {
+            kind: OsKind::MacOs,
+            version: OsVersion::new(major, minor, 0),
+            build: None,
+            arch: None,
+        }
+    }
+
+    pub fn linux(major: u16, minor: u16) -> Self {
+        Self {
+            kind: OsKind::Linux {
+                distribution: None,
+                release: None,
+            },
+            version: OsVersion::new(major, minor, 0),
+            build: None,
+            arch: None,
+        }
+    }
+
+    pub fn with_patch(self, patch: u16) -> Self {
+        Self {
+            version: OsVersion {
+                patch,
+                ..self.version
+            },
+            ..self
+        }
+    }
+
+    pub fn with_build(self, build: u32) -> Self {
+        Self {
+            build: Some(build),
+            ..self
+        }
+    }
+
+    pub fn with_arch(self, arch: CpuArch) -> Self {
+        Self {
+            arch: Some(arch),
+            ..self
+        }
+    }
+
+    /// Set the Windows edition.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is not a Windows operating system.
+    pub fn with_edition(self, edition: WindowsEdition) -> Self {
+        assert!(self.is_windows(), "Only Windows has editions.");
+        Self {
+            kind: OsKind::Windows {
+                edition: Some(edition),
+            },
+            ..self
+        }
+    }
+
+    /// Set the Linux distribution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is not a Linux operating system.
+    pub fn with_distribution(self, distribution: LinuxDistribution) -> Self {
+        let OsKind::Linux { release, .. } = self.kind else {
+            panic!("Only Linux has distributions.");
+        };
+        Self {
+            kind: OsKind::Linux {
+                distribution: Some(distribution),
+                release,
+            },
+            ..self
+        }
+    }
+
+    /// Set the release of the Linux distribution, e.g. "22.04".
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is not a Linux operating system with a distribution.
+    pub fn with_release<S: ToString>(self, release: S) -> Self {
+        let OsKind::Linux {
+            distribution: Some(distribution),
+            ..
+        } = self.kind
+        else {
+            panic!("Only Linux distributions have releases.");
+        };
+        Self {
+            kind: OsKind::Linux {
+                distribution: Some(distribution),
+                release: Some(release.to_string()),
+            },
+            ..self
+        }
+    }
+
+    /// The Linux distribution, if known.
+    pub fn distribution(&self) -> Option<&LinuxDistribution> {
+        match &self.kind {
+            OsKind::Linux { distribution, .. } => distribution.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The release of the Linux distribution, if known.
+    pub fn distribution_release(&self) -> Option<&str> {
+        match &self.kind {
+            OsKind::Linux { release, .. } => release.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn family(&self) -> OsFamily {
+        match self.kind {
+            OsKind::Windows { .. } => OsFamily::Windows,
+            OsKind::MacOs => OsFamily::MacOs,
+            OsKind::Linux { .. } => OsFamily::Linux,
+        }
+    }
+
+    pub fn is_windows(&self) -> bool {
+        matches!(self.kind, OsKind::Windows { .. })
+    }
+
+    /// The Windows release this version and build belongs to, if any.
+    pub fn windows_release(&self) -> Option<WindowsRelease> {
+        if !self.is_windows() {
+            return None;
+        }
+        let key = (self.version, self.build.unwrap_or_default());
+        WindowsRelease::ALL
+            .into_iter()
+            .rev()
+            .find(|r| (r.version(), r.base_build()) <= key)
+            // Versions of a release beyond its base build only count if the
+            // kernel version matches; otherwise it is some NT version we do
+            // not have a name for.
+            .filter(|r| {
+                r.version().major == self.version.major && r.version().minor == self.version.minor
+            })
+    }
+
+    /// Compare the releases of two operating systems of the same family by
+    /// version and build, ignoring edition, distribution and architecture.
+    pub fn cmp_release(&self, other: &Self) -> Option<Ordering> {
+        if self.family() != other.family() {
+            return None;
+        }
+        Some(
+            (self.version, self.build.unwrap_or_default())
+                .cmp(&(other.version, other.build.unwrap_or_default())),
+        )
+    }
+
+    /// The name of the release without edition, distribution, build and
+    /// architecture, e.g. "Windows 11", "macOS 10.14" or "Linux 6.22".
+    pub fn release_name(&self) -> String {
+        match self.family() {
+            OsFamily::Windows => match self
+                .windows_release()
+                .filter(|r| r.version() == self.version)
+            {
+                Some(release) => format!("Windows {}", release.name()),
+                None => format!("Windows NT {}", self.version),
+            },
+            family => format!("{family} {}", self.version),
+        }
+    }
+}
+
+impl PartialOrd for OperatingSystem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.cmp_release(other)? {
+            Ordering::Equal if self != other => None,
+            ordering => Some(ordering),
+        }
+    }
+}
+
+impl fmt::Display for OperatingSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            OsKind::Windows { edition } => {
+                match self
+                    .windows_release()
+                    .filter(|r| r.version() == self.version)
+                {
+                    Some(release) => write!(f, "Windows {}", release.name())?,
+                    None => write!(f, "Windows NT {}", self.version)?,
+                }
+                if let Some(edition) = edition {
+                    write!(f, " {edition}")?;
+                }
+            }
+            OsKind::MacOs => write!(f, "macOS {}", self.version)?,
+            OsKind::Linux {
+                distribution,
+                release,
+            } => {
+                if let Some(distribution) = distribution {
+                    write!(f, "{distribution} ")?;
+                }
+                if let Some(release) = release {
+                    write!(f, "{release} ")?;
+                }
+                write!(f, "Linux {}", self.version)?;
+            }
+        }
+        // The build of a Windows release is implied by its name unless it is a
+        // later build.
+        let implied_build = self
+            .windows_release()
+            .filter(|r| r.version() == self.version)
+            .map(WindowsRelease::base_build);
+        if let Some(build) = self.build.filter(|b| Some(*b) != implied_build) {
+            write!(f, " (build {build})")?;
+        }
+        if let Some(arch) = self.arch {
+            write!(f, " {arch}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for OsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for OsFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Windows => "Windows",
+            Self::MacOs => "macOS",
+            Self::Linux => "Linux",
+        })
+    }
+}
+
+impl fmt::Display for WindowsEdition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Home => "Home",
+            Self::Pro => "Pro",
+            Self::Enterprise => "Enterprise",
+            Self::Education => "Education",
+        })
+    }
+}
+
+impl fmt::Display for LinuxDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ubuntu => "Ubuntu",
+            Self::Debian => "Debian",
+            Self::Fedora => "Fedora",
+            Self::Rhel => "RHEL",
+            Self::Arch => "Arch",
+            Self::Other(name) => name,
+        })
+    }
+}
+
+impl fmt::Display for CpuArch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::X86 => "x86",
+            Self::X86_64 => "x86_64",
+            Self::Arm64 => "arm64",
+        })
+    }
+}
+
+impl FromStr for CpuArch {
+    type Err = OsParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "x86" | "i386" | "i686" => Ok(Self::X86),
+            "x86_64" | "x86-64" | "amd64" | "x64" => Ok(Self::X86_64),
+            "arm64" | "aarch64" => Ok(Self::Arm64),
+            _ => Err(OsParseError::Unexpected(s.into())),
+        }
+    }
+}
+
+impl LinuxDistribution {
+    // Only well-known distributions are recognized without a following
+    // "Linux".
+    fn known(s: &str) -> Option<Self> {
+        match s {
+            "ubuntu" => Some(Self::Ubuntu),
+            "debian" => Some(Self::Debian),
+            "fedora" => Some(Self::Fedora),
+            "rhel" => Some(Self::Rhel),
+            "arch" => Some(Self::Arch),
+            _ => None,
+        }
+    }
+}
+
+/// Parse an operating system from strings like "Windows 11", "win7",
+/// "Windows 11 Pro (build 22631) x86_64", "macOS 10.14.6", "Linux 6.22",
+/// "Ubuntu Linux 6.5 arm64", "Debian 12 Linux 6.1" or
+/// "linux-5.15.0-91-generic".
+///
+/// The version after "Linux" is always the kernel's; a distribution and its
+/// release come before it. "Fedora 39" alone lacks the kernel version.
+///
+/// Anything after a `-` or `+` following a version is ignored, as it is common
+/// for kernel release strings. The output of [fmt::Display] can always be
+/// parsed again.
+impl FromStr for OperatingSystem {
+    type Err = OsParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s
+            .trim()
+            .to_lowercase()
+            .replace("mac os x", "macos")
+            .replace("mac os", "macos")
+            .replace(['(', ')'], " ");
+        let mut tokens: Vec<&str> = s.split_whitespace().collect();
+
+        let arch = tokens.last().and_then(|t| t.parse::<CpuArch>().ok());
+        if arch.is_some() {
+            tokens.pop();
+        }
+        let mut build = None;
+        if let Some(pos) = tokens.iter().position(|t| *t == "build") {
+            let number = tokens.get(pos + 1).copied().unwrap_or_default();
+            build = Some(
+                number
+                    .parse::<u32>()
+                    .map_err(|_| OsParseError::InvalidBuild(number.into()))?,
+            );
+            tokens.drain(pos..(pos + 2).min(tokens.len()));
+        }
+        // Only an architecture or build number is no operating system either.
+        if tokens.is_empty() {
+            return Err(OsParseError::Empty);
+        }
+
+        // Find the family and split the tokens into what comes before (a Linux
+        // distribution and its release) and after (version and edition). A
+        // well-known distribution without "Linux" is still Linux, but lacks
+        // the kernel version.
+        let (pos, family, glued) = tokens
+            .iter()
+            .enumerate()
+            .find_map(|(pos, t)| split_family(t).map(|(family, rest)| (pos, family, rest)))
+            .or_else(|| {
+                LinuxDistribution::known(tokens[0]).map(|_| (tokens.len(), OsFamily::Linux, ""))
+            })
+            .ok_or_else(|| OsParseError::UnknownFamily(s.trim().into()))?;
+        let distribution = |name: &str| {
+            LinuxDistribution::known(name).unwrap_or(LinuxDistribution::Other(name.to_string()))
+        };
+        let (distribution, release) = match (family, &tokens[..pos]) {
+            (_, []) => (None, None),
+            (OsFamily::Linux, [name]) => (Some(distribution(name)), None),
+            (OsFamily::Linux, [name, release]) => {
+                (Some(distribution(name)), Some(release.to_string()))
+            }
+            (OsFamily::Linux, [_, _, unexpected, ..]) | (_, [unexpected, ..]) => {
+                return Err(OsParseError::Unexpected(unexpected.to_string()))
+            }
+        };
+        let mut rest = tokens.get(pos + 1..).unwrap_or_default().iter().copied();
+        let version = match glued {
+            "" => rest.next().ok_or(OsParseError::MissingVersion { family })?,
+            glued => glued,
+        };
+
+        let mut os = match family {
+            OsFamily::Windows => parse_windows(version, &mut rest)?,
+            OsFamily::MacOs => Self {
+                kind: OsKind::MacOs,
+                version: parse_version(version)?,
+                build: None,
+                arch: None,
+            },
+            OsFamily::Linux => Self {
+                kind: OsKind::Linux {
+                    distribution,
+                    release,
+                },
+                version: parse_version(version)?,
+                build: None,
+                arch: None,
+            },
+        };
+        if let Some(unexpected) = rest.next() {
+            return Err(OsParseError::Unexpected(unexpected.into()));
+        }
+        os.build = build.or(os.build);
+        os.arch = arch;
+        Ok(os)
+    }
+}
+
+// Split a token like "win7" or "linux-5.15" into the family and what follows.
+fn split_family(token: &str) -> Option<(OsFamily, &str)> {
+    [
+        ("windows", OsFamily::Windows),
+        ("win", OsFamily::Windows),
+        ("macos", OsFamily::MacOs),
+        ("osx", OsFamily::MacOs),
+        ("linux", OsFamily::Linux),
+    ]
+    .into_iter()
+    .find_map(|(prefix, family)| {
+        let rest = token.strip_prefix(prefix)?.trim_start_matches(['-', '_']);
+        // "winamp" is not Windows.
+        let separated = rest.is_empty()
+            || rest.starts_with(|c: char| c.is_ascii_digit())
+            || rest == "xp"
+            || rest == "vista";
+        separated.then_some((family, rest))
+    })
+}
+
+fn parse_windows<'a>(
+    version: &str,
+    rest: &mut impl Iterator<Item = &'a str>,
+) -> Result<OperatingSystem, OsParseError> {
+    let mut os = match WindowsRelease::ALL
+        .into_iter()
+        .find(|r| r.name().to_lowercase() == version)
+    {
+        Some(release) => OperatingSystem::windows(release),
+        None if version == "nt" => {
+            let version = rest.next().ok_or(OsParseError::MissingVersion {
+                family: OsFamily::Windows,
+            })?;
+            OperatingSystem {
+                kind: OsKind::Windows { edition: None },
+                version: parse_version(version)?,
+                build: None,
+                arch: None,
+            }
+        }
+        None => return Err(OsParseError::UnknownWindowsRelease(version.into())),
+    };
+    let mut rest = rest.peekable();
+    let edition = match rest.peek().copied() {
+        Some("home") => Some(WindowsEdition::Home),
+        Some("pro") | Some("professional") => Some(WindowsEdition::Pro),
+        Some("enterprise") => Some(WindowsEdition::Enterprise),
+        Some("education") => Some(WindowsEdition::Education),
+        _ => None,
+    };
+    if let Some(edition) = edition {
+        rest.next();
+        os = os.with_edition(edition);
+    }
+    if let Some(unexpected) = rest.next() {
+        return Err(OsParseError::Unexpected(unexpected.into()));
+    }
+    Ok(os)
+}
+
+// Parse `major[.minor[.patch]]`, optionally followed by a suffix starting with
+// `-` or `+`.
+fn parse_version(s: &str) -> Result<OsVersion, OsParseError> {
+    let version = s.split(['-', '+']).next().unwrap_or_default();
+    let invalid = || OsParseError::InvalidVersion {
+        version: version.into(),
+    };
+    let parts = version
+        .split('.')
+        .map(|part| part.parse::<u16>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [major] => Ok(OsVersion::new(major, 0, 0)),
+        [major, minor] => Ok(OsVersion::new(major, minor, 0)),
+        [major, minor, patch] => Ok(OsVersion::new(major, minor, patch)),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum OsParseError {
+    #[error("No operating system given.")]
+    Empty,
+    #[error("Unknown operating system: {0:?}")]
+    UnknownFamily(String),
+    #[error("Unknown Windows release: {0:?}")]
+    UnknownWindowsRelease(String),
+    #[error("The {family:?} version is missing.")]
+    MissingVersion { family: OsFamily },
+    #[error("Invalid version {version:?}, expected major[.minor[.patch]].")]
+    InvalidVersion { version: String },
+    #[error("Invalid build number: {0:?}")]
+    InvalidBuild(String),
+    #[error("Unexpected {0:?} in operating system.")]
+    Unexpected(String),
+}
+
+impl TryFrom<String> for OperatingSystem {
+    type Error = OsParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<OperatingSystem> for String {
+    fn from(value: OperatingSystem) -> Self {
+        value.to_string()
+    }
+}
+
+/// The operating system model from before distributions, patch levels, builds
+/// and architectures were introduced. Existing code can keep using the old
+/// variants and convert them with [OperatingSystem::from] until it is migrated
+/// to the constructors of [OperatingSystem].
+#[deprecated(note = "use the constructors of OperatingSystem instead")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LegacyOperatingSystem {
+    WindowsXp,
+    WindowsVista,
+    Windows7,
+    Windows11,
+    MacOs { major: u16, minor: u16 },
+    Linux { major: u16, minor: u16 },
+}
+
+#[allow(deprecated)]
+impl From<LegacyOperatingSystem> for OperatingSystem {
+    fn from(value: LegacyOperatingSystem) -> Self {
+        use LegacyOperatingSystem::*;
+        match value {
+            WindowsXp => Self::windows(WindowsRelease::Xp),
+            WindowsVista => Self::windows(WindowsRelease::Vista),
+            Windows7 => Self::windows(WindowsRelease::Win7),
+            Windows11 => Self::windows(WindowsRelease::Win11),
+            MacOs { major, minor } => Self::macos(major, minor),
+            Linux { major, minor } => Self::linux(major, minor),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<OperatingSystem, OsParseError> {
+        s.parse()
+    }
+
+    #[test]
+    fn test_parse_operating_system() {
+        use WindowsRelease::*;
+        for (s, os) in [
+            ("Windows 11", OperatingSystem::windows(Win11)),
+            ("win7", OperatingSystem::windows(Win7)),
+            ("Windows XP", OperatingSystem::windows(Xp)),
+            ("windows-vista", OperatingSystem::windows(Vista)),
+            ("macOS 10.14", OperatingSystem::macos(10, 14)),
+            (
+                "Mac OS X 10.14.6",
+                OperatingSystem::macos(10, 14).with_patch(6),
+            ),
+            ("Linux 6.22", OperatingSystem::linux(6, 22)),
+            ("linux-5.15.0-91-generic", OperatingSystem::linux(5, 15)),
+            ("linux 6", OperatingSystem::linux(6, 0)),
+            (
+                "Windows 11 Pro (build 22631) x64",
+                OperatingSystem::windows(Win11)
+                    .with_edition(WindowsEdition::Pro)
+                    .with_build(22631)
+                    .with_arch(CpuArch::X86_64),
+            ),
+            (
+                "Ubuntu Linux 6.5.3 aarch64",
+                OperatingSystem::linux(6, 5)
+                    .with_patch(3)
+                    .with_distribution(LinuxDistribution::Ubuntu)
+                    .with_arch(CpuArch::Arm64),
+            ),
+            (
+                "fedora linux 6.8",
+                OperatingSystem::linux(6, 8).with_distribution(LinuxDistribution::Fedora),
+            ),
+            (
+                "Ubuntu 22.04 Linux 6.5",
+                OperatingSystem::linux(6, 5)
+                    .with_distribution(LinuxDistribution::Ubuntu)
+                    .with_release("22.04"),
+            ),
+        ] {
+            assert_eq!(parse(s), Ok(os), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse(""), Err(OsParseError::Empty));
+        assert_eq!(parse("x64"), Err(OsParseError::Empty));
+        assert_eq!(parse("build 5"), Err(OsParseError::Empty));
+        assert_eq!(parse("(build 22631) arm64"), Err(OsParseError::Empty));
+        assert_eq!(
+            parse("Linux x86_64"),
+            Err(OsParseError::MissingVersion {
+                family: OsFamily::Linux
+            })
+        );
+        assert!(matches!(
+            parse("BeOS 5"),
+            Err(OsParseError::UnknownFamily(_))
+        ));
+        assert_eq!(
+            parse("Windows 9"),
+            Err(OsParseError::UnknownWindowsRelease("9".into()))
+        );
+        assert_eq!(
+            parse("Linux"),
+            Err(OsParseError::MissingVersion {
+                family: OsFamily::Linux
+            })
+        );
+        // The release of a distribution is not the kernel version.
+        assert_eq!(
+            parse("fedora 39"),
+            Err(OsParseError::MissingVersion {
+                family: OsFamily::Linux
+            })
+        );
+        assert_eq!(
+            parse("Debian 12 extra Linux 6.1"),
+            Err(OsParseError::Unexpected("extra".into()))
+        );
+        assert!(matches!(
+            parse("macOS 10.x"),
+            Err(OsParseError::InvalidVersion { .. })
+        ));
+        assert_eq!(
+            parse("Windows 11 build x"),
+            Err(OsParseError::InvalidBuild("x".into()))
+        );
+        assert_eq!(
+            parse("Windows 11 Ultimate"),
+            Err(OsParseError::Unexpected("ultimate".into()))
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        use WindowsRelease::*;
+        for os in [
+            OperatingSystem::windows(Xp),
+            OperatingSystem::windows(Win81),
+            OperatingSystem::windows(Win10).with_build(19045),
+            OperatingSystem::windows(Win11).with_edition(WindowsEdition::Enterprise),
+            OperatingSystem::windows(Win7)
+                .with_patch(1)
+                .with_build(7601),
+            OperatingSystem::macos(10, 14).with_arch(CpuArch::X86_64),
+            OperatingSystem::linux(6, 22).with_patch(1),
+            OperatingSystem::linux(6, 1).with_distribution(LinuxDistribution::Other("mint".into())),
+            OperatingSystem::linux(6, 1)
+                .with_distribution(LinuxDistribution::Debian)
+                .with_release("bookworm"),
+        ] {
+            assert_eq!(parse(&os.to_string()), Ok(os.clone()), "{os}");
+        }
+        assert_eq!(
+            OperatingSystem::windows(Win11)
+                .with_edition(WindowsEdition::Pro)
+                .with_build(22631)
+                .to_string(),
+            "Windows 11 Pro (build 22631)"
+        );
+    }
+
+    #[test]
+    fn test_ordering() {
+        use WindowsRelease::*;
+        assert!(OperatingSystem::windows(Vista) < OperatingSystem::windows(Win7));
+        assert!(OperatingSystem::windows(Win10) < OperatingSystem::windows(Win11));
+        assert!(OperatingSystem::linux(5, 15) < OperatingSystem::linux(6, 1));
+        assert!(OperatingSystem::linux(6, 1) < OperatingSystem::linux(6, 1).with_patch(2));
+        // Families are not comparable.
+        assert_eq!(
+            OperatingSystem::windows(Win11).partial_cmp(&OperatingSystem::linux(6, 1)),
+            None
+        );
+        // Neither are different distributions of the same release, unless
+        // explicitly asked for.
+        let ubuntu = OperatingSystem::linux(6, 1).with_distribution(LinuxDistribution::Ubuntu);
+        assert_eq!(ubuntu.partial_cmp(&OperatingSystem::linux(6, 1)), None);
+        assert_eq!(
+            ubuntu.cmp_release(&OperatingSystem::linux(6, 1)),
+            Some(Ordering::Equal)
+        );
+    }
+
+    #[test]
+    fn test_windows_release() {
+        assert_eq!(
+            OperatingSystem::windows(WindowsRelease::Win10)
+                .with_build(22631)
+                .windows_release(),
+            Some(WindowsRelease::Win11)
+        );
+        assert_eq!(OperatingSystem::linux(6, 1).windows_release(), None);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_legacy_conversion() {
+        assert_eq!(
+            OperatingSystem::from(LegacyOperatingSystem::Windows11),
+            OperatingSystem::windows(WindowsRelease::Win11)
+        );
+        assert_eq!(
+            OperatingSystem::from(LegacyOperatingSystem::Linux {
+                major: 6,
+                minor: 22
+            }),
+            OperatingSystem::linux(6, 22)
+        );
+    }
+}
@@ -1,8 +1,20 @@
-use std::collections::HashSet;
+use std::{fmt, str::FromStr};
 
-use phantom_newtype::Amount;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::person::Person;
+use crate::{
+    chargeback::Depreciation,
+    person::{ChfAmout, Person},
+    profiles::Profiles,
+};
+
+// The operating system, the CPU flags and the sizes used to be defined in this
+// module.
+pub use crate::bytes::{NumBytes, GIBIBYTE, MEBIBYTE, TEBIBYTE};
+pub use crate::cpu::{CpuFlag, CpuFlags};
+pub use crate::os::OperatingSystem;
 
 
 #[derive(Default, Debug, Clone)]
@@ -15,95 +27,346 @@ pub struct PcBuilder {
 impl PcBuilder {
     // Let's assume we don't want to set assume global defaults for the
     // individual types, we set the defaults for the fields here in the
-    // builder.
+    // builder. Which defaults apply depends on the owner and is configured in
+    // the profiles.
     pub fn fill_defaults(&mut self) {
+        self.fill_defaults_from(Profiles::builtin());
+    }
+
+    pub fn fill_defaults_from(&mut self, profiles: &Profiles) {
+        let profile = profiles.default_for(self.owner.as_ref());
         if self.hardware.is_none() {
-            self.hardware = Some(PcHardware::beefy_workstation());
+            self.hardware = Some(profile.hardware.build());
         }
         if self.os.is_none() {
-            self.os = Some(OperatingSystem::Linux { major: 5, minor: 5 });
+            self.os = Some(profile.os.clone());
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// The hardware of a PC, as far as asset tracking is concerned.
+// The inventory fields were added later; they default to empty when loading
+// older directories.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PcHardware {
-    pub flags: HashSet<CpuFlag>,
+    #[serde(default)]
+    pub cpu: Option<Cpu>,
+    pub flags: CpuFlags,
     pub ram: NumBytes,
+    #[serde(default)]
+    pub disks: Vec<Disk>,
+    #[serde(default)]
+    pub gpus: Vec<Gpu>,
+    #[serde(default)]
+    pub nics: Vec<NetworkInterface>,
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    #[serde(default)]
+    pub purchase_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub purchase_price: Option<ChfAmout>,
+    /// How the purchase price is spread over the years. Unset means the
+    /// default [Depreciation].
+    #[serde(default)]
+    pub depreciation: Option<Depreciation>,
 }
 
+// The presets describe models of PCs. What differs from unit to unit, like
+// serial numbers and MAC addresses, is set with the `with_*` methods.
 impl PcHardware {
+    pub fn new(flags: CpuFlags, ram: NumBytes) -> Self {
+        Self {
+            cpu: None,
+            flags,
+            ram,
+            disks: vec![],
+            gpus: vec![],
+            nics: vec![],
+            serial_number: None,
+            purchase_date: None,
+            purchase_price: None,
+            depreciation: None,
+        }
+    }
+
     pub fn nerd_workstation() -> Self {
         use CpuFlag::*;
         Self {
-            flags: [MMX, SSE, SEV, AVX].into_iter().collect(),
-            ram: NumBytes::new(GIBIBYTE.get() * 64),
+            cpu: Some(Cpu::new("AMD Ryzen Threadripper PRO 5975WX", 32)),
+            disks: vec![
+                Disk::new(DiskKind::Nvme, "Samsung 990 PRO", TEBIBYTE * 2),
+                Disk::new(DiskKind::Hdd, "WD Red Plus", TEBIBYTE * 8),
+            ],
+            gpus: vec![Gpu::new("NVIDIA RTX A5000", Some(GIBIBYTE * 24))],
+            ..Self::new(
+                [MMX, SSE, SEV, AVX].into_iter().collect(),
+                NumBytes::new(GIBIBYTE.get() * 64),
+            )
         }
     }
 
     pub fn beefy_workstation() -> Self {
         use CpuFlag::*;
         Self {
-            flags: [MMX, SSE, SEV].into_iter().collect(),
-            ram: NumBytes::new(GIBIBYTE.get() * 32),
+            cpu: Some(Cpu::new("AMD Ryzen 9 7950X", 16)),
+            disks: vec![Disk::new(DiskKind::Nvme, "Samsung 980 PRO", TEBIBYTE)],
+            gpus: vec![Gpu::new("AMD Radeon RX 7800 XT", Some(GIBIBYTE * 16))],
+            ..Self::new(
+                [MMX, SSE, SEV].into_iter().collect(),
+                NumBytes::new(GIBIBYTE.get() * 32),
+            )
         }
     }
 
     pub fn normal() -> Self {
         use CpuFlag::*;
         Self {
-            flags: [MMX, SSE].into_iter().collect(),
-            ram: NumBytes::new(GIBIBYTE.get() * 16),
+            cpu: Some(Cpu::new("Intel Core i5-8500", 6)),
+            disks: vec![Disk::new(DiskKind::Ssd, "Crucial MX500", GIBIBYTE * 512)],
+            // Integrated graphics share the RAM.
+            gpus: vec![Gpu::new("Intel UHD Graphics 630", None)],
+            ..Self::new(
+                [MMX, SSE].into_iter().collect(),
+                NumBytes::new(GIBIBYTE.get() * 16),
+            )
+        }
+    }
+
+    pub fn with_serial_number<T: ToString>(self, serial_number: T) -> Self {
+        Self {
+            serial_number: Some(serial_number.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_purchase_date(self, purchase_date: NaiveDate) -> Self {
+        Self {
+            purchase_date: Some(purchase_date),
+            ..self
+        }
+    }
+
+    pub fn with_purchase_price(self, purchase_price: ChfAmout) -> Self {
+        Self {
+            purchase_price: Some(purchase_price),
+            ..self
+        }
+    }
+
+    pub fn with_depreciation(self, depreciation: Depreciation) -> Self {
+        Self {
+            depreciation: Some(depreciation),
+            ..self
         }
     }
+
+    pub fn with_nic<T: ToString>(mut self, name: T, mac: MacAddr) -> Self {
+        self.nics.push(NetworkInterface {
+            name: name.to_string(),
+            mac,
+        });
+        self
+    }
+
+    /// The capacity of all disks together.
+    pub fn storage(&self) -> NumBytes {
+        self.disks.iter().map(|d| d.capacity).sum()
+    }
+
+    pub fn has_mac(&self, mac: &MacAddr) -> bool {
+        self.nics.iter().any(|nic| &nic.mac == mac)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum CpuFlag {
-    MMX,
-    SSE,
-    SEV,
-    AVX,
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cpu {
+    pub model: String,
+    pub cores: u16,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub enum OperatingSystem {
-    WindowsXp,
-    WindowsVista,
-    Windows7,
-    Windows11,
-    MacOs { major: u16, minor: u16 },
-    Linux { major: u16, minor: u16 },
+impl Cpu {
+    pub fn new<T: ToString>(model: T, cores: u16) -> Self {
+        Self {
+            model: model.to_string(),
+            cores,
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiskKind {
+    Hdd,
+    Ssd,
+    Nvme,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Disk {
+    pub kind: DiskKind,
+    pub model: String,
+    pub capacity: NumBytes,
+}
 
-impl OperatingSystem {
-    // ;-)
-    pub fn is_crappy(&self) -> bool {
-        match self {
-            Self::WindowsXp | Self::WindowsVista => true,
-            Self::Linux { major, minor: _ } if *major < 5 => true,
-            _ => false,
+impl Disk {
+    pub fn new<T: ToString>(kind: DiskKind, model: T, capacity: NumBytes) -> Self {
+        Self {
+            kind,
+            model: model.to_string(),
+            capacity,
         }
     }
+}
 
-    pub fn is_windows(&self) -> bool {
-        matches!(
-            self,
-            Self::WindowsXp | Self::WindowsVista | Self::Windows7 | Self::Windows11
-        )
-        /* ... as a tip from clippy revealed, the above is the same as:
-        match self {
-            Self::WindowsXp | Self::WindowsVista | Self::Windows7 | Self::Windows11 => true,
-            _ => false,
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gpu {
+    pub model: String,
+    /// Dedicated video memory, if any.
+    pub memory: Option<NumBytes>,
+}
+
+impl Gpu {
+    pub fn new<T: ToString>(model: T, memory: Option<NumBytes>) -> Self {
+        Self {
+            model: model.to_string(),
+            memory,
         }
-        */
     }
 }
 
-// This is a marker type that can and should not be instantiated.
-pub enum Bytes {}
-type NumBytes = Amount<Bytes, u64>;
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub mac: MacAddr,
+}
+
+/// A MAC address like "00:1a:2b:3c:4d:5e".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MacAddr(pub [u8; 6]);
 
-pub const MEBIBYTE: NumBytes = NumBytes::new(1u64 << 20);
-pub const GIBIBYTE: NumBytes = NumBytes::new(1u64 << 30);
\ No newline at end of file
+#[derive(Debug, Error)]
+#[error("Invalid MAC address: {0}")]
+pub struct MacParseError(String);
+
+/// Both ':' and '-' are accepted as separators.
+impl FromStr for MacAddr {
+    type Err = MacParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || MacParseError(s.into());
+        let mut octets = [0; 6];
+        let mut parts = s.split([':', '-']);
+        for octet in octets.iter_mut() {
+            // `from_str_radix` would accept a sign like in "+a".
+            let part = parts
+                .next()
+                .filter(|p| p.len() == 2 && p.bytes().all(|b| b.is_ascii_hexdigit()))
+                .ok_or_else(err)?;
+            *octet = u8::from_str_radix(part, 16).map_err(|_| err())?;
+        }
+        match parts.next() {
+            Some(_) => Err(err()),
+            None => Ok(Self(octets)),
+        }
+    }
+}
+
+impl TryFrom<String> for MacAddr {
+    type Error = MacParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<MacAddr> for String {
+    fn from(value: MacAddr) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl fmt::Display for Cpu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} cores)", self.model, self.cores)
+    }
+}
+
+impl fmt::Display for DiskKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Hdd => "HDD",
+            Self::Ssd => "SSD",
+            Self::Nvme => "NVMe",
+        })
+    }
+}
+
+impl fmt::Display for Disk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.capacity, self.kind, self.model)
+    }
+}
+
+impl fmt::Display for Gpu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.memory {
+            Some(memory) => write!(f, "{} ({})", self.model, memory),
+            None => f.write_str(&self.model),
+        }
+    }
+}
+
+impl fmt::Display for NetworkInterface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.mac)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mac_addr() {
+        let mac: MacAddr = "00:1A:2b:3c:4d:5e".parse().unwrap();
+        assert_eq!(mac, MacAddr([0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]));
+        assert_eq!(mac.to_string(), "00:1a:2b:3c:4d:5e");
+        assert_eq!("00-1a-2b-3c-4d-5e".parse::<MacAddr>().unwrap(), mac);
+        assert!("00:1a:2b:3c:4d".parse::<MacAddr>().is_err());
+        assert!("00:1a:2b:3c:4d:5e:6f".parse::<MacAddr>().is_err());
+        assert!("00:1a:2b:3c:4d:xy".parse::<MacAddr>().is_err());
+        assert!("00:1a:2b:3c:4d:+a".parse::<MacAddr>().is_err());
+        assert!("00:1a:2b:3c:4d:-a".parse::<MacAddr>().is_err());
+        assert!("00:1a:2b:3c:4d:5".parse::<MacAddr>().is_err());
+    }
+
+    #[test]
+    fn test_inventory() {
+        let mac = "00:1a:2b:3c:4d:5e".parse().unwrap();
+        let hw = PcHardware::nerd_workstation()
+            .with_serial_number("ITC-0042")
+            .with_nic("eth0", mac);
+        assert_eq!(hw.storage(), TEBIBYTE * 10);
+        assert!(hw.has_mac(&mac));
+
+        let json = serde_json::to_string(&hw).unwrap();
+        let loaded: PcHardware = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.serial_number.as_deref(), Some("ITC-0042"));
+        assert_eq!(loaded.nics, hw.nics);
+        assert_eq!(loaded.disks, hw.disks);
+    }
+
+    #[test]
+    fn test_load_hardware_without_inventory() {
+        let hw: PcHardware = serde_json::from_str(r#"{"flags":["MMX"],"ram":1024}"#).unwrap();
+        assert!(hw.cpu.is_none() && hw.disks.is_empty() && hw.nics.is_empty());
+        assert_eq!(hw.ram, NumBytes::new(1024));
+    }
+}
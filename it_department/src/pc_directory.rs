@@ -1,11 +1,46 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    rc::Rc,
+};
 
-use crate::{pc::{OperatingSystem, PcBuilder, PcHardware}, person::{Affiliation, ChfAmout, EmailAddr, Person, PersonBuilder}};
+use chrono::NaiveDate;
+
+use crate::{access::Permission, compatibility::{CompatibilityMatrix, Incompatibility}, location::{Location, Move}, org::OrgChart, os::WindowsRelease, pc::{OperatingSystem, PcBuilder, PcHardware}, person::{Affiliation, ChfAmout, EmailAddr, Person, PersonBuilder, PersonId}, profiles::Profiles, software::{self, Package, Version}};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Default)]
 pub struct PcDirectory {
     directory: Vec<PcDirectoryEntry>,
+    /// Whether owners are notified when maintenance on their PCs starts and
+    /// ends. This is opt-in; see [PcDirectory::set_maintenance_notifications].
+    notify_maintenance: Cell<bool>,
+    /// Emails that could not be delivered because none of the recipient's PCs
+    /// was turned on at the time.
+    deferred_mail: RefCell<VecDeque<DeferredEmail>>,
+    /// Where the defaults for new PCs come from.
+    profiles: Profiles,
+    /// Everyone who owns or owned a PC. All PCs of an owner share the same
+    /// [Person], so it only has to be changed here and on the PCs.
+    owners: BTreeMap<PersonId, Rc<Person>>,
+    /// The teams and managers of the owners.
+    org: OrgChart,
+    /// Where the PCs were moved, oldest first.
+    moves: Vec<Move>,
+    /// When owners were last reminded that their engagement ends, see
+    /// [crate::engagement::EngagementSweep].
+    reminders: BTreeMap<PersonId, NaiveDate>,
+}
+
+/// An email waiting for one of the recipient's PCs to become available.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct DeferredEmail {
+    pub to: EmailAddr,
+    pub message: String,
 }
 
 impl PcDirectory {
@@ -13,44 +48,374 @@ impl PcDirectory {
         self.directory.iter()
     }
 
-    /// Add a new PC to the directory.
+    /// Look up the PC with the given id.
+    pub fn get_pc(&self, id: usize) -> Option<&PcDirectoryEntry> {
+        self.directory.get(id)
+    }
+
+    /// Look up the person with the given id.
+    pub fn person(&self, id: PersonId) -> Option<&Rc<Person>> {
+        self.owners.get(&id)
+    }
+
+    /// Look up the person who uses or used the given email address.
+    pub fn find_person(&self, email: &EmailAddr) -> Option<(PersonId, &Rc<Person>)> {
+        self.owners
+            .iter()
+            .find(|(_, p)| p.has_email(email))
+            .map(|(&id, p)| (id, p))
+    }
+
+    /// Change the person with the given id, e.g. after a marriage or a domain
+    /// migration. All PCs of the person see the change. If the email address
+    /// changes, the old one is kept as a previous address, so mail sent to it
+    /// is still delivered.
     ///
     /// # Returns
-    pub fn add_pc(&mut self, mut pcb: PcBuilder) -> Result<(), PcDirectoryError> {
-        pcb.fill_defaults();
-
-        let new_entry = if let Some(pivot_email) = pcb.owner.as_ref().map(|p| &p.email) {
-            // In a real world scenario, we would of course store email addresses in
-            // some lookup-table to quickly find entries containing that email
-            // address.
-            if let Some(entry) = self.iter_pcs().find(|e| {
-                e.owner
-                    .as_ref()
-                    .map(|p| &p.email == pivot_email)
-                    .unwrap_or_default()
-            }) {
-                // if the owner is not the same, return an error
-                if entry.owner.as_deref() != pcb.owner.as_ref() {
-                    return Err(PcDirectoryError::DuplicateEmailAddress {
-                        email: pivot_email.clone(),
-                    });
-                }
-                PcDirectoryEntry::new(self.directory.len(), pcb, entry.owner.clone())
-            } else {
-                let owner = pcb.owner.take().map(Rc::new);
-                PcDirectoryEntry::new(self.directory.len(), pcb, owner)
+    ///
+    /// An error if the person does not exist or if one of their addresses
+    /// belongs to someone else.
+    pub fn update_person<F: FnOnce(&mut Person)>(
+        &mut self,
+        id: PersonId,
+        update: F,
+    ) -> Result<(), PcDirectoryError> {
+        let old = self
+            .owners
+            .get(&id)
+            .ok_or(PcDirectoryError::PersonNotFound { id })?;
+        let mut person = Person::clone(old);
+        update(&mut person);
+        if !person.has_email(&old.email) {
+            person.previous_emails.push(old.email.clone());
+        }
+        let current = person.email.clone();
+        person.previous_emails.retain(|e| e != &current);
+
+        if let Some(email) = person
+            .emails()
+            .find(|e| self.find_person(e).is_some_and(|(other, _)| other != id))
+        {
+            return Err(PcDirectoryError::DuplicateEmailAddress {
+                email: email.clone(),
+            });
+        }
+        let person = Rc::new(person);
+        for pc in self.directory.iter_mut().filter(|pc| pc.owner_id == Some(id)) {
+            pc.owner = Some(person.clone());
+        }
+        self.owners.insert(id, person);
+        Ok(())
+    }
+
+    /// When the person with the given id was last reminded that their
+    /// engagement ends.
+    pub fn last_reminded(&self, id: PersonId) -> Option<NaiveDate> {
+        self.reminders.get(&id).copied()
+    }
+
+    pub(crate) fn set_reminded(&mut self, id: PersonId, date: NaiveDate) {
+        self.reminders.insert(id, date);
+    }
+
+    /// The departments, teams and managers of the owners.
+    pub fn org(&self) -> &OrgChart {
+        &self.org
+    }
+
+    pub fn org_mut(&mut self) -> &mut OrgChart {
+        &mut self.org
+    }
+
+    /// The PCs owned by any of `people`, e.g. the members of a team.
+    pub fn pcs_of<'a>(
+        &'a self,
+        people: &'a BTreeSet<PersonId>,
+    ) -> impl Iterator<Item = &'a PcDirectoryEntry> {
+        self.iter_pcs()
+            .filter(|pc| pc.owner_id.is_some_and(|id| people.contains(&id)))
+    }
+
+    /// Send `message` to each of `people` who owns a PC. Mail that cannot be
+    /// delivered right away is deferred.
+    ///
+    /// # Returns
+    ///
+    /// The people the message was sent to.
+    pub(crate) fn broadcast(&self, people: &BTreeSet<PersonId>, message: &str) -> Vec<PersonId> {
+        let recipients: BTreeSet<_> = self.pcs_of(people).filter_map(|pc| pc.owner_id).collect();
+        for id in &recipients {
+            self.deliver_or_defer(&self.owners[id].email, message.into());
+        }
+        recipients.into_iter().collect()
+    }
+
+    /// Move the PC with the given id to `to`, or take it away from where it is
+    /// if `to` is `None`. The move is recorded in the history.
+    pub fn move_pc(
+        &mut self,
+        id: usize,
+        to: Option<Location>,
+        date: NaiveDate,
+    ) -> Result<(), PcDirectoryError> {
+        let pc = self
+            .directory
+            .get_mut(id)
+            .ok_or(PcDirectoryError::PcNotFound { id })?;
+        if pc.location == to {
+            return Ok(());
+        }
+        let from = std::mem::replace(&mut pc.location, to.clone());
+        self.moves.push(Move { pc: id, date, from, to });
+        Ok(())
+    }
+
+    /// All moves, oldest first.
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// The PCs at `at` or anywhere within it.
+    pub fn pcs_at<'a>(&'a self, at: &'a Location) -> impl Iterator<Item = &'a PcDirectoryEntry> {
+        self.iter_pcs()
+            .filter(|pc| pc.location.as_ref().is_some_and(|l| at.contains(l)))
+    }
+
+    /// Use `profiles` for the defaults of PCs added from now on.
+    pub fn set_profiles(&mut self, profiles: Profiles) {
+        self.profiles = profiles;
+    }
+
+    /// Opt in (or out) of automatic maintenance notifications. If enabled,
+    /// maintenance locks acquired through the directory, e.g. with
+    /// [PcDirectory::acquire_maintenance_lock_as], inform the owner when the maintenance starts and summarize the changes
+    /// once the [MaintenanceHandle] is dropped.
+    pub fn set_maintenance_notifications(&self, enabled: bool) {
+        self.notify_maintenance.set(enabled);
+    }
+
+    /// Acquire a maintenance lock for the PC with the given id. Contrary to
+    /// [PcDirectoryEntry::acquire_maintenance_lock], the owner of the PC and
+    /// their manager are notified, if maintenance notifications are enabled.
+    pub(crate) fn acquire_maintenance_lock<S: ToString>(
+        &self,
+        id: usize,
+        reason: S,
+    ) -> Result<MaintenanceHandle<'_>, PcDirectoryError> {
+        let pc = self.get_pc(id).ok_or(PcDirectoryError::PcNotFound { id })?;
+        let reason = reason.to_string();
+        let mut handle = pc.acquire_maintenance_lock(&reason)?;
+        self.announce_maintenance(&mut handle, reason);
+        Ok(handle)
+    }
+
+    // Tell the owner of the PC and their manager that its maintenance has
+    // started, and prepare the summary sent when it ends, if maintenance
+    // notifications are enabled. Only call this once the lock is held.
+    pub(crate) fn announce_maintenance<'a>(&'a self, handle: &mut MaintenanceHandle<'a>, reason: String) {
+        let id = handle.id;
+        let pc = self.get_pc(id).expect("handles belong to PCs of the directory");
+        if !self.notify_maintenance.get() {
+            return;
+        }
+        if let Some(owner) = pc.owner.as_deref() {
+            self.deliver_or_defer(
+                &owner.email,
+                format!("Your PC {id} is being maintained: {reason}"),
+            );
+            let manager = pc.owner_id.and_then(|owner| self.org.manager_of(owner));
+            if let Some(manager) = manager.and_then(|m| self.owners.get(&m)) {
+                self.deliver_or_defer(
+                    &manager.email,
+                    format!(
+                        "PC {id} of {} {} is being maintained: {reason}",
+                        owner.first, owner.last
+                    ),
+                );
             }
-        } else {
-            PcDirectoryEntry::new(self.directory.len(), pcb, None)
+            let notifier = MaintenanceNotifier {
+                owner: owner.email.clone(),
+                owner_id: pc.owner_id,
+                owner_name: format!("{} {}", owner.first, owner.last),
+                id,
+                reason,
+                os_before: pc.state.borrow().os.clone(),
+                software_before: pc.state.borrow().software.clone(),
+            };
+            handle.notifier = Some((self, notifier));
+        }
+    }
+
+    /// Turn a lease of this directory back into a handle, e.g. to change the
+    /// PC or to end the maintenance by dropping the handle.
+    pub fn resume_maintenance(
+        &self,
+        lease: MaintenanceLease,
+    ) -> Result<MaintenanceHandle<'_>, PcDirectoryError> {
+        let pc = self.get_pc(lease.id).ok_or(PcDirectoryError::PcNotFound { id: lease.id })?;
+        Ok(MaintenanceHandle {
+            id: pc.id,
+            state: &pc.state,
+            hardware: &pc.hardware,
+            notifier: lease.notifier.map(|notifier| (self, notifier)),
+        })
+    }
+
+    /// The recipients of the emails that are waiting for delivery, one per
+    /// email. The messages themselves are only delivered to their mailboxes.
+    pub fn deferred_recipients(&self) -> Vec<EmailAddr> {
+        self.deferred_mail.borrow().iter().map(|mail| mail.to.clone()).collect()
+    }
+
+    /// Try to deliver all deferred emails. Emails that still cannot be
+    /// delivered remain in the queue.
+    pub fn flush_deferred_mail(&self) {
+        let pending = std::mem::take(&mut *self.deferred_mail.borrow_mut());
+        for mail in pending {
+            self.deliver_or_defer(&mail.to, mail.message);
+        }
+    }
+
+    // Deliver the message to the first available PC of the recipient, or queue
+    // it if none is available.
+    pub(crate) fn deliver_or_defer(&self, to: &EmailAddr, message: String) {
+        match self.send_email(to.clone(), &message) {
+            Ok(()) => (),
+            Err(_) => self.deferred_mail.borrow_mut().push_back(DeferredEmail {
+                to: to.clone(),
+                message,
+            }),
+        }
+    }
+
+    /// Turn the PC with the given id off. PCs that are being maintained cannot
+    /// be turned off.
+    pub(crate) fn power_off(&self, id: usize) -> Result<(), PcDirectoryError> {
+        let pc = self.get_pc(id).ok_or(PcDirectoryError::PcNotFound { id })?;
+        let mut state = pc.state.borrow_mut();
+        if let OperationalState::BeingMaintained { reason } = &state.maintenance {
+            return Err(PcDirectoryError::InMaintenance {
+                reason: reason.clone(),
+            });
+        }
+        state.maintenance = OperationalState::Off;
+        Ok(())
+    }
+
+    /// Take the PC with the given id back from its owner: it is turned off,
+    /// its mailbox is emptied and it no longer has an owner. The owner keeps
+    /// their [PersonId].
+    ///
+    /// # Returns
+    ///
+    /// The previous owner.
+    pub(crate) fn reclaim(&mut self, id: usize) -> Result<Option<Rc<Person>>, PcDirectoryError> {
+        self.power_off(id)?;
+        let pc = &mut self.directory[id];
+        pc.state.borrow().mailbox.borrow_mut().clear();
+        pc.owner_id = None;
+        Ok(pc.owner.take())
+    }
+
+    /// Give the PC with the given id to the person with id `to`, or to nobody.
+    /// Its mailbox is emptied, as the messages were meant for the previous
+    /// owner.
+    pub(crate) fn transfer_pc(&mut self, id: usize, to: Option<PersonId>) -> Result<(), PcDirectoryError> {
+        let owner = match to {
+            Some(to) => Some(
+                self.owners
+                    .get(&to)
+                    .cloned()
+                    .ok_or(PcDirectoryError::PersonNotFound { id: to })?,
+            ),
+            None => None,
         };
-        self.directory.push(new_entry);
+        let pc = self
+            .directory
+            .get_mut(id)
+            .ok_or(PcDirectoryError::PcNotFound { id })?;
+        pc.state.borrow().mailbox.borrow_mut().clear();
+        pc.owner = owner;
+        pc.owner_id = to;
         Ok(())
     }
 
+    /// Add a new PC to the directory.
+    ///
+    /// # Returns
+    ///
+    /// An error if the owner's email address is already used by someone else
+    /// or if the hardware cannot run the operating system.
+    pub(crate) fn add_pc(&mut self, mut pcb: PcBuilder) -> Result<(), PcDirectoryError> {
+        pcb.fill_defaults_from(&self.profiles);
+        CompatibilityMatrix::builtin().check(
+            pcb.os.as_ref().expect("set by fill_defaults"),
+            pcb.hardware.as_ref().expect("set by fill_defaults"),
+        )?;
+        self.insert_pc(pcb, None)
+    }
+
+    /// Create a directory from the given PCs, see [PcDirectory::add_pc_as].
+    pub fn from_pcs<I>(pcs: I) -> Result<Self, PcDirectoryError>
+    where
+        I: IntoIterator<Item = PcBuilder>,
+    {
+        let mut dir = PcDirectory::default();
+        for pcb in pcs {
+            dir.add_pc(pcb)?;
+        }
+        Ok(dir)
+    }
+
+    // Add a PC whose OS and hardware are set. If its owner is new, they get the
+    // id `owner_id` if it is still free. Whether the hardware can run the OS is
+    // up to the caller, so that restored PCs stay as they were saved even if
+    // the compatibility matrix changed since.
+    fn insert_pc(
+        &mut self,
+        mut pcb: PcBuilder,
+        owner_id: Option<PersonId>,
+    ) -> Result<(), PcDirectoryError> {
+        let owner = match pcb.owner.take() {
+            Some(person) => Some(self.register_owner(person, owner_id)?),
+            None => None,
+        };
+        self.directory
+            .push(PcDirectoryEntry::new(self.directory.len(), pcb, owner));
+        Ok(())
+    }
+
+    // Find the owner in the registry or register them. No two people may share
+    // an email address, previous addresses included.
+    fn register_owner(
+        &mut self,
+        person: Person,
+        preferred: Option<PersonId>,
+    ) -> Result<(PersonId, Rc<Person>), PcDirectoryError> {
+        // In a real world scenario, we would of course store email addresses in
+        // some lookup-table to quickly find the owner.
+        if let Some((&id, known)) = self.owners.iter().find(|(_, p)| ***p == person) {
+            return Ok((id, known.clone()));
+        }
+        if let Some(email) = person.emails().find(|e| self.find_person(e).is_some()) {
+            return Err(PcDirectoryError::DuplicateEmailAddress {
+                email: email.clone(),
+            });
+        }
+        let id = match preferred {
+            Some(id) if !self.owners.contains_key(&id) => id,
+            _ => PersonId(self.owners.keys().next_back().map_or(0, |id| id.0 + 1)),
+        };
+        let person = Rc::new(person);
+        self.owners.insert(id, person.clone());
+        Ok((id, person))
+    }
+
     /// Send an email to the person with address [`to`]. The email will be put
     /// into mailbox of the first PC that is turned on and belongs to the person
-    /// with the given email address.
-    pub fn send_email<E: TryInto<EmailAddr>, T: ToString>(
+    /// with the given email address. Previous addresses of the person work as
+    /// well.
+    pub(crate) fn send_email<E: TryInto<EmailAddr>, T: ToString>(
         &self,
         to: E,
         message: T,
@@ -64,7 +429,7 @@ impl PcDirectory {
             .filter(|pc| {
                 pc.owner
                     .as_deref()
-                    .map(|p| p.email == to)
+                    .map(|p| p.has_email(&to))
                     .unwrap_or_default()
             })
             .collect();
@@ -83,14 +448,145 @@ impl PcDirectory {
     }
 }
 
-impl<T> From<T> for PcDirectory
-where
-    T: IntoIterator<Item = PcBuilder>,
-{
-    fn from(iter: T) -> Self {
+// The on-disk representation of a directory. Owners are stored with every PC
+// and deduplicated again when loading. Everyone who ever owned a PC is stored
+// in `people` as well, so their ids are never given to someone else.
+#[derive(Serialize, Deserialize)]
+struct DirectorySnapshot {
+    // Older files have no people; their owners without PCs are lost.
+    #[serde(default)]
+    people: Vec<PersonRecord>,
+    pcs: Vec<PcRecord>,
+    deferred_mail: VecDeque<DeferredEmail>,
+    #[serde(default)]
+    org: OrgChart,
+    #[serde(default)]
+    moves: Vec<Move>,
+    #[serde(default)]
+    reminders: BTreeMap<PersonId, NaiveDate>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersonRecord {
+    id: PersonId,
+    person: Person,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PcRecord {
+    owner: Option<Person>,
+    // Older files have no ids; their owners are numbered when loading.
+    #[serde(default)]
+    owner_id: Option<PersonId>,
+    #[serde(default)]
+    location: Option<Location>,
+    #[serde(default)]
+    software: Vec<Package>,
+    hardware: PcHardware,
+    os: OperatingSystem,
+    state: OperationalState,
+    mailbox: Vec<String>,
+}
+
+impl PcDirectory {
+    /// Write the directory to a JSON file at `path`.
+    ///
+    /// Maintenance locks only live as long as the process holding them, so
+    /// PCs that are being maintained are stored as being on.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistenceError> {
+        let snapshot = DirectorySnapshot {
+            people: self
+                .owners
+                .iter()
+                .map(|(&id, person)| PersonRecord {
+                    id,
+                    person: Person::clone(person),
+                })
+                .collect(),
+            pcs: self
+                .iter_pcs()
+                .map(|pc| {
+                    let state = pc.state.borrow();
+                    let mailbox = state.mailbox.borrow().clone();
+                    PcRecord {
+                        owner: pc.owner.as_deref().cloned(),
+                        owner_id: pc.owner_id,
+                        location: pc.location.clone(),
+                        software: state.software.values().cloned().collect(),
+                        hardware: pc.hardware.clone(),
+                        os: state.os.clone(),
+                        state: match &state.maintenance {
+                            OperationalState::Off => OperationalState::Off,
+                            _ => OperationalState::On,
+                        },
+                        mailbox,
+                    }
+                })
+                .collect(),
+            deferred_mail: self.deferred_mail.borrow().clone(),
+            org: self.org.clone(),
+            moves: self.moves.clone(),
+            reminders: self.reminders.clone(),
+        };
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, &snapshot)?;
+        Ok(())
+    }
+
+    /// Read a directory previously written by [PcDirectory::save].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PersistenceError> {
+        let reader = BufReader::new(File::open(path)?);
+        let snapshot: DirectorySnapshot = serde_json::from_reader(reader)?;
+
         let mut dir = PcDirectory::default();
-        iter.into_iter().for_each(|pcb| dir.add_pc(pcb).unwrap());
-        dir
+        for record in snapshot.people {
+            dir.register_owner(record.person, Some(record.id))?;
+        }
+        for record in snapshot.pcs {
+            let pc = PcBuilder {
+                hardware: Some(record.hardware),
+                os: Some(record.os),
+                owner: record.owner,
+            };
+            dir.insert_pc(pc, record.owner_id)?;
+            let pc = dir.directory.last_mut().expect("PC was just added");
+            pc.location = record.location;
+            let mut state = pc.state.borrow_mut();
+            state.mailbox.replace(record.mailbox);
+            state.software = record.software.into_iter().map(|p| (p.name.clone(), p)).collect();
+            state.maintenance = record.state;
+        }
+        dir.deferred_mail.replace(snapshot.deferred_mail);
+        dir.org = snapshot.org;
+        dir.moves = snapshot.moves;
+        dir.reminders = snapshot.reminders;
+        Ok(dir)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PersistenceError {
+    #[error("Could not access the directory file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The directory file is malformed: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("The directory file is inconsistent: {0}")]
+    Directory(#[from] PcDirectoryError),
+}
+
+impl<const N: usize> TryFrom<[PcBuilder; N]> for PcDirectory {
+    type Error = PcDirectoryError;
+
+    fn try_from(pcs: [PcBuilder; N]) -> Result<Self, Self::Error> {
+        Self::from_pcs(pcs)
+    }
+}
+
+impl TryFrom<Vec<PcBuilder>> for PcDirectory {
+    type Error = PcDirectoryError;
+
+    fn try_from(pcs: Vec<PcBuilder>) -> Result<Self, Self::Error> {
+        Self::from_pcs(pcs)
     }
 }
 
@@ -106,30 +602,54 @@ pub enum PcDirectoryError {
     InMaintenance { reason: String },
     #[error("The provided email address is invalid.")]
     InvalidEMailAddress,
+    #[error("There is no PC with id {id}.")]
+    PcNotFound { id: usize },
+    #[error("There is no person with id {id}.")]
+    PersonNotFound { id: PersonId },
+    #[error(
+        "{} is not allowed to {permission}{}.",
+        caller.as_ref(),
+        pc.map_or(String::new(), |id| format!(" PC {id}"))
+    )]
+    PermissionDenied {
+        caller: EmailAddr,
+        permission: Permission,
+        pc: Option<usize>,
+    },
+    #[error("{name} is not installed on the PC.")]
+    NotInstalled { name: String },
+    #[error("The hardware is not compatible: {0}")]
+    Incompatible(#[from] Incompatibility),
 }
 
 pub struct PcDirectoryEntry {
     pub id: usize,
     pub hardware: PcHardware,
     pub owner: Option<Rc<Person>>,
+    owner_id: Option<PersonId>,
+    location: Option<Location>,
     state: RefCell<PcState>,
 }
 
 impl PcDirectoryEntry {
-    fn new(id: usize, builder: PcBuilder, owner: Option<Rc<Person>>) -> Self {
+    fn new(id: usize, builder: PcBuilder, owner: Option<(PersonId, Rc<Person>)>) -> Self {
+        let (owner_id, owner) = owner.unzip();
         Self {
             id,
+            owner_id,
+            location: None,
             hardware: builder.hardware.unwrap(),
             state: RefCell::new(PcState {
                 os: builder.os.unwrap(),
                 mailbox: Default::default(),
+                software: BTreeMap::new(),
                 maintenance: OperationalState::On,
             }),
             owner,
         }
     }
 
-    pub fn acquire_maintenance_lock<S: ToString>(
+    pub(crate) fn acquire_maintenance_lock<S: ToString>(
         &self,
         reason: S,
     ) -> Result<MaintenanceHandle<'_>, PcDirectoryError> {
@@ -143,7 +663,12 @@ impl PcDirectoryEntry {
                 state.maintenance = OperationalState::BeingMaintained {
                     reason: reason.to_string(),
                 };
-                Ok(MaintenanceHandle { state: &self.state })
+                Ok(MaintenanceHandle {
+                    id: self.id,
+                    state: &self.state,
+                    hardware: &self.hardware,
+                    notifier: None,
+                })
             }
         }
     }
@@ -151,16 +676,49 @@ impl PcDirectoryEntry {
     pub fn id(&self) -> usize {
         self.id
     }
+
+    /// Where the PC is, if known. See [PcDirectory::move_pc].
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+
+    /// The id of the owner, see [PcDirectory::person].
+    pub fn owner_id(&self) -> Option<PersonId> {
+        self.owner_id
+    }
+
+    /// The operating system currently installed.
+    pub fn os(&self) -> OperatingSystem {
+        self.state.borrow().os.clone()
+    }
+
+    /// Whether the PC is on, off or being maintained.
+    pub fn operational_state(&self) -> OperationalState {
+        self.state.borrow().maintenance.clone()
+    }
+
+    /// The installed software, ordered by name.
+    pub fn software(&self) -> Vec<Package> {
+        self.state.borrow().software.values().cloned().collect()
+    }
+
+    /// The messages in this PC's mailbox. Others read them with
+    /// [PcDirectory::read_mailbox_as].
+    pub(crate) fn mailbox(&self) -> Vec<String> {
+        self.state.borrow().mailbox.borrow().clone()
+    }
 }
 
 
 pub struct PcState {
     os: OperatingSystem,
     mailbox: RefCell<Vec<String>>,
+    /// The installed packages by name.
+    software: BTreeMap<String, Package>,
     maintenance: OperationalState,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OperationalState {
     On,
     Off,
@@ -174,50 +732,154 @@ impl OperationalState {
 }
 
 pub struct MaintenanceHandle<'a> {
+    id: usize,
     state: &'a RefCell<PcState>,
+    hardware: &'a PcHardware,
+    notifier: Option<(&'a PcDirectory, MaintenanceNotifier)>,
+}
+
+/// A maintenance lock that does not borrow the directory, so that a PC can
+/// stay in maintenance while the directory changes, e.g. between the requests
+/// to a server. The PC is maintained until the lease is turned back into a
+/// handle with [PcDirectory::resume_maintenance] and the handle is dropped.
+#[must_use = "the PC stays in maintenance until the lease is resumed"]
+pub struct MaintenanceLease {
+    id: usize,
+    notifier: Option<MaintenanceNotifier>,
+}
+
+impl MaintenanceLease {
+    /// The id of the PC being maintained.
+    pub fn pc(&self) -> usize {
+        self.id
+    }
 }
 
 impl<'a> MaintenanceHandle<'a> {
-    pub fn update_os(&self, new: OperatingSystem) {
+    /// The id of the PC being maintained.
+    pub fn pc(&self) -> usize {
+        self.id
+    }
+
+    /// Keep the PC in maintenance without borrowing the directory.
+    pub fn into_lease(mut self) -> MaintenanceLease {
+        let lease = MaintenanceLease {
+            id: self.id,
+            notifier: self.notifier.take().map(|(_, notifier)| notifier),
+        };
+        // Dropping the handle would end the maintenance.
+        std::mem::forget(self);
+        lease
+    }
+
+    /// Install a new operating system, if the hardware can run it.
+    pub fn update_os(&self, new: OperatingSystem) -> Result<(), PcDirectoryError> {
+        CompatibilityMatrix::builtin().check(&new, self.hardware)?;
         self.state.borrow_mut().os = new;
+        Ok(())
+    }
+
+    /// Install a package, replacing any other version of it. The replaced
+    /// package is returned.
+    pub fn install(&self, package: Package) -> Option<Package> {
+        let mut state = self.state.borrow_mut();
+        state.software.insert(package.name.clone(), package)
+    }
+
+    /// Remove the package with the given name.
+    pub fn remove(&self, name: &str) -> Result<Package, PcDirectoryError> {
+        let mut state = self.state.borrow_mut();
+        state.software.remove(name).ok_or_else(|| PcDirectoryError::NotInstalled { name: name.into() })
     }
 }
 
 impl Drop for MaintenanceHandle<'_> {
     fn drop(&mut self) {
         self.state.borrow_mut().maintenance = OperationalState::On;
+        if let Some((directory, notifier)) = self.notifier.take() {
+            let (os_after, software_after) = {
+                let state = self.state.borrow();
+                (state.os.clone(), state.software.clone())
+            };
+            notifier.notify_release(directory, os_after, &software_after);
+        }
+    }
+}
+
+// Everything needed to tell the owner and their manager what happened once the
+// maintenance is over.
+struct MaintenanceNotifier {
+    owner: EmailAddr,
+    owner_id: Option<PersonId>,
+    owner_name: String,
+    id: usize,
+    reason: String,
+    os_before: OperatingSystem,
+    software_before: BTreeMap<String, Package>,
+}
+
+impl MaintenanceNotifier {
+    fn notify_release(self, directory: &PcDirectory, os_after: OperatingSystem, software_after: &BTreeMap<String, Package>) {
+        let mut changes = vec![];
+        if self.os_before != os_after {
+            changes.push(format!("Operating system: {} -> {os_after}.", self.os_before));
+        }
+        changes.extend(software::describe_changes(&self.software_before, software_after));
+        let changes = match changes.is_empty() {
+            true => "No changes were made.".to_string(),
+            false => changes.join(" "),
+        };
+        // The PC is available again, so mail that was held back can now be
+        // delivered before the summary.
+        directory.flush_deferred_mail();
+        directory.deliver_or_defer(
+            &self.owner,
+            format!(
+                "Maintenance of your PC {} is finished ({}). {changes}",
+                self.id, self.reason
+            ),
+        );
+        // The manager may have changed during the maintenance.
+        let manager = self.owner_id.and_then(|owner| directory.org.manager_of(owner));
+        if let Some(manager) = manager.and_then(|m| directory.owners.get(&m)) {
+            directory.deliver_or_defer(
+                &manager.email,
+                format!(
+                    "Maintenance of PC {} of {} is finished ({}). {changes}",
+                    self.id, self.owner_name, self.reason
+                ),
+            );
+        }
     }
 }
 
 #[rustfmt::skip]
 pub fn get_directory() -> PcDirectory {
-    use OperatingSystem::*;
-    let linux6 = Linux {
-        major: 6,
-        minor: 22,
-    };
-    let macos10 = MacOs {
-        major: 10,
-        minor: 14,
-    };
+    use WindowsRelease::*;
+    let linux6 = OperatingSystem::linux(6, 22);
+    let macos10 = OperatingSystem::macos(10, 14);
+    let (windows11, vista) = (OperatingSystem::windows(Win11), OperatingSystem::windows(Vista));
     let super_income = Affiliation::Employee {
-        annual_income: ChfAmout::new(10),
+        annual_income: ChfAmout::from_major(10),
     };
     let mid_income = Affiliation::Employee {
-        annual_income: ChfAmout::new(5),
+        annual_income: ChfAmout::from_major(5),
     };
     let contractor = Affiliation::Contractor {
         company_name: "minisoft".into(),
+        engagement: None,
     };
+    let intern = Affiliation::Intern { engagement: None };
 
-    [
-        ("Maria", "Dingdong", "maria@dingong.com",   super_income.clone(), Windows11,      PcHardware::beefy_workstation()),
+    let pcs = [
+        ("Maria", "Dingdong", "maria@dingong.com",   super_income.clone(), windows11,      PcHardware::beefy_workstation()),
         ("Hans",  "Overkill", "hans@overkill.com",   super_income.clone(), linux6.clone(), PcHardware::nerd_workstation()),
-        ("Sue",   "Sensible", "sue@whatever.com",    Affiliation::Intern,  macos10,        PcHardware::beefy_workstation()),
-        ("Don",   "Drumpf",   "don@drumpf.com",      mid_income,           WindowsVista,   PcHardware::normal()),
-        ("Lex",   "Long",     "lexlong@voll.com",    contractor,           WindowsVista,   PcHardware::normal()),
+        ("Sue",   "Sensible", "sue@whatever.com",    intern,               macos10,        PcHardware::beefy_workstation()),
+        ("Don",   "Drumpf",   "don@drumpf.com",      mid_income,           vista.clone(),  PcHardware::normal()),
+        ("Lex",   "Long",     "lexlong@voll.com",    contractor,           vista,          PcHardware::normal()),
         ("Karl",  "Keule",    "karl@keule.com",      super_income,         linux6,         PcHardware::nerd_workstation()),
-    ].into_iter().map(|item| {
+    ];
+    let mut dir = PcDirectory::from_pcs(pcs.into_iter().enumerate().map(|(i, item)| {
         PcBuilder {
             owner: Some(
                 PersonBuilder::new()
@@ -229,9 +891,39 @@ pub fn get_directory() -> PcDirectory {
                 .unwrap(),
             ),
             os: Some(item.4),
-            hardware: Some(item.5)
+            hardware: Some(item.5.with_serial_number(format!("ITC-{:04}", 1000 + i)))
         }
-        }).into()
+        })).unwrap();
+
+    // The owners are numbered in the order of their PCs.
+    let [maria, hans, sue, don, lex, karl] = [0, 1, 2, 3, 4, 5].map(PersonId);
+    let org = dir.org_mut();
+    org.add_department("Engineering");
+    org.add_department("Operations");
+    org.add_team("platform", "Engineering").unwrap();
+    org.add_team("apps", "Engineering").unwrap();
+    org.add_team("facilities", "Operations").unwrap();
+    for (team, member) in [("platform", hans), ("platform", karl), ("platform", lex), ("apps", maria), ("apps", sue), ("facilities", don)] {
+        org.add_member(team, member).unwrap();
+    }
+    for (person, manager) in [(hans, maria), (karl, hans), (lex, hans), (sue, maria)] {
+        org.set_manager(person, manager).unwrap();
+    }
+
+    let package = |name: &str, version: &str, vendor: &str| Package::new(name, version.parse::<Version>().unwrap(), vendor);
+    let software = [
+        (0, vec![package("Firefox", "128.0", "Mozilla"), package("Office", "2021", "Microsoft")]),
+        (1, vec![package("Firefox", "128.0", "Mozilla"), package("OpenSSL", "3.0.13", "OpenSSL")]),
+        (2, vec![package("Firefox", "115.2", "Mozilla")]),
+        (3, vec![package("Office", "2016", "Microsoft"), package("OpenSSL", "1.0.2", "OpenSSL")]),
+        (4, vec![package("Office", "2021", "Microsoft")]),
+        (5, vec![package("Firefox", "128.0", "Mozilla"), package("OpenSSL", "3.0.13", "OpenSSL")]),
+    ];
+    for (id, packages) in software {
+        let handle = dir.acquire_maintenance_lock(id, "initial setup").unwrap();
+        packages.into_iter().for_each(|p| { handle.install(p); });
+    }
+    dir
 }
 
 #[cfg(test)]
@@ -240,7 +932,11 @@ mod tests {
         collections::BTreeSet, fs::{File, OpenOptions}, io::Write, path::PathBuf
     };
 
-    use crate::person::{Affiliation, PersonBuilder};
+    use crate::{
+        org::OrgUnit,
+        person::{Affiliation, PersonBuilder},
+        query::Query,
+    };
 
     use super::*;
 
@@ -282,7 +978,7 @@ mod tests {
                 .map(|pc| pc.acquire_maintenance_lock("test"))
                 .collect::<Result<Vec<_>, _>>()
                 .unwrap();
-            handles[0].update_os(OperatingSystem::Linux { major: 5, minor: 5 });
+            handles[0].update_os(OperatingSystem::linux(5, 5)).unwrap();
 
             // The variable holding the locks on the maintenance state are release here.
         }
@@ -303,9 +999,68 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_incompatible_hardware_is_rejected() {
+        let mut dir = PcDirectory::default();
+        let pc = PcBuilder {
+            hardware: Some(PcHardware::normal()),
+            ..maria_dingong_pc()
+        };
+        assert!(matches!(
+            dir.add_pc(pc),
+            Err(PcDirectoryError::Incompatible(_))
+        ));
+
+        dir.add_pc(PcBuilder {
+            hardware: Some(PcHardware::normal()),
+            ..john_does_pc()
+        })
+        .unwrap();
+        let handle = dir.acquire_maintenance_lock(0, "upgrade").unwrap();
+        assert!(matches!(
+            handle.update_os(OperatingSystem::windows(WindowsRelease::Win11)),
+            Err(PcDirectoryError::Incompatible(_))
+        ));
+        drop(handle);
+        assert_eq!(
+            dir.get_pc(0).unwrap().os(),
+            OperatingSystem::windows(WindowsRelease::Win7)
+        );
+    }
+
+    #[test]
+    fn test_directory_from_incompatible_pcs_fails() {
+        let pc = PcBuilder {
+            hardware: Some(PcHardware::normal()),
+            ..maria_dingong_pc()
+        };
+        assert!(matches!(
+            PcDirectory::try_from([john_does_pc(), pc]),
+            Err(PcDirectoryError::Incompatible(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_keeps_pcs_the_matrix_no_longer_supports() {
+        // E.g. a PC that was set up before the matrix was tightened.
+        let mut dir = PcDirectory::default();
+        let pc = PcBuilder {
+            hardware: Some(PcHardware::normal()),
+            ..maria_dingong_pc()
+        };
+        dir.insert_pc(pc, None).unwrap();
+        let path = PathBuf::from(std::env::var("TMPDIR").unwrap()).join("incompatible.json");
+        dir.save(&path).unwrap();
+        let dir = PcDirectory::load(&path).unwrap();
+        assert_eq!(
+            dir.get_pc(0).unwrap().os(),
+            OperatingSystem::windows(WindowsRelease::Win11)
+        );
+    }
+
     #[test]
     fn test_email_does_not_exist() {
-        let dir: PcDirectory = [john_does_pc(), maria_dingong_pc()].into();
+        let dir = PcDirectory::try_from([john_does_pc(), maria_dingong_pc()]).unwrap();
 
         let given_email = EmailAddr::try_from("dings@bla.com").unwrap();
         assert!(matches!(
@@ -320,8 +1075,9 @@ mod tests {
 
         // small helper
         fn vista_users(dir: &PcDirectory) -> impl Iterator<Item = &PcDirectoryEntry> {
-            dir.iter_pcs()
-                .filter(|pc| matches!(&pc.state.borrow().os, &OperatingSystem::WindowsVista))
+            dir.iter_pcs().filter(|pc| {
+                pc.state.borrow().os == OperatingSystem::windows(WindowsRelease::Vista)
+            })
         }
 
         // send all vista users an email
@@ -340,7 +1096,7 @@ mod tests {
 
             let handles = handles.unwrap();
             for handle in handles.iter() {
-                handle.state.borrow_mut().os = OperatingSystem::Windows11;
+                handle.state.borrow_mut().os = OperatingSystem::windows(WindowsRelease::Win11);
             }
 
             assert!(dir
@@ -354,6 +1110,265 @@ mod tests {
             .is_ok());
     }
 
+    #[test]
+    fn test_query_finds_vista_users() {
+        let dir = get_directory();
+        let vista = Query::parse(r#"os = "Windows Vista""#).unwrap();
+        let ids: Vec<_> = vista.filter(&dir).map(|pc| pc.id()).collect();
+        assert_eq!(ids, vec![3, 4]);
+
+        // PCs in maintenance are still found.
+        let handles: Vec<_> = vista
+            .filter(&dir)
+            .map(|pc| pc.acquire_maintenance_lock("Update from windows vista!").unwrap())
+            .collect();
+        assert_eq!(vista.filter(&dir).count(), handles.len());
+    }
+
+    #[test]
+    fn test_maintenance_notifications_are_opt_in() {
+        let dir = PcDirectory::try_from([john_does_pc(), john_does_pc()]).unwrap();
+        {
+            let _handle = dir.acquire_maintenance_lock(0, "test").unwrap();
+        }
+        assert!(dir.iter_pcs().all(|pc| pc.mailbox().is_empty()));
+        assert!(dir.deferred_recipients().is_empty());
+    }
+
+    #[test]
+    fn test_maintenance_notification_to_other_pc() {
+        let dir = PcDirectory::try_from([john_does_pc(), john_does_pc()]).unwrap();
+        dir.set_maintenance_notifications(true);
+        {
+            let handle = dir.acquire_maintenance_lock(0, "upgrade").unwrap();
+            assert_eq!(
+                dir.get_pc(1).unwrap().mailbox(),
+                vec!["Your PC 0 is being maintained: upgrade".to_string()]
+            );
+            handle
+                .update_os(OperatingSystem::windows(WindowsRelease::Win11))
+                .unwrap();
+        }
+        let summary = dir.get_pc(0).unwrap().mailbox();
+        assert_eq!(summary.len(), 1);
+        assert!(summary[0].contains("Windows 7 -> Windows 11"));
+    }
+
+    #[test]
+    fn test_maintenance_notification_is_deferred() {
+        let dir = PcDirectory::try_from([john_does_pc()]).unwrap();
+        dir.set_maintenance_notifications(true);
+        {
+            let _handle = dir.acquire_maintenance_lock(0, "cleanup").unwrap();
+            assert_eq!(dir.deferred_recipients().len(), 1);
+        }
+        assert!(dir.deferred_recipients().is_empty());
+        let mailbox = dir.get_pc(0).unwrap().mailbox();
+        assert_eq!(mailbox.len(), 2);
+        assert!(mailbox[1].contains("No changes were made."));
+    }
+
+    #[test]
+    fn test_maintenance_lease() {
+        let mut dir = PcDirectory::try_from([john_does_pc()]).unwrap();
+        dir.set_maintenance_notifications(true);
+        let lease = dir.acquire_maintenance_lock(0, "upgrade").unwrap().into_lease();
+        // The directory can change while the PC is maintained.
+        dir.add_pc(maria_dingong_pc()).unwrap();
+        assert!(matches!(
+            dir.acquire_maintenance_lock(0, "again"),
+            Err(PcDirectoryError::InMaintenance { .. })
+        ));
+
+        let handle = dir.resume_maintenance(lease).unwrap();
+        handle
+            .update_os(OperatingSystem::windows(WindowsRelease::Win11))
+            .unwrap();
+        drop(handle);
+        assert!(dir.get_pc(0).unwrap().operational_state().is_on());
+        let mailbox = dir.get_pc(0).unwrap().mailbox();
+        assert!(mailbox[1].contains("Windows 7 -> Windows 11"));
+    }
+
+    #[test]
+    fn test_update_person() {
+        let mut dir = PcDirectory::try_from([john_does_pc(), maria_dingong_pc(), john_does_pc()]).unwrap();
+        let john = dir.get_pc(0).unwrap().owner_id().unwrap();
+        assert_eq!(dir.get_pc(2).unwrap().owner_id(), Some(john));
+
+        dir.update_person(john, |p| {
+            p.last = "Smith".into();
+            p.email = EmailAddr::try_from("john@smith.com").unwrap();
+        })
+        .unwrap();
+        let (pc0, pc2) = (dir.get_pc(0).unwrap(), dir.get_pc(2).unwrap());
+        assert_eq!(pc0.owner.as_ref().unwrap().last, "Smith");
+        assert!(Rc::ptr_eq(pc0.owner.as_ref().unwrap(), pc2.owner.as_ref().unwrap()));
+        assert_eq!(
+            dir.person(john).unwrap().previous_emails,
+            vec![EmailAddr::try_from("john@doe.com").unwrap()]
+        );
+
+        // Mail to the old address still arrives.
+        dir.send_email("john@doe.com", "hello").unwrap();
+        assert_eq!(dir.get_pc(0).unwrap().mailbox(), vec!["hello".to_string()]);
+        let old = EmailAddr::try_from("john@doe.com").unwrap();
+        assert_eq!(dir.find_person(&old).map(|(id, _)| id), Some(john));
+    }
+
+    #[test]
+    fn test_addresses_are_not_shared() {
+        let mut dir = PcDirectory::try_from([john_does_pc(), maria_dingong_pc()]).unwrap();
+        let maria = dir.get_pc(1).unwrap().owner_id().unwrap();
+        assert!(matches!(
+            dir.update_person(maria, |p| p.previous_emails
+                .push(EmailAddr::try_from("john@doe.com").unwrap())),
+            Err(PcDirectoryError::DuplicateEmailAddress { .. })
+        ));
+        assert!(dir.person(maria).unwrap().previous_emails.is_empty());
+
+        let john = dir.get_pc(0).unwrap().owner_id().unwrap();
+        dir.update_person(john, |p| p.email = EmailAddr::try_from("john@smith.com").unwrap())
+            .unwrap();
+        // Someone new cannot take over John's previous address either.
+        assert!(matches!(
+            dir.add_pc(john2_does_pc()),
+            Err(PcDirectoryError::DuplicateEmailAddress { .. })
+        ));
+        assert!(matches!(
+            dir.update_person(PersonId(7), |_| ()),
+            Err(PcDirectoryError::PersonNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_person_ids_are_saved() {
+        let path = PathBuf::from(std::env::var("TMPDIR").unwrap()).join("person_ids.json");
+        let mut dir = get_directory();
+        dir.reclaim(0).unwrap();
+        dir.update_person(PersonId(1), |p| {
+            p.email = EmailAddr::try_from("hans@underkill.com").unwrap()
+        })
+        .unwrap();
+        dir.save(&path).unwrap();
+
+        let dir = PcDirectory::load(&path).unwrap();
+        // Maria owns no PC anymore, but she is still known.
+        assert_eq!(dir.person(PersonId(0)).unwrap().email.as_ref(), "maria@dingong.com");
+        assert_eq!(dir.get_pc(1).unwrap().owner_id(), Some(PersonId(1)));
+        assert_eq!(dir.get_pc(5).unwrap().owner_id(), Some(PersonId(5)));
+        dir.send_email("hans@overkill.com", "still there?").unwrap();
+        assert_eq!(dir.org().teams_of(PersonId(1)), vec!["platform"]);
+    }
+
+    #[test]
+    fn test_person_ids_are_not_reused() {
+        let path = PathBuf::from(std::env::var("TMPDIR").unwrap()).join("person_ids_reused.json");
+        let mut dir = get_directory();
+        dir.reclaim(5).unwrap();
+        dir.save(&path).unwrap();
+
+        let mut dir = PcDirectory::load(&path).unwrap();
+        dir.add_pc(PcBuilder {
+            owner: Some(
+                PersonBuilder::new()
+                    .with_first_name("Nina")
+                    .with_last_name("New")
+                    .with_email_address("nina@new.com")
+                    .with_affiliation(Affiliation::Intern { engagement: None })
+                    .build()
+                    .unwrap(),
+            ),
+            ..Default::default()
+        })
+        .unwrap();
+        // Karl's id, team and manager are not passed on to Nina.
+        let nina = dir.iter_pcs().last().unwrap().owner_id().unwrap();
+        assert_eq!(nina, PersonId(6));
+        assert!(dir.org().teams_of(nina).is_empty());
+        assert_eq!(dir.org().manager_of(nina), None);
+        assert_eq!(dir.org().teams_of(PersonId(5)), vec!["platform"]);
+    }
+
+    #[test]
+    fn test_software_changes_during_maintenance() {
+        let path = PathBuf::from(std::env::var("TMPDIR").unwrap()).join("software.json");
+        let dir = get_directory();
+        dir.set_maintenance_notifications(true);
+        {
+            let handle = dir.acquire_maintenance_lock(3, "cleanup").unwrap();
+            let old = handle.install(Package::new("Office", "2021".parse().unwrap(), "Microsoft"));
+            assert_eq!(old.unwrap().version.to_string(), "2016");
+            handle.remove("OpenSSL").unwrap();
+            assert!(matches!(handle.remove("OpenSSL"), Err(PcDirectoryError::NotInstalled { .. })));
+        }
+        let summary = dir.get_pc(3).unwrap().mailbox().pop().unwrap();
+        assert!(summary.ends_with("Office: 2016 -> 2021. Removed OpenSSL 1.0.2 (OpenSSL)."));
+
+        dir.save(&path).unwrap();
+        let dir = PcDirectory::load(&path).unwrap();
+        let names: Vec<_> = dir.get_pc(3).unwrap().software().into_iter().map(|p| p.to_string()).collect();
+        assert_eq!(names, vec!["Office 2021 (Microsoft)"]);
+    }
+
+    #[test]
+    fn test_moves_are_recorded() {
+        let path = PathBuf::from(std::env::var("TMPDIR").unwrap()).join("moves.json");
+        let mut dir = get_directory();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 4, day).unwrap();
+        let (desk, storage): (Location, Location) =
+            ("Zurich/HQ/2/2.01/D1".parse().unwrap(), "Zurich/HQ/-1/Store".parse().unwrap());
+        dir.move_pc(3, Some(desk.clone()), date(1)).unwrap();
+        // Moving a PC to where it is changes nothing.
+        dir.move_pc(3, Some(desk.clone()), date(2)).unwrap();
+        dir.move_pc(3, Some(storage.clone()), date(3)).unwrap();
+        assert!(matches!(
+            dir.move_pc(9, None, date(3)),
+            Err(PcDirectoryError::PcNotFound { id: 9 })
+        ));
+        dir.save(&path).unwrap();
+
+        let dir = PcDirectory::load(&path).unwrap();
+        assert_eq!(dir.get_pc(3).unwrap().location(), Some(&storage));
+        let history: Vec<_> = dir.moves().iter().map(|m| (m.date, m.to.clone())).collect();
+        assert_eq!(history, vec![(date(1), Some(desk)), (date(3), Some(storage))]);
+        let building = "Zurich/HQ".parse().unwrap();
+        assert_eq!(dir.pcs_at(&building).map(|pc| pc.id()).collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn test_manager_is_notified_about_maintenance() {
+        let dir = get_directory();
+        dir.set_maintenance_notifications(true);
+        let handle = dir.acquire_maintenance_lock(5, "new disk").unwrap();
+        // Karl reports to Hans.
+        assert_eq!(
+            dir.get_pc(1).unwrap().mailbox(),
+            vec!["PC 5 of Karl Keule is being maintained: new disk".to_string()]
+        );
+        drop(handle);
+        assert_eq!(
+            dir.get_pc(1).unwrap().mailbox()[1],
+            "Maintenance of PC 5 of Karl Keule is finished (new disk). No changes were made."
+        );
+    }
+
+    #[test]
+    fn test_broadcast_to_team() {
+        let dir = get_directory();
+        let platform = dir.org().members(&OrgUnit::Team("platform".into())).unwrap();
+        let pcs: Vec<_> = dir.pcs_of(&platform).map(|pc| pc.id()).collect();
+        assert_eq!(pcs, vec![1, 4, 5]);
+
+        let _handle = dir.acquire_maintenance_lock(4, "cleanup").unwrap();
+        let sent = dir.broadcast(&platform, "Stand-up moved to 10:00");
+        assert_eq!(sent, [1, 4, 5].map(PersonId));
+        assert_eq!(dir.get_pc(5).unwrap().mailbox().len(), 1);
+        assert!(dir.get_pc(0).unwrap().mailbox().is_empty());
+        // Lex's only PC is being maintained.
+        assert_eq!(dir.deferred_recipients().len(), 1);
+    }
+
     fn john_does_pc() -> PcBuilder {
         PcBuilder {
             owner: Some(
@@ -361,12 +1376,13 @@ mod tests {
                     .with_first_name("John")
                     .with_last_name("Doe")
                     .with_email_address("john@doe.com")
-                    .with_affiliation(Affiliation::Intern)
+                    .with_affiliation(Affiliation::Intern { engagement: None })
                     .build()
                     .unwrap(),
             ),
-            os: Some(OperatingSystem::Windows7),
-            ..Default::default()
+            os: Some(OperatingSystem::windows(WindowsRelease::Win7)),
+            // Interns get normal PCs by default, which cannot run Windows 11.
+            hardware: Some(PcHardware::beefy_workstation()),
         }
     }
 
@@ -377,11 +1393,11 @@ mod tests {
                     .with_first_name("John2")
                     .with_last_name("Doe")
                     .with_email_address("john@doe.com")
-                    .with_affiliation(Affiliation::Intern)
+                    .with_affiliation(Affiliation::Intern { engagement: None })
                     .build()
                     .unwrap(),
             ),
-            os: Some(OperatingSystem::Windows7),
+            os: Some(OperatingSystem::windows(WindowsRelease::Win7)),
             ..Default::default()
         }
     }
@@ -393,12 +1409,13 @@ mod tests {
                     .with_first_name("Maria")
                     .with_last_name("Dingdong")
                     .with_email_address("maria@dingdong.com")
-                    .with_affiliation(Affiliation::Intern)
+                    .with_affiliation(Affiliation::Intern { engagement: None })
                     .build()
                     .unwrap(),
             ),
-            os: Some(OperatingSystem::Windows11),
-            ..Default::default()
+            os: Some(OperatingSystem::windows(WindowsRelease::Win11)),
+            // Interns get normal PCs by default, which cannot run Windows 11.
+            hardware: Some(PcHardware::beefy_workstation()),
         }
     }
 
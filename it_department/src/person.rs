@@ -1,10 +1,11 @@
+use chrono::NaiveDate;
 use once_cell::sync::OnceCell;
-use phantom_newtype::Amount;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// Represent a person.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Person {
     /// First name.
     pub first: String,
@@ -16,10 +17,38 @@ pub struct Person {
     pub pref_lang: Option<PreferredLanguage>,
     /// What relations ship does this person have to the company.
     pub affiliation: Affiliation,
+    /// Addresses the person used before, e.g. before a domain migration. Mail
+    /// to them is still delivered.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_emails: Vec<EmailAddr>,
 }
 
-pub enum Chf {}
-pub type ChfAmout = Amount<Chf, u64>;
+impl Person {
+    /// Whether `email` is the current or a previous address of the person.
+    pub fn has_email(&self, email: &EmailAddr) -> bool {
+        &self.email == email || self.previous_emails.contains(email)
+    }
+
+    /// The current and all previous email addresses.
+    pub fn emails(&self) -> impl Iterator<Item = &EmailAddr> {
+        std::iter::once(&self.email).chain(&self.previous_emails)
+    }
+}
+
+/// Identifies a person independent of their name and email address. Ids are
+/// handed out by the [crate::pc_directory::PcDirectory] that owns the person.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PersonId(pub u32);
+
+impl std::fmt::Display for PersonId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// Amounts of money used to be defined in this module.
+pub use crate::money::{Chf, ChfAmout};
 
 // A note on constructors: Typically, it is good advice to always provide
 // constructor functions for the types you define. Even if they just accept the
@@ -40,6 +69,7 @@ pub struct PersonBuilder {
     email: Option<EmailAddr>,
     pref_lang: Option<PreferredLanguage>,
     affiliation: Option<Affiliation>,
+    previous_emails: Vec<EmailAddr>,
 }
 
 impl PersonBuilder {
@@ -81,6 +111,21 @@ impl PersonBuilder {
         Self { email, ..self }
     }
 
+    /// Add an address the person used before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the provided string is not a valid email address.
+    pub fn with_previous_email<T>(mut self, s: T) -> Self
+    where
+        EmailAddr: TryFrom<T>,
+        <EmailAddr as TryFrom<T>>::Error: std::fmt::Debug,
+    {
+        let email = EmailAddr::try_from(s).expect("Could not parse email Address.");
+        self.previous_emails.push(email);
+        self
+    }
+
     pub fn with_preferred_language(self, pref_lang: PreferredLanguage) -> Self {
         Self {
             pref_lang: Some(pref_lang),
@@ -115,6 +160,7 @@ impl PersonBuilder {
             email,
             pref_lang: self.pref_lang,
             affiliation,
+            previous_emails: self.previous_emails,
         })
     }
 }
@@ -142,7 +188,10 @@ const EMAIL_RGX_STR: &str = r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$";
 static EMAIL_REGEX: OnceCell<Regex> = OnceCell::new();
 
 /// A syntactically valid EMail address.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+// When deserializing, the address is validated by going through the TryFrom
+// implementation below.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub struct EmailAddr(String);
 
 impl EmailAddr {
@@ -195,12 +244,26 @@ impl TryFrom<&str> for EmailAddr {
     }
 }
 
+impl TryFrom<String> for EmailAddr {
+    type Error = EmailParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        EmailAddr::try_from(value.as_str())
+    }
+}
+
+impl From<EmailAddr> for String {
+    fn from(value: EmailAddr) -> Self {
+        value.0
+    }
+}
+
 // Define your custom error type
 #[derive(Debug, Error)]
 #[error("Invalid email address in string")]
 pub struct EmailParseError();
 
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PreferredLanguage {
     // The following is a nice way how cargo give you tips and tricks to improve
     // your code. If remove the #[default] below and uncomment the explicit
@@ -220,13 +283,107 @@ impl Default for PreferredLanguage {
 }
 */
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "AffiliationRepr")]
 pub enum Affiliation {
-    Employee { annual_income: ChfAmout },
-    Contractor { company_name: String },
+    Employee {
+        annual_income: ChfAmout,
+    },
+    Contractor {
+        company_name: String,
+        engagement: Option<Engagement>,
+    },
+    Intern {
+        engagement: Option<Engagement>,
+    },
+}
+
+impl Affiliation {
+    /// The period contractors and interns work for the company, if known.
+    /// Employees have no end date.
+    pub fn engagement(&self) -> Option<&Engagement> {
+        match self {
+            Self::Employee { .. } => None,
+            Self::Contractor { engagement, .. } | Self::Intern { engagement } => {
+                engagement.as_ref()
+            }
+        }
+    }
+
+    pub fn engagement_mut(&mut self) -> Option<&mut Engagement> {
+        match self {
+            Self::Employee { .. } => None,
+            Self::Contractor { engagement, .. } | Self::Intern { engagement } => {
+                engagement.as_mut()
+            }
+        }
+    }
+}
+
+// Engagements were added later: contractors may lack one, and interns used to
+// be stored as a plain "Intern".
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AffiliationRepr {
+    Current(#[serde(with = "AffiliationDef")] Affiliation),
+    Legacy(LegacyIntern),
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "Affiliation")]
+enum AffiliationDef {
+    Employee {
+        annual_income: ChfAmout,
+    },
+    Contractor {
+        company_name: String,
+        #[serde(default)]
+        engagement: Option<Engagement>,
+    },
+    Intern {
+        #[serde(default)]
+        engagement: Option<Engagement>,
+    },
+}
+
+#[derive(Deserialize)]
+enum LegacyIntern {
     Intern,
 }
 
+impl From<AffiliationRepr> for Affiliation {
+    fn from(value: AffiliationRepr) -> Self {
+        match value {
+            AffiliationRepr::Current(affiliation) => affiliation,
+            AffiliationRepr::Legacy(LegacyIntern::Intern) => Self::Intern { engagement: None },
+        }
+    }
+}
+
+/// The period someone works for the company. Without an end, the engagement
+/// is open-ended.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Engagement {
+    pub start: NaiveDate,
+    pub end: Option<NaiveDate>,
+}
+
+impl Engagement {
+    pub fn new(start: NaiveDate, end: Option<NaiveDate>) -> Self {
+        Self { start, end }
+    }
+
+    /// Whether the engagement is over on `date`. The last day still counts.
+    pub fn has_ended(&self, date: NaiveDate) -> bool {
+        self.end.is_some_and(|end| end < date)
+    }
+
+    /// The number of days from `date` until the last day of the engagement.
+    pub fn days_left(&self, date: NaiveDate) -> Option<i64> {
+        self.end.map(|end| (end - date).num_days())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // This is a typical short-cut in test modules to make just everything
@@ -256,10 +413,32 @@ mod tests {
         matches!(get_manuel().build(), Err(BuildPersonError::EmailUnset));
     }
 
+    #[test]
+    fn test_legacy_affiliation() {
+        let intern: Affiliation = serde_json::from_str(r#""Intern""#).unwrap();
+        assert_eq!(intern, Affiliation::Intern { engagement: None });
+
+        let contractor: Affiliation =
+            serde_json::from_str(r#"{"Contractor":{"company_name":"minisoft"}}"#).unwrap();
+        assert_eq!(contractor.engagement(), None);
+
+        let date = |d| NaiveDate::from_ymd_opt(2024, 6, d).unwrap();
+        let intern = Affiliation::Intern {
+            engagement: Some(Engagement::new(date(1), Some(date(30)))),
+        };
+        let json = serde_json::to_string(&intern).unwrap();
+        let engagement = *serde_json::from_str::<Affiliation>(&json)
+            .unwrap()
+            .engagement()
+            .unwrap();
+        assert!(!engagement.has_ended(date(30)));
+        assert_eq!(engagement.days_left(date(30)), Some(0));
+    }
+
     fn get_manuel() -> PersonBuilder {
         PersonBuilder::new()
             .with_first_name("Manuel")
             .with_last_name("Gorbatchov")
-            .with_affiliation(Affiliation::Intern)
+            .with_affiliation(Affiliation::Intern { engagement: None })
     }
 }
@@ -0,0 +1,180 @@
+//! Detect the hardware and operating system of the local machine.
+//!
+//! The information is read from the `/proc` file system, so probing only works
+//! on Linux. The parsers take the contents of the files rather than paths, so
+//! they can be tested against samples from other machines.
+use std::{fs, io, path::PathBuf};
+
+use thiserror::Error;
+
+use crate::{
+    os::{CpuArch, OperatingSystem, OsParseError},
+    pc::{Cpu, CpuFlag, CpuFlags, NumBytes, PcBuilder, PcHardware},
+};
+
+pub const CPUINFO: &str = "/proc/cpuinfo";
+pub const MEMINFO: &str = "/proc/meminfo";
+pub const OSRELEASE: &str = "/proc/sys/kernel/osrelease";
+
+#[derive(Debug, Error)]
+pub enum ProbeError {
+    #[error("Could not read {}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("The field {field} is missing.")]
+    MissingField { field: &'static str },
+    #[error("Could not parse line: {line}")]
+    Malformed { line: String },
+    #[error("Unknown kernel release: {0}")]
+    OsRelease(#[from] OsParseError),
+}
+
+fn read(path: &str) -> Result<String, ProbeError> {
+    fs::read_to_string(path).map_err(|source| ProbeError::Io {
+        path: path.into(),
+        source,
+    })
+}
+
+/// The CPU features listed in the contents of `/proc/cpuinfo`.
+///
+/// Only the first processor is looked at.
+pub fn parse_cpuinfo(contents: &str) -> Result<CpuFlags, ProbeError> {
+    // x86 calls them flags, ARM features.
+    let flags = contents
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| matches!(key.trim(), "flags" | "Features"))
+        .map(|(_, value)| value)
+        .ok_or(ProbeError::MissingField { field: "flags" })?;
+
+    Ok(flags.split_whitespace().map(CpuFlag::from_name).collect())
+}
+
+/// The CPU model and the number of (logical) processors in the contents of
+/// `/proc/cpuinfo`. Not all architectures report the model.
+pub fn parse_cpu(contents: &str) -> Option<Cpu> {
+    let fields = contents.lines().filter_map(|line| line.split_once(':'));
+    let model = fields
+        .clone()
+        .find(|(key, _)| key.trim() == "model name")
+        .map(|(_, value)| value.trim())?;
+    let cores = fields.filter(|(key, _)| key.trim() == "processor").count();
+    Some(Cpu::new(model, cores.try_into().unwrap_or(u16::MAX)))
+}
+
+/// The total amount of RAM according to the contents of `/proc/meminfo`.
+pub fn parse_meminfo(contents: &str) -> Result<NumBytes, ProbeError> {
+    let line = contents
+        .lines()
+        .find(|line| line.starts_with("MemTotal:"))
+        .ok_or(ProbeError::MissingField { field: "MemTotal" })?;
+    let malformed = || ProbeError::Malformed { line: line.into() };
+
+    // The kernel always reports the amount in kibibytes, despite the "kB".
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [_, amount, "kB"] => amount
+            .parse::<u64>()
+            .map(|kib| NumBytes::new(kib * 1024))
+            .map_err(|_| malformed()),
+        _ => Err(malformed()),
+    }
+}
+
+/// The Linux kernel version in the contents of `/proc/sys/kernel/osrelease`,
+/// e.g. "6.5.0-21-generic".
+pub fn parse_osrelease(contents: &str) -> Result<OperatingSystem, ProbeError> {
+    let release = contents.trim();
+    let (version, _) = release.split_once(['-', '+']).unwrap_or((release, ""));
+    // Some vendors, e.g. WSL, append a fourth component to the version.
+    let version: Vec<_> = version.split('.').take(3).collect();
+    Ok(format!("Linux {}", version.join(".")).parse()?)
+}
+
+pub fn probe_hardware() -> Result<PcHardware, ProbeError> {
+    let cpuinfo = read(CPUINFO)?;
+    Ok(PcHardware {
+        cpu: parse_cpu(&cpuinfo),
+        ..PcHardware::new(parse_cpuinfo(&cpuinfo)?, parse_meminfo(&read(MEMINFO)?)?)
+    })
+}
+
+/// The running kernel, on the architecture this program was built for.
+pub fn probe_os() -> Result<OperatingSystem, ProbeError> {
+    let os = parse_osrelease(&read(OSRELEASE)?)?;
+    Ok(match std::env::consts::ARCH.parse::<CpuArch>() {
+        Ok(arch) => os.with_arch(arch),
+        Err(_) => os,
+    })
+}
+
+/// A builder for the local machine with hardware and operating system set.
+pub fn probe() -> Result<PcBuilder, ProbeError> {
+    Ok(PcBuilder {
+        hardware: Some(probe_hardware()?),
+        os: Some(probe_os()?),
+        owner: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pc::{GIBIBYTE, MEBIBYTE};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_cpuinfo() {
+        use CpuFlag::*;
+        let intel = parse_cpuinfo(include_str!("../fixtures/probe/cpuinfo_intel")).unwrap();
+        assert!(intel.is_superset(&[MMX, SSE, SSE4_2, AVX2, AVX512F, AES].into_iter().collect()));
+        assert!(!intel.contains(&SEV));
+        assert!(intel.contains(&Unknown("hypervisor".into())));
+        let epyc = parse_cpuinfo(include_str!("../fixtures/probe/cpuinfo_epyc")).unwrap();
+        assert!(epyc.is_superset(&[MMX, SSE, SVM, SEV, SEVES, AVX].into_iter().collect()));
+        let arm = parse_cpuinfo(include_str!("../fixtures/probe/cpuinfo_arm64")).unwrap();
+        assert!(arm.is_superset(&[NEON, AES, SHA2, CRC32].into_iter().collect()));
+        assert!(!arm.contains(&MMX));
+
+        assert!(matches!(
+            parse_cpuinfo("processor\t: 0\n"),
+            Err(ProbeError::MissingField { field: "flags" })
+        ));
+    }
+
+    #[test]
+    fn test_parse_cpu() {
+        let intel = parse_cpu(include_str!("../fixtures/probe/cpuinfo_intel")).unwrap();
+        assert_eq!(
+            intel,
+            Cpu::new("11th Gen Intel(R) Core(TM) i5-1135G7 @ 2.40GHz", 2)
+        );
+        assert!(parse_cpu(include_str!("../fixtures/probe/cpuinfo_arm64")).is_none());
+    }
+
+    #[test]
+    fn test_parse_meminfo() {
+        let ram = parse_meminfo(include_str!("../fixtures/probe/meminfo")).unwrap();
+        assert_eq!(ram.get(), 16318412 * 1024);
+        assert!(ram > GIBIBYTE * 15 && ram < GIBIBYTE * 16);
+        assert_eq!(parse_meminfo("MemTotal: 2048 kB").unwrap(), MEBIBYTE * 2);
+        assert!(matches!(
+            parse_meminfo("MemTotal: lots"),
+            Err(ProbeError::Malformed { .. })
+        ));
+        assert!(matches!(
+            parse_meminfo("MemFree: 2048 kB"),
+            Err(ProbeError::MissingField { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_osrelease() {
+        let os = parse_osrelease(include_str!("../fixtures/probe/osrelease")).unwrap();
+        assert_eq!(os, OperatingSystem::linux(6, 5));
+        assert_eq!(
+            parse_osrelease("5.15.146.1-microsoft-standard-WSL2").unwrap(),
+            OperatingSystem::linux(5, 15).with_patch(146)
+        );
+        assert!(parse_osrelease("").is_err());
+    }
+}
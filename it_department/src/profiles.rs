@@ -0,0 +1,252 @@
+//! Named hardware and operating system profiles for new PCs.
+//!
+//! Profiles are declared in a TOML file, together with the profile a new PC
+//! gets by default, depending on the affiliation of its owner:
+//!
+//! ```toml
+//! [profile.office]
+//! hardware = "normal"
+//! os = "Windows 10"
+//!
+//! [defaults]
+//! intern = "office"
+//! other = "office"
+//! ```
+//!
+//! The hardware of a profile is either the name of a built-in preset or a
+//! table describing a [PcHardware]. The profiles in `profiles.toml` are built
+//! in and used unless others are loaded.
+use std::{collections::BTreeMap, fs, path::Path};
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    os::OperatingSystem,
+    pc::{PcBuilder, PcHardware},
+    person::{Affiliation, Person},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profiles {
+    #[serde(rename = "profile", default)]
+    profiles: BTreeMap<String, Profile>,
+    defaults: Defaults,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+    pub hardware: HardwareSpec,
+    pub os: OperatingSystem,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum HardwareSpec {
+    Preset(Preset),
+    Custom(PcHardware),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    NerdWorkstation,
+    BeefyWorkstation,
+    Normal,
+}
+
+/// The names of the default profiles per affiliation.
+#[derive(Debug, Clone, Deserialize)]
+struct Defaults {
+    employee: Option<String>,
+    contractor: Option<String>,
+    intern: Option<String>,
+    other: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("Could not read the profiles: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The profiles are malformed: {0}")]
+    Format(#[from] toml::de::Error),
+    #[error("There is no profile named {name}.")]
+    UnknownProfile { name: String },
+}
+
+static BUILTIN: Lazy<Profiles> = Lazy::new(|| {
+    Profiles::parse(include_str!("../profiles.toml")).expect("built-in profiles are valid")
+});
+
+impl HardwareSpec {
+    pub fn build(&self) -> PcHardware {
+        match self {
+            Self::Preset(Preset::NerdWorkstation) => PcHardware::nerd_workstation(),
+            Self::Preset(Preset::BeefyWorkstation) => PcHardware::beefy_workstation(),
+            Self::Preset(Preset::Normal) => PcHardware::normal(),
+            Self::Custom(hardware) => hardware.clone(),
+        }
+    }
+}
+
+impl Profiles {
+    pub fn builtin() -> &'static Self {
+        &BUILTIN
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ProfileError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse profiles, making sure all defaults refer to existing profiles.
+    pub fn parse(s: &str) -> Result<Self, ProfileError> {
+        let profiles: Self = toml::from_str(s)?;
+        let d = &profiles.defaults;
+        for name in [&d.employee, &d.contractor, &d.intern]
+            .into_iter()
+            .flatten()
+            .chain([&d.other])
+        {
+            profiles.get(name)?;
+        }
+        Ok(profiles)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Profile, ProfileError> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| ProfileError::UnknownProfile { name: name.into() })
+    }
+
+    /// All profiles, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Profile)> {
+        self.profiles.iter().map(|(name, p)| (name.as_str(), p))
+    }
+
+    /// The name of the profile a PC owned by `owner` gets by default.
+    pub fn default_name(&self, owner: Option<&Person>) -> &str {
+        let d = &self.defaults;
+        let specific = owner.and_then(|p| match p.affiliation {
+            Affiliation::Employee { .. } => d.employee.as_ref(),
+            Affiliation::Contractor { .. } => d.contractor.as_ref(),
+            Affiliation::Intern { .. } => d.intern.as_ref(),
+        });
+        specific.unwrap_or(&d.other)
+    }
+
+    /// The profile a PC owned by `owner` gets by default.
+    pub fn default_for(&self, owner: Option<&Person>) -> &Profile {
+        self.get(self.default_name(owner))
+            .expect("defaults are checked when parsing")
+    }
+
+    /// A builder for a PC with the hardware and operating system of the
+    /// profile called `name`.
+    pub fn builder(&self, name: &str) -> Result<PcBuilder, ProfileError> {
+        let profile = self.get(name)?;
+        Ok(PcBuilder {
+            hardware: Some(profile.hardware.build()),
+            os: Some(profile.os.clone()),
+            owner: None,
+        })
+    }
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self::builtin().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        os::WindowsRelease,
+        pc::{CpuFlag, GIBIBYTE},
+        person::PersonBuilder,
+    };
+
+    use super::*;
+
+    const PROFILES: &str = r#"
+        [profile.lab]
+        hardware = "nerd_workstation"
+        os = "Ubuntu Linux 6.5"
+
+        [profile.kiosk]
+        os = "Windows 10"
+        [profile.kiosk.hardware]
+        flags = ["mmx", "sse"]
+        ram = "4 GiB"
+
+        [defaults]
+        contractor = "kiosk"
+        other = "lab"
+    "#;
+
+    fn person(affiliation: Affiliation) -> Person {
+        PersonBuilder::new()
+            .with_first_name("Pat")
+            .with_last_name("Doe")
+            .with_email_address("pat@doe.com")
+            .with_affiliation(affiliation)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_builtin_defaults() {
+        let profiles = Profiles::builtin();
+        assert_eq!(profiles.default_name(None), "beefy");
+        assert_eq!(
+            profiles.default_name(Some(&person(Affiliation::Intern { engagement: None }))),
+            "office"
+        );
+
+        let mut pcb = PcBuilder {
+            owner: Some(person(Affiliation::Intern { engagement: None })),
+            ..Default::default()
+        };
+        pcb.fill_defaults();
+        assert_eq!(
+            pcb.os,
+            Some(OperatingSystem::windows(WindowsRelease::Win10))
+        );
+        assert_eq!(pcb.hardware.unwrap().ram, PcHardware::normal().ram);
+    }
+
+    #[test]
+    fn test_custom_profiles() {
+        let profiles = Profiles::parse(PROFILES).unwrap();
+        let contractor = person(Affiliation::Contractor {
+            company_name: "minisoft".into(),
+            engagement: None,
+        });
+        let kiosk = profiles.default_for(Some(&contractor));
+        let hardware = kiosk.hardware.build();
+        assert_eq!(hardware.ram, GIBIBYTE * 4);
+        assert!(hardware.flags.contains(&CpuFlag::SSE));
+        assert_eq!(
+            profiles.default_name(Some(&person(Affiliation::Intern { engagement: None }))),
+            "lab"
+        );
+
+        let pcb = profiles.builder("lab").unwrap();
+        assert_eq!(pcb.os.unwrap().to_string(), "Ubuntu Linux 6.5");
+        assert!(matches!(
+            profiles.builder("gaming"),
+            Err(ProfileError::UnknownProfile { .. })
+        ));
+    }
+
+    #[test]
+    fn test_defaults_must_exist() {
+        let err = Profiles::parse("[defaults]\nother = \"gaming\"").unwrap_err();
+        assert!(matches!(err, ProfileError::UnknownProfile { name } if name == "gaming"));
+        assert!(matches!(
+            Profiles::parse("[profile.lab]\nhardware = \"normal\"\nos = \"Linux 6.1\""),
+            Err(ProfileError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,898 @@
+//! A small query language for the PC directory.
+//!
+//! A query is a condition on the fields of a PC, its owner and its state:
+//!
+//! ```text
+//! os < "Windows 11" and ram >= 32GiB and owner.affiliation = contractor and state = on
+//! ```
+//!
+//! Conditions compare a field to a value with one of `=`, `!=`, `<`, `<=`, `>`,
+//! `>=` and `~` (contains), and are combined with `and`, `or`, `not` and
+//! parentheses. Values containing spaces or operator characters are quoted.
+//! Which operators and values a field accepts depends on its type; this is
+//! checked when the query is parsed. A condition on a field that a PC lacks,
+//! e.g. the owner of a PC without owner, is false. The `team` and `department`
+//! of an owner in several teams match if one of them does.
+use std::{cmp::Ordering, collections::BTreeSet, fmt, str::FromStr};
+
+use chrono::NaiveDate;
+use thiserror::Error;
+
+use crate::{
+    bytes::NumBytes,
+    os::{OperatingSystem, OsFamily},
+    pc::{CpuFlag, CpuFlags},
+    pc_directory::{OperationalState, PcDirectory, PcDirectoryEntry},
+    person::{Affiliation, Person},
+};
+
+/// What can be queried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Id,
+    Os,
+    OsFamily,
+    OsDistribution,
+    OsRelease,
+    Ram,
+    Storage,
+    Cores,
+    CpuModel,
+    Flags,
+    Serial,
+    Purchased,
+    State,
+    OwnerFirst,
+    OwnerLast,
+    OwnerEmail,
+    OwnerAffiliation,
+    OwnerCompany,
+    Site,
+    Location,
+    Team,
+    Department,
+}
+
+/// The type of a field, which determines the operators and values it accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Number,
+    Size,
+    Os,
+    Text,
+    Date,
+    Flags,
+    /// One of a fixed set of words.
+    Keyword(&'static [&'static str]),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Number(u64),
+    Size(NumBytes),
+    Os(OperatingSystem),
+    Text(String),
+    Date(NaiveDate),
+    Flags(CpuFlags),
+    Keyword(String),
+    /// Several names, e.g. the teams of an owner. A condition holds if it
+    /// holds for one of them; `!=` holds if it holds for all of them.
+    Names(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Compare { field: Field, op: Op, value: Value },
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// A parsed query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    expr: Expr,
+}
+
+/// Errors found while parsing a query. Positions are byte offsets into the
+/// query.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QueryError {
+    #[error("Expected {expected}, found {found}.")]
+    Syntax {
+        pos: usize,
+        expected: &'static str,
+        found: String,
+    },
+    #[error("Unterminated string.")]
+    UnterminatedString { pos: usize },
+    #[error("Unknown field {name}. Known fields are: {}.", Field::ALL.map(|f| f.name()).join(", "))]
+    UnknownField { pos: usize, name: String },
+    #[error("{field} cannot be compared with {op}.")]
+    UnsupportedOperator {
+        pos: usize,
+        field: &'static str,
+        op: Op,
+    },
+    #[error("{field} expects {expected}, got {value:?}.")]
+    InvalidValue {
+        pos: usize,
+        field: &'static str,
+        value: String,
+        expected: String,
+    },
+    #[error("Queries may be at most {max} bytes long.")]
+    TooLong { pos: usize, max: usize },
+    #[error("Queries may nest not and parentheses at most {max} deep.")]
+    TooDeep { pos: usize, max: usize },
+}
+
+impl QueryError {
+    pub fn position(&self) -> usize {
+        match self {
+            Self::Syntax { pos, .. }
+            | Self::UnterminatedString { pos }
+            | Self::UnknownField { pos, .. }
+            | Self::UnsupportedOperator { pos, .. }
+            | Self::InvalidValue { pos, .. }
+            | Self::TooLong { pos, .. }
+            | Self::TooDeep { pos, .. } => *pos,
+        }
+    }
+
+    /// The query with a marker below the position of the error, followed by
+    /// the error message.
+    pub fn show(&self, query: &str) -> String {
+        let column = query[..self.position().min(query.len())].chars().count();
+        format!("{query}\n{}^ {self}", " ".repeat(column))
+    }
+}
+
+/// The longest query that is parsed, in bytes.
+pub const MAX_QUERY_LEN: usize = 4096;
+/// How deep `not` and parentheses may nest, so that parsing and evaluating a
+/// query cannot overflow the stack.
+pub const MAX_QUERY_DEPTH: usize = 64;
+
+const STATES: &[&str] = &["on", "off", "maintenance"];
+const AFFILIATIONS: &[&str] = &["employee", "contractor", "intern"];
+const FAMILIES: &[&str] = &["windows", "macos", "linux"];
+
+impl Field {
+    pub const ALL: [Field; 22] = [
+        Self::Id,
+        Self::Os,
+        Self::OsFamily,
+        Self::OsDistribution,
+        Self::OsRelease,
+        Self::Ram,
+        Self::Storage,
+        Self::Cores,
+        Self::CpuModel,
+        Self::Flags,
+        Self::Serial,
+        Self::Purchased,
+        Self::State,
+        Self::OwnerFirst,
+        Self::OwnerLast,
+        Self::OwnerEmail,
+        Self::OwnerAffiliation,
+        Self::OwnerCompany,
+        Self::Site,
+        Self::Location,
+        Self::Team,
+        Self::Department,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Os => "os",
+            Self::OsFamily => "os.family",
+            Self::OsDistribution => "os.distribution",
+            Self::OsRelease => "os.release",
+            Self::Ram => "ram",
+            Self::Storage => "storage",
+            Self::Cores => "cores",
+            Self::CpuModel => "cpu.model",
+            Self::Flags => "flags",
+            Self::Serial => "serial",
+            Self::Purchased => "purchased",
+            Self::State => "state",
+            Self::OwnerFirst => "owner.first",
+            Self::OwnerLast => "owner.last",
+            Self::OwnerEmail => "owner.email",
+            Self::OwnerAffiliation => "owner.affiliation",
+            Self::OwnerCompany => "owner.company",
+            Self::Site => "site",
+            Self::Location => "location",
+            Self::Team => "team",
+            Self::Department => "department",
+        }
+    }
+
+    fn kind(&self) -> Kind {
+        match self {
+            Self::Id | Self::Cores => Kind::Number,
+            Self::Ram | Self::Storage => Kind::Size,
+            Self::Os => Kind::Os,
+            Self::OsFamily => Kind::Keyword(FAMILIES),
+            Self::OsDistribution
+            | Self::OsRelease
+            | Self::CpuModel
+            | Self::Serial
+            | Self::OwnerFirst
+            | Self::OwnerLast
+            | Self::OwnerEmail
+            | Self::OwnerCompany
+            | Self::Site
+            | Self::Location
+            | Self::Team
+            | Self::Department => Kind::Text,
+            Self::Flags => Kind::Flags,
+            Self::Purchased => Kind::Date,
+            Self::State => Kind::Keyword(STATES),
+            Self::OwnerAffiliation => Kind::Keyword(AFFILIATIONS),
+        }
+    }
+
+    /// The value of the field for `pc` of `dir`, if it has one.
+    pub fn value(&self, dir: &PcDirectory, pc: &PcDirectoryEntry) -> Option<Value> {
+        let hw = &pc.hardware;
+        let owner = || pc.owner.as_deref();
+        // The teams of the owner, if they are in any.
+        let teams = || {
+            let owner = pc.owner_id()?;
+            let teams: Vec<_> = dir
+                .org()
+                .teams()
+                .filter(|(_, team)| team.members.contains(&owner))
+                .collect();
+            (!teams.is_empty()).then_some(teams)
+        };
+        let text = |s: &str| Some(Value::Text(s.to_owned()));
+        let keyword = |s: &str| Some(Value::Keyword(s.to_owned()));
+        match self {
+            Self::Id => Some(Value::Number(pc.id() as u64)),
+            Self::Os => Some(Value::Os(pc.os())),
+            Self::OsFamily => keyword(match pc.os().family() {
+                OsFamily::Windows => "windows",
+                OsFamily::MacOs => "macos",
+                OsFamily::Linux => "linux",
+            }),
+            Self::OsDistribution => pc.os().distribution().and_then(|d| text(&d.to_string())),
+            Self::OsRelease => pc.os().distribution_release().and_then(text),
+            Self::Ram => Some(Value::Size(hw.ram)),
+            Self::Storage => Some(Value::Size(hw.storage())),
+            Self::Cores => hw.cpu.as_ref().map(|c| Value::Number(c.cores.into())),
+            Self::CpuModel => hw.cpu.as_ref().and_then(|c| text(&c.model)),
+            Self::Flags => Some(Value::Flags(hw.flags.clone())),
+            Self::Serial => hw.serial_number.as_deref().and_then(text),
+            Self::Purchased => hw.purchase_date.map(Value::Date),
+            Self::State => keyword(match pc.operational_state() {
+                OperationalState::On => "on",
+                OperationalState::Off => "off",
+                OperationalState::BeingMaintained { .. } => "maintenance",
+            }),
+            Self::OwnerFirst => owner().and_then(|p| text(&p.first)),
+            Self::OwnerLast => owner().and_then(|p| text(&p.last)),
+            Self::OwnerEmail => owner().and_then(|p| text(p.email.as_ref())),
+            Self::OwnerAffiliation => owner().and_then(|p: &Person| {
+                keyword(match p.affiliation {
+                    Affiliation::Employee { .. } => "employee",
+                    Affiliation::Contractor { .. } => "contractor",
+                    Affiliation::Intern { .. } => "intern",
+                })
+            }),
+            Self::OwnerCompany => owner().and_then(|p| match &p.affiliation {
+                Affiliation::Contractor { company_name, .. } => text(company_name),
+                _ => None,
+            }),
+            Self::Site => pc.location().and_then(|l| text(l.site())),
+            Self::Location => pc.location().and_then(|l| text(&l.to_string())),
+            Self::Team => teams().map(|teams| {
+                Value::Names(teams.into_iter().map(|(name, _)| name.to_owned()).collect())
+            }),
+            Self::Department => teams().map(|teams| {
+                let departments: BTreeSet<_> = teams
+                    .into_iter()
+                    .map(|(_, team)| team.department.clone())
+                    .collect();
+                Value::Names(departments.into_iter().collect())
+            }),
+        }
+    }
+
+    fn supports(&self, op: Op) -> bool {
+        match self.kind() {
+            Kind::Number | Kind::Size | Kind::Date | Kind::Os => op != Op::Contains,
+            Kind::Text => matches!(op, Op::Eq | Op::Ne | Op::Contains),
+            Kind::Keyword(_) => matches!(op, Op::Eq | Op::Ne),
+            Kind::Flags => op == Op::Contains,
+        }
+    }
+
+    /// Parse a value for this field.
+    fn parse_value(&self, s: &str) -> Result<Value, String> {
+        match self.kind() {
+            Kind::Number => s.parse().map(Value::Number).map_err(|_| "a number".into()),
+            Kind::Size => s
+                .parse()
+                .map(Value::Size)
+                .map_err(|e| format!("a size like \"32GiB\" ({e})")),
+            Kind::Os => s
+                .parse()
+                .map(Value::Os)
+                .map_err(|e| format!("an operating system like \"Windows 11\" ({e})")),
+            Kind::Text => Ok(Value::Text(s.into())),
+            Kind::Date => s
+                .parse()
+                .map(Value::Date)
+                .map_err(|_| "a date like 2024-01-31".into()),
+            Kind::Flags => Ok(Value::Flags(s.split(',').map(CpuFlag::from_name).collect())),
+            Kind::Keyword(words) => {
+                let word = s.to_lowercase();
+                match words.contains(&word.as_str()) {
+                    true => Ok(Value::Keyword(word)),
+                    false => Err(format!("one of {}", words.join(", "))),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|f| f.name() == s).ok_or(())
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Eq => "=",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::Contains => "~",
+        })
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Size(n) => write!(f, "{n}"),
+            Self::Os(os) => write!(f, "{os}"),
+            Self::Text(s) | Self::Keyword(s) => f.write_str(s),
+            Self::Names(names) => f.write_str(&names.join(", ")),
+            Self::Date(d) => write!(f, "{d}"),
+            Self::Flags(flags) => write!(f, "{flags}"),
+        }
+    }
+}
+
+impl Value {
+    /// Compare two values of the same field for sorting. Operating systems of
+    /// different families are ordered by family.
+    pub fn sort_cmp(&self, other: &Value) -> Ordering {
+        use Value::*;
+        match (self, other) {
+            (Number(a), Number(b)) => a.cmp(b),
+            (Size(a), Size(b)) => a.cmp(b),
+            (Os(a), Os(b)) => a
+                .cmp_release(b)
+                .unwrap_or_else(|| a.family().cmp(&b.family())),
+            (Text(a), Text(b)) | (Keyword(a), Keyword(b)) => a.cmp(b),
+            (Date(a), Date(b)) => a.cmp(b),
+            (Flags(a), Flags(b)) => a.len().cmp(&b.len()),
+            (Names(a), Names(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        }
+    }
+
+    fn matches(&self, op: Op, rhs: &Value) -> bool {
+        use Value::*;
+        let ordering = match (self, rhs) {
+            (Names(names), _) => {
+                let mut names = names.iter().map(|name| Text(name.clone()));
+                return match op {
+                    Op::Ne => names.all(|name| name.matches(op, rhs)),
+                    _ => names.any(|name| name.matches(op, rhs)),
+                };
+            }
+            (Text(a), Text(b)) if op == Op::Contains => {
+                return a.to_lowercase().contains(&b.to_lowercase())
+            }
+            (Flags(a), Flags(b)) => return op == Op::Contains && a.is_superset(b),
+            // Releases of different families cannot be compared.
+            (Os(a), Os(b)) => match a.cmp_release(b) {
+                Some(ordering) => ordering,
+                None => return op == Op::Ne,
+            },
+            _ => self.sort_cmp(rhs),
+        };
+        match op {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+            Op::Contains => false,
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, dir: &PcDirectory, pc: &PcDirectoryEntry) -> bool {
+        match self {
+            Self::Compare { field, op, value } => field
+                .value(dir, pc)
+                .is_some_and(|actual| actual.matches(*op, value)),
+            Self::Not(e) => !e.eval(dir, pc),
+            Self::And(a, b) => a.eval(dir, pc) && b.eval(dir, pc),
+            Self::Or(a, b) => a.eval(dir, pc) || b.eval(dir, pc),
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(s: &str) -> Result<Self, QueryError> {
+        if s.len() > MAX_QUERY_LEN {
+            return Err(QueryError::TooLong {
+                pos: MAX_QUERY_LEN,
+                max: MAX_QUERY_LEN,
+            });
+        }
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            end: s.len(),
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(Self { expr }),
+            Some(token) => Err(parser.unexpected(token, "and, or or the end of the query")),
+        }
+    }
+
+    /// Whether `pc` of `dir` matches the query.
+    pub fn matches(&self, dir: &PcDirectory, pc: &PcDirectoryEntry) -> bool {
+        self.expr.eval(dir, pc)
+    }
+
+    /// The PCs of the directory matching the query.
+    pub fn filter<'a>(
+        &'a self,
+        dir: &'a PcDirectory,
+    ) -> impl Iterator<Item = &'a PcDirectoryEntry> {
+        dir.iter_pcs().filter(|pc| self.matches(dir, pc))
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    /// An unquoted word, e.g. a field name, a keyword or a value.
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    pos: usize,
+    kind: TokenKind,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TokenKind::Word(w) => write!(f, "{w:?}"),
+            TokenKind::Quoted(s) => write!(f, "the string {s:?}"),
+            TokenKind::Op(op) => write!(f, "{op:?}", op = op.to_string()),
+            TokenKind::Open => write!(f, "\"(\""),
+            TokenKind::Close => write!(f, "\")\""),
+        }
+    }
+}
+
+fn is_special(c: char) -> bool {
+    c.is_whitespace() || "()=!<>~\"".contains(c)
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = vec![];
+    let mut chars = s.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '(' => TokenKind::Open,
+            ')' => TokenKind::Close,
+            '~' => TokenKind::Op(Op::Contains),
+            '=' => {
+                // Allow "==" as well.
+                chars.next_if(|(_, c)| *c == '=');
+                TokenKind::Op(Op::Eq)
+            }
+            '<' if chars.next_if(|(_, c)| *c == '=').is_some() => TokenKind::Op(Op::Le),
+            '<' => TokenKind::Op(Op::Lt),
+            '>' if chars.next_if(|(_, c)| *c == '=').is_some() => TokenKind::Op(Op::Ge),
+            '>' => TokenKind::Op(Op::Gt),
+            '!' if chars.next_if(|(_, c)| *c == '=').is_some() => TokenKind::Op(Op::Ne),
+            '!' => {
+                return Err(QueryError::Syntax {
+                    pos,
+                    expected: "\"!=\"",
+                    found: "\"!\"".into(),
+                })
+            }
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(QueryError::UnterminatedString { pos }),
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => value.push(c),
+                            None => return Err(QueryError::UnterminatedString { pos }),
+                        },
+                        Some((_, c)) => value.push(c),
+                    }
+                }
+                TokenKind::Quoted(value)
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some((_, c)) = chars.next_if(|(_, c)| !is_special(*c)) {
+                    word.push(c);
+                }
+                TokenKind::Word(word)
+            }
+        };
+        tokens.push(Token { pos, kind });
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    /// The position of the end of the query, for errors.
+    end: usize,
+    /// How many `not`s and parentheses enclose the next token.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn unexpected(&self, token: &Token, expected: &'static str) -> QueryError {
+        QueryError::Syntax {
+            pos: token.pos,
+            expected,
+            found: token.to_string(),
+        }
+    }
+
+    fn end_of_query(&self, expected: &'static str) -> QueryError {
+        QueryError::Syntax {
+            pos: self.end,
+            expected,
+            found: "the end of the query".into(),
+        }
+    }
+
+    fn expect(&mut self, expected: &'static str) -> Result<Token, QueryError> {
+        self.advance().ok_or_else(|| self.end_of_query(expected))
+    }
+
+    /// Consume the next token if it is the keyword `keyword`.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(
+            self.peek(),
+            Some(Token { kind: TokenKind::Word(w), .. }) if w.eq_ignore_ascii_case(keyword)
+        );
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, QueryError> {
+        if self.depth == MAX_QUERY_DEPTH {
+            return Err(QueryError::TooDeep {
+                pos: self.peek().map_or(self.end, |t| t.pos),
+                max: MAX_QUERY_DEPTH,
+            });
+        }
+        self.depth += 1;
+        let expr = self.unary();
+        self.depth -= 1;
+        expr
+    }
+
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if matches!(
+            self.peek(),
+            Some(Token {
+                kind: TokenKind::Open,
+                ..
+            })
+        ) {
+            self.next += 1;
+            let expr = self.or()?;
+            let token = self.expect("\")\"")?;
+            return match token.kind {
+                TokenKind::Close => Ok(expr),
+                _ => Err(self.unexpected(&token, "\")\"")),
+            };
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, QueryError> {
+        let token = self.expect("a field")?;
+        let TokenKind::Word(name) = &token.kind else {
+            return Err(self.unexpected(&token, "a field"));
+        };
+        let field: Field = name.parse().map_err(|_| QueryError::UnknownField {
+            pos: token.pos,
+            name: name.clone(),
+        })?;
+
+        let token = self.expect("an operator")?;
+        let TokenKind::Op(op) = token.kind else {
+            return Err(self.unexpected(&token, "an operator"));
+        };
+        if !field.supports(op) {
+            return Err(QueryError::UnsupportedOperator {
+                pos: token.pos,
+                field: field.name(),
+                op,
+            });
+        }
+
+        let token = self.expect("a value")?;
+        let (TokenKind::Word(raw) | TokenKind::Quoted(raw)) = &token.kind else {
+            return Err(self.unexpected(&token, "a value"));
+        };
+        let value = field
+            .parse_value(raw)
+            .map_err(|expected| QueryError::InvalidValue {
+                pos: token.pos,
+                field: field.name(),
+                value: raw.clone(),
+                expected,
+            })?;
+        Ok(Expr::Compare { field, op, value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pc_directory::get_directory;
+
+    use super::*;
+
+    fn ids(query: &str) -> Vec<usize> {
+        let dir = get_directory();
+        let query = Query::parse(query).unwrap();
+        query.filter(&dir).map(|pc| pc.id()).collect()
+    }
+
+    #[test]
+    fn test_queries() {
+        assert_eq!(
+            ids(
+                r#"os < "Windows 11" and ram >= 16GiB and owner.affiliation = contractor and state = on"#
+            ),
+            vec![4]
+        );
+        assert_eq!(ids("ram >= 32GiB and os.family = linux"), vec![1, 5]);
+        assert_eq!(ids("flags ~ avx,sse"), vec![1, 5]);
+        assert_eq!(
+            ids("owner.email ~ OVERKILL or owner.first = Sue"),
+            vec![1, 2]
+        );
+        assert_eq!(ids("not (id > 1) and cpu.model ~ ryzen"), vec![0, 1]);
+        assert_eq!(ids("owner.company = minisoft"), vec![4]);
+        assert_eq!(ids("serial = ITC-1003"), vec![3]);
+        // Windows and Linux releases are not comparable.
+        assert_eq!(ids(r#"os >= "Linux 6.0""#), vec![1, 5]);
+        assert_eq!(ids("purchased < 2024-01-01"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_state() {
+        let dir = get_directory();
+        let query = Query::parse("state = maintenance").unwrap();
+        let _handle = dir.acquire_maintenance_lock(3, "upgrade").unwrap();
+        let ids: Vec<_> = query.filter(&dir).map(|pc| pc.id()).collect();
+        assert_eq!(ids, vec![3]);
+    }
+
+    #[test]
+    fn test_distribution() {
+        let dir = get_directory();
+        let handle = dir.acquire_maintenance_lock(5, "upgrade").unwrap();
+        handle
+            .update_os("Ubuntu 22.04 Linux 6.5".parse().unwrap())
+            .unwrap();
+        drop(handle);
+        for (query, expected) in [
+            ("os.distribution = Ubuntu", vec![5]),
+            ("os.release = 22.04", vec![5]),
+            // The kernel version is not the release.
+            ("os.release = 6.5", vec![]),
+            (r#"os >= "Linux 6.22""#, vec![1]),
+        ] {
+            let query = Query::parse(query).unwrap();
+            let ids: Vec<_> = query.filter(&dir).map(|pc| pc.id()).collect();
+            assert_eq!(ids, expected);
+        }
+    }
+
+    #[test]
+    fn test_teams() {
+        let mut dir = get_directory();
+        let maria = dir.get_pc(0).unwrap().owner_id().unwrap();
+        dir.org_mut().add_member("facilities", maria).unwrap();
+        for (query, expected) in [
+            ("team = platform", vec![1, 4, 5]),
+            ("team = facilities", vec![0, 3]),
+            // Maria is in apps as well.
+            ("team != apps", vec![1, 3, 4, 5]),
+            ("team ~ FAC", vec![0, 3]),
+            ("department = Operations and team = apps", vec![0]),
+            ("department != Engineering", vec![3]),
+        ] {
+            let parsed = Query::parse(query).unwrap();
+            let ids: Vec<_> = parsed.filter(&dir).map(|pc| pc.id()).collect();
+            assert_eq!(ids, expected, "{query}");
+        }
+        assert_eq!(
+            Field::Team
+                .value(&dir, dir.get_pc(0).unwrap())
+                .unwrap()
+                .to_string(),
+            "apps, facilities"
+        );
+    }
+
+    #[test]
+    fn test_location() {
+        let mut dir = get_directory();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        for (pc, to) in [(0, "Zurich/HQ/3/3.14"), (2, "Bern/Main/1")] {
+            dir.move_pc(pc, Some(to.parse().unwrap()), date).unwrap();
+        }
+        let ids = |query: &str| {
+            let query = Query::parse(query).unwrap();
+            query.filter(&dir).map(|pc| pc.id()).collect::<Vec<_>>()
+        };
+        assert_eq!(ids("site = Zurich"), vec![0]);
+        assert_eq!(ids("location ~ Main/1 or location ~ 3.14"), vec![0, 2]);
+        assert_eq!(ids("not site = Zurich"), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = Query::parse("ram >= 32XiB").unwrap_err();
+        assert!(matches!(err, QueryError::InvalidValue { pos: 7, .. }));
+        assert_eq!(
+            err.show("ram >= 32XiB").lines().nth(1).unwrap(),
+            r#"       ^ ram expects a size like "32GiB" (Unknown unit: XiB), got "32XiB"."#
+        );
+        assert!(matches!(
+            Query::parse("rom = 1"),
+            Err(QueryError::UnknownField { pos: 0, .. })
+        ));
+        assert!(matches!(
+            Query::parse("state < on"),
+            Err(QueryError::UnsupportedOperator {
+                pos: 6,
+                op: Op::Lt,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Query::parse("state = on and"),
+            Err(QueryError::Syntax { pos: 14, .. })
+        ));
+        assert!(matches!(
+            Query::parse("(state = on"),
+            Err(QueryError::Syntax { .. })
+        ));
+        assert!(matches!(
+            Query::parse("os = \"Windows"),
+            Err(QueryError::UnterminatedString { pos: 5 })
+        ));
+        assert!(matches!(
+            Query::parse("state = on off"),
+            Err(QueryError::Syntax { pos: 11, .. })
+        ));
+    }
+
+    #[test]
+    fn test_limits() {
+        let deep = format!("{}state = on", "not ".repeat(200_000));
+        assert!(matches!(
+            Query::parse(&deep),
+            Err(QueryError::TooLong { .. })
+        ));
+        let nots = "not ".repeat(MAX_QUERY_DEPTH);
+        assert!(matches!(
+            Query::parse(&format!("{nots}state = on")),
+            Err(QueryError::TooDeep { pos, .. }) if pos == nots.len()
+        ));
+        let parens = "(".repeat(MAX_QUERY_DEPTH);
+        assert!(matches!(
+            Query::parse(&format!("{parens}state = on")),
+            Err(QueryError::TooDeep { .. })
+        ));
+        let nots = "not ".repeat(MAX_QUERY_DEPTH - 1);
+        assert!(Query::parse(&format!("{nots}state = on")).is_ok());
+        let both = format!(
+            "{}state = on{}",
+            "(not ".repeat(MAX_QUERY_DEPTH / 2 - 1),
+            ")".repeat(MAX_QUERY_DEPTH / 2 - 1)
+        );
+        assert!(Query::parse(&both).is_ok());
+    }
+}
@@ -0,0 +1,537 @@
+//! Summaries of the fleet.
+//!
+//! A [FleetReport] aggregates a [PcDirectory]: how many PCs run which
+//! operating system, how much RAM they have, which CPU features they support,
+//! who owns them and which of them are currently unavailable. It is rendered
+//! as plain text tables, CSV or JSON, see [ReportFormat]. Other reports
+//! implement [Report] to be rendered the same way.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    bytes::NumBytes,
+    cpu::{CpuFlag, CpuFlags},
+    os::OperatingSystem,
+    pc_directory::{OperationalState, PcDirectory, PcDirectoryEntry},
+    person::{Affiliation, EmailAddr},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FleetReport {
+    /// The number of PCs in the directory.
+    pub pcs: usize,
+    pub os_families: Vec<Count>,
+    /// PCs per release, e.g. "Windows 11", ordered by family and version.
+    pub os_releases: Vec<Count>,
+    /// PCs per amount of RAM, smallest first.
+    pub ram: Vec<Count>,
+    pub cpu_flags: Vec<Coverage>,
+    /// PCs per affiliation of their owner.
+    pub affiliations: Vec<Count>,
+    /// PCs that are on, off or being maintained.
+    pub states: Vec<Count>,
+    /// PCs that are off or being maintained.
+    pub unavailable: Vec<UnavailablePc>,
+    /// Owners of more than one PC.
+    pub multi_pc_owners: Vec<MultiPcOwner>,
+}
+
+/// The number of PCs with some property.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Count {
+    pub name: String,
+    pub pcs: usize,
+}
+
+/// How many PCs support a CPU feature.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Coverage {
+    pub flag: CpuFlag,
+    pub pcs: usize,
+    /// The share of all PCs, between 0 and 1.
+    pub share: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnavailablePc {
+    pub id: usize,
+    pub state: String,
+    /// Why the PC is being maintained.
+    pub reason: Option<String>,
+    pub owner: Option<EmailAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MultiPcOwner {
+    pub email: EmailAddr,
+    pub name: String,
+    pub pcs: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Aligned tables for humans.
+    Text,
+    /// One table after the other; the first column of every row names the
+    /// table it belongs to.
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Unknown report format {0:?}; use text, csv or json.")]
+pub struct UnknownFormat(String);
+
+impl FromStr for ReportFormat {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(UnknownFormat(s.into())),
+        }
+    }
+}
+
+/// Something that can be rendered in any [ReportFormat].
+pub trait Report: Serialize {
+    /// A line printed above the tables of a text report.
+    fn summary(&self) -> String;
+
+    /// The report as tables, in the order they are rendered.
+    fn tables(&self) -> Vec<Table>;
+
+    fn render(&self, format: ReportFormat) -> String {
+        let mut out = String::new();
+        match format {
+            ReportFormat::Text => {
+                writeln!(out, "{}", self.summary()).unwrap();
+                for table in self.tables() {
+                    writeln!(out).unwrap();
+                    write!(out, "{table}").unwrap();
+                }
+            }
+            ReportFormat::Csv => {
+                for table in self.tables() {
+                    table.write_csv(&mut out).unwrap();
+                }
+            }
+            ReportFormat::Json => {
+                out = serde_json::to_string_pretty(self).expect("reports are serializable");
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+/// A table of a report, before it is rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    /// A short name, used to tell the tables in a CSV file apart.
+    pub name: &'static str,
+    pub title: &'static str,
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+// Count how often each key occurs, keeping the order of the map.
+fn count<K: Ord>(keys: impl Iterator<Item = K>) -> BTreeMap<K, usize> {
+    let mut counts = BTreeMap::new();
+    for key in keys {
+        *counts.entry(key).or_insert(0) += 1;
+    }
+    counts
+}
+
+impl FleetReport {
+    pub fn new(dir: &PcDirectory) -> Self {
+        let pcs = dir.iter_pcs().count();
+
+        let os_families = count(dir.iter_pcs().map(|pc| pc.os().family()))
+            .into_iter()
+            .map(|(family, pcs)| Count {
+                name: family.to_string(),
+                pcs,
+            })
+            .collect();
+
+        // Releases are ordered by family first, then by version.
+        let mut releases: HashMap<String, (OperatingSystem, usize)> = HashMap::new();
+        for os in dir.iter_pcs().map(|pc| pc.os()) {
+            releases.entry(os.release_name()).or_insert((os, 0)).1 += 1;
+        }
+        let mut releases: Vec<_> = releases.into_iter().collect();
+        releases.sort_by(|(_, (a, _)), (_, (b, _))| {
+            a.cmp_release(b)
+                .unwrap_or_else(|| a.family().cmp(&b.family()))
+        });
+        let os_releases = releases
+            .into_iter()
+            .map(|(name, (_, pcs))| Count { name, pcs })
+            .collect();
+
+        let ram = count(dir.iter_pcs().map(|pc| pc.hardware.ram))
+            .into_iter()
+            .map(|(ram, pcs): (NumBytes, _)| Count {
+                name: ram.to_string(),
+                pcs,
+            })
+            .collect();
+
+        let all_flags: CpuFlags = dir
+            .iter_pcs()
+            .flat_map(|pc| pc.hardware.flags.iter())
+            .collect();
+        let cpu_flags = all_flags
+            .iter()
+            .map(|flag| {
+                let supported = dir
+                    .iter_pcs()
+                    .filter(|pc| pc.hardware.flags.contains(&flag))
+                    .count();
+                Coverage {
+                    flag,
+                    pcs: supported,
+                    share: supported as f64 / pcs as f64,
+                }
+            })
+            .collect();
+
+        let affiliation_name = |pc: &PcDirectoryEntry| match pc.owner.as_deref() {
+            Some(owner) => match owner.affiliation {
+                Affiliation::Employee { .. } => "employee",
+                Affiliation::Contractor { .. } => "contractor",
+                Affiliation::Intern { .. } => "intern",
+            },
+            None => "no owner",
+        };
+        let affiliations = ["employee", "contractor", "intern", "no owner"]
+            .into_iter()
+            .map(|name| Count {
+                name: name.into(),
+                pcs: dir
+                    .iter_pcs()
+                    .filter(|pc| affiliation_name(pc) == name)
+                    .count(),
+            })
+            .collect();
+
+        let state_name = |state: &OperationalState| match state {
+            OperationalState::On => "on",
+            OperationalState::Off => "off",
+            OperationalState::BeingMaintained { .. } => "maintenance",
+        };
+        let states = ["on", "off", "maintenance"]
+            .into_iter()
+            .map(|name| Count {
+                name: name.into(),
+                pcs: dir
+                    .iter_pcs()
+                    .filter(|pc| state_name(&pc.operational_state()) == name)
+                    .count(),
+            })
+            .collect();
+        let unavailable = dir
+            .iter_pcs()
+            .filter_map(|pc| {
+                let state = pc.operational_state();
+                let reason = match &state {
+                    OperationalState::On => return None,
+                    OperationalState::Off => None,
+                    OperationalState::BeingMaintained { reason } => Some(reason.clone()),
+                };
+                Some(UnavailablePc {
+                    id: pc.id(),
+                    state: state_name(&state).into(),
+                    reason,
+                    owner: pc.owner.as_ref().map(|p| p.email.clone()),
+                })
+            })
+            .collect();
+
+        let mut owners: BTreeMap<&EmailAddr, MultiPcOwner> = BTreeMap::new();
+        for pc in dir.iter_pcs() {
+            let Some(owner) = pc.owner.as_deref() else {
+                continue;
+            };
+            owners
+                .entry(&owner.email)
+                .or_insert_with(|| MultiPcOwner {
+                    email: owner.email.clone(),
+                    name: format!("{} {}", owner.first, owner.last),
+                    pcs: vec![],
+                })
+                .pcs
+                .push(pc.id());
+        }
+        let multi_pc_owners = owners
+            .into_values()
+            .filter(|owner| owner.pcs.len() > 1)
+            .collect();
+
+        Self {
+            pcs,
+            os_families,
+            os_releases,
+            ram,
+            cpu_flags,
+            affiliations,
+            states,
+            unavailable,
+            multi_pc_owners,
+        }
+    }
+}
+
+impl Report for FleetReport {
+    fn summary(&self) -> String {
+        format!("{} PCs", self.pcs)
+    }
+
+    fn tables(&self) -> Vec<Table> {
+        let counts = |name, title, column, counts: &[Count]| Table {
+            name,
+            title,
+            columns: vec![column, "PCs"],
+            rows: counts
+                .iter()
+                .map(|c| vec![c.name.clone(), c.pcs.to_string()])
+                .collect(),
+        };
+        vec![
+            counts(
+                "os_families",
+                "Operating system families",
+                "family",
+                &self.os_families,
+            ),
+            counts(
+                "os_releases",
+                "Operating system releases",
+                "release",
+                &self.os_releases,
+            ),
+            counts("ram", "RAM", "RAM", &self.ram),
+            Table {
+                name: "cpu_flags",
+                title: "CPU features",
+                columns: vec!["feature", "PCs", "share"],
+                rows: self
+                    .cpu_flags
+                    .iter()
+                    .map(|c| {
+                        let percent = format!("{:.0}%", c.share * 100.0);
+                        vec![c.flag.to_string(), c.pcs.to_string(), percent]
+                    })
+                    .collect(),
+            },
+            counts(
+                "affiliations",
+                "Owners' affiliations",
+                "affiliation",
+                &self.affiliations,
+            ),
+            counts("states", "Operational states", "state", &self.states),
+            Table {
+                name: "unavailable",
+                title: "Unavailable PCs",
+                columns: vec!["PC", "state", "reason", "owner"],
+                rows: self
+                    .unavailable
+                    .iter()
+                    .map(|pc| {
+                        vec![
+                            pc.id.to_string(),
+                            pc.state.clone(),
+                            pc.reason.clone().unwrap_or_default(),
+                            pc.owner.as_ref().map_or("", |e| e.as_ref()).into(),
+                        ]
+                    })
+                    .collect(),
+            },
+            Table {
+                name: "multi_pc_owners",
+                title: "Owners of several PCs",
+                columns: vec!["owner", "name", "PCs"],
+                rows: self
+                    .multi_pc_owners
+                    .iter()
+                    .map(|o| {
+                        let pcs: Vec<_> = o.pcs.iter().map(|id| id.to_string()).collect();
+                        vec![o.email.as_ref().into(), o.name.clone(), pcs.join(" ")]
+                    })
+                    .collect(),
+            },
+        ]
+    }
+}
+
+impl Table {
+    /// Write the table as CSV, prefixing the header and every row with the
+    /// name of the table.
+    pub fn write_csv(&self, out: &mut impl Write) -> fmt::Result {
+        let header = self.columns.iter().map(|c| c.to_string());
+        for row in std::iter::once(header.collect()).chain(self.rows.iter().cloned()) {
+            let fields: Vec<_> = std::iter::once(self.name.to_string())
+                .chain(row)
+                .map(|field| csv_field(&field))
+                .collect();
+            writeln!(out, "{}", fields.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+// Quote fields that would otherwise be misread.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+/// The title, followed by the columns, aligned to the widest value. Tables
+/// without rows say so.
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.title)?;
+        if self.rows.is_empty() {
+            return writeln!(f, "  none");
+        }
+        let widths: Vec<_> = (0..self.columns.len())
+            .map(|i| {
+                self.rows
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([self.columns[i].chars().count()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let line = |f: &mut fmt::Formatter<'_>, cells: Vec<&str>| {
+            let cells: Vec<_> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect();
+            writeln!(f, "  {}", cells.join("  ").trim_end())
+        };
+        line(f, self.columns.clone())?;
+        let rule: Vec<_> = widths.iter().map(|w| "-".repeat(*w)).collect();
+        line(f, rule.iter().map(String::as_str).collect())?;
+        for row in &self.rows {
+            line(f, row.iter().map(String::as_str).collect())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        pc::{PcBuilder, PcHardware},
+        pc_directory::get_directory,
+        person::PersonBuilder,
+    };
+
+    use super::*;
+
+    fn names(counts: &[Count]) -> Vec<(&str, usize)> {
+        counts.iter().map(|c| (c.name.as_str(), c.pcs)).collect()
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let mut dir = get_directory();
+        // A second PC for Don.
+        let don = dir.get_pc(3).unwrap().owner.as_deref().unwrap().clone();
+        dir.add_pc(PcBuilder {
+            owner: Some(don),
+            hardware: Some(PcHardware::beefy_workstation()),
+            os: Some(OperatingSystem::linux(6, 22)),
+        })
+        .unwrap();
+        let _handle = dir.acquire_maintenance_lock(1, "new disk").unwrap();
+
+        let report = FleetReport::new(&dir);
+        assert_eq!(report.pcs, 7);
+        assert_eq!(
+            names(&report.os_releases),
+            vec![
+                ("Windows Vista", 2),
+                ("Windows 11", 1),
+                ("macOS 10.14", 1),
+                ("Linux 6.22", 3)
+            ]
+        );
+        assert_eq!(names(&report.os_families)[0], ("Windows", 3));
+        assert_eq!(names(&report.ram)[0], ("16 GiB", 2));
+        assert_eq!(
+            names(&report.affiliations),
+            vec![
+                ("employee", 5),
+                ("contractor", 1),
+                ("intern", 1),
+                ("no owner", 0)
+            ]
+        );
+        assert_eq!(
+            names(&report.states),
+            vec![("on", 6), ("off", 0), ("maintenance", 1)]
+        );
+        assert_eq!(report.unavailable[0].reason.as_deref(), Some("new disk"));
+
+        let mmx = &report.cpu_flags[0];
+        assert_eq!((&mmx.flag, mmx.pcs, mmx.share), (&CpuFlag::MMX, 7, 1.0));
+        assert_eq!(report.multi_pc_owners.len(), 1);
+        assert_eq!(report.multi_pc_owners[0].pcs, vec![3, 6]);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut dir = PcDirectory::default();
+        let owner = PersonBuilder::new()
+            .with_first_name("Anna")
+            .with_last_name("Muster, jun.")
+            .with_email_address("anna@muster.ch")
+            .with_affiliation(Affiliation::Intern { engagement: None })
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            dir.add_pc(PcBuilder {
+                owner: Some(owner.clone()),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        let report = FleetReport::new(&dir);
+
+        let text = report.render(ReportFormat::Text);
+        assert!(text.starts_with("2 PCs\n"));
+        assert!(text.contains(
+            "Operating system releases\n  release     PCs\n  ----------  ---\n  Windows 10  2\n"
+        ));
+        assert!(text.contains("Unavailable PCs\n  none\n"));
+
+        let csv = report.render(ReportFormat::Csv);
+        assert!(csv.contains("ram,RAM,PCs\nram,16 GiB,2\n"));
+        assert!(csv.contains("multi_pc_owners,anna@muster.ch,\"Anna Muster, jun.\",0 1\n"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&report.render(ReportFormat::Json)).unwrap();
+        assert_eq!(json["pcs"], 2);
+        assert_eq!(json["states"][0]["pcs"], 2);
+
+        assert_eq!("CSV".parse(), Ok(ReportFormat::Csv));
+        assert!("xml".parse::<ReportFormat>().is_err());
+    }
+}
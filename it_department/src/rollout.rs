@@ -0,0 +1,435 @@
+//! Staged operating system upgrades across the fleet.
+//!
+//! A [Rollout] first upgrades a small set of canaries. Every wave has to be
+//! confirmed healthy explicitly before the next one is upgraded. If a wave
+//! reports a failure, the rollout halts and, if configured, the PCs of that
+//! wave are rolled back to their previous operating system.
+//!
+//! The state of a rollout is plain data: it can be saved to a file and loaded
+//! again, e.g. after a restart, to continue where it left off.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    pc::OperatingSystem,
+    pc_directory::{PcDirectory, PcDirectoryEntry},
+};
+
+/// How many PCs are upgraded per wave after the canaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WaveSize {
+    /// A fixed number of PCs per wave.
+    Count(usize),
+    /// A percentage of the PCs remaining after the canaries.
+    Percent(u8),
+}
+
+#[derive(Debug, Clone)]
+pub struct RolloutConfig {
+    pub target: OperatingSystem,
+    /// Number of PCs upgraded in the first wave.
+    pub canaries: usize,
+    pub wave_size: WaveSize,
+    /// Whether to restore the previous operating system of a failed wave.
+    pub rollback_on_failure: bool,
+}
+
+/// Where a rollout currently stands.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RolloutStage {
+    /// The given wave is ready to be upgraded.
+    Ready { wave: usize },
+    /// The given wave was upgraded and waits for a health confirmation.
+    AwaitingHealth { wave: usize },
+    /// The given wave failed; the rollout does not proceed.
+    Halted { wave: usize, reason: String },
+    /// All waves were upgraded and confirmed healthy.
+    Completed,
+}
+
+/// The outcome of the rollout for a single PC.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PcRolloutResult {
+    Pending,
+    Upgraded { previous: OperatingSystem },
+    Failed { error: String },
+    RolledBack { previous: OperatingSystem },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RolloutProgress {
+    pub pending: usize,
+    pub upgraded: usize,
+    pub failed: usize,
+    pub rolled_back: usize,
+}
+
+#[derive(Debug, Error)]
+pub enum RolloutError {
+    #[error("The rollout cannot {action} while it is in stage {stage:?}.")]
+    InvalidStage {
+        action: &'static str,
+        stage: RolloutStage,
+    },
+    #[error("Could not access the rollout file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The rollout file is malformed: {0}")]
+    Format(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rollout {
+    target: OperatingSystem,
+    rollback_on_failure: bool,
+    // PC ids per wave; the first wave contains the canaries.
+    waves: Vec<Vec<usize>>,
+    stage: RolloutStage,
+    results: BTreeMap<usize, PcRolloutResult>,
+}
+
+impl Rollout {
+    /// Plan a rollout for all PCs accepted by `selector` that do not run the
+    /// target operating system yet.
+    pub fn plan<F>(dir: &PcDirectory, config: RolloutConfig, selector: F) -> Self
+    where
+        F: Fn(&PcDirectoryEntry) -> bool,
+    {
+        let pcs: Vec<_> = dir
+            .iter_pcs()
+            .filter(|pc| selector(pc) && pc.os() != config.target)
+            .map(|pc| pc.id())
+            .collect();
+
+        let (canaries, rest) = pcs.split_at(config.canaries.min(pcs.len()));
+        let wave_size = match config.wave_size {
+            WaveSize::Count(n) => n,
+            WaveSize::Percent(p) => (rest.len() * p as usize).div_ceil(100),
+        }
+        .max(1);
+
+        let mut waves = vec![canaries.to_vec()];
+        waves.extend(rest.chunks(wave_size).map(<[_]>::to_vec));
+        waves.retain(|wave| !wave.is_empty());
+
+        Self {
+            target: config.target,
+            rollback_on_failure: config.rollback_on_failure,
+            results: pcs
+                .iter()
+                .map(|&id| (id, PcRolloutResult::Pending))
+                .collect(),
+            stage: if waves.is_empty() {
+                RolloutStage::Completed
+            } else {
+                RolloutStage::Ready { wave: 0 }
+            },
+            waves,
+        }
+    }
+
+    pub fn target(&self) -> &OperatingSystem {
+        &self.target
+    }
+
+    pub fn stage(&self) -> &RolloutStage {
+        &self.stage
+    }
+
+    /// The PC ids per wave. The first wave contains the canaries.
+    pub fn waves(&self) -> &[Vec<usize>] {
+        &self.waves
+    }
+
+    /// The result per PC id.
+    pub fn results(&self) -> &BTreeMap<usize, PcRolloutResult> {
+        &self.results
+    }
+
+    pub fn progress(&self) -> RolloutProgress {
+        let mut progress = RolloutProgress::default();
+        for result in self.results.values() {
+            match result {
+                PcRolloutResult::Pending => progress.pending += 1,
+                PcRolloutResult::Upgraded { .. } => progress.upgraded += 1,
+                PcRolloutResult::Failed { .. } => progress.failed += 1,
+                PcRolloutResult::RolledBack { .. } => progress.rolled_back += 1,
+            }
+        }
+        progress
+    }
+
+    /// Upgrade the next wave. If one of its PCs cannot be upgraded, the
+    /// rollout halts as if the wave had reported a failure.
+    pub fn advance(&mut self, dir: &PcDirectory) -> Result<&RolloutStage, RolloutError> {
+        let RolloutStage::Ready { wave } = self.stage else {
+            return Err(self.invalid_stage("advance"));
+        };
+        let reason = format!("OS rollout to {} (wave {wave})", self.target);
+
+        let mut failures = vec![];
+        for &id in self.waves[wave].iter() {
+            let previous = dir.get_pc(id).map(|pc| pc.os());
+            let upgrade = dir
+                .acquire_maintenance_lock(id, &reason)
+                .and_then(|handle| handle.update_os(self.target.clone()));
+            let result = match (upgrade, previous) {
+                (Ok(()), Some(previous)) => PcRolloutResult::Upgraded { previous },
+                (Ok(()), None) => unreachable!("PC {id} was just upgraded"),
+                (Err(e), _) => {
+                    failures.push(id);
+                    PcRolloutResult::Failed {
+                        error: e.to_string(),
+                    }
+                }
+            };
+            self.results.insert(id, result);
+        }
+
+        if failures.is_empty() {
+            self.stage = RolloutStage::AwaitingHealth { wave };
+        } else {
+            self.halt(dir, wave, format!("could not upgrade PCs {failures:?}"));
+        }
+        Ok(&self.stage)
+    }
+
+    /// Confirm that the last upgraded wave is healthy.
+    pub fn confirm_health(&mut self) -> Result<&RolloutStage, RolloutError> {
+        let RolloutStage::AwaitingHealth { wave } = self.stage else {
+            return Err(self.invalid_stage("confirm health"));
+        };
+        self.stage = if wave + 1 < self.waves.len() {
+            RolloutStage::Ready { wave: wave + 1 }
+        } else {
+            RolloutStage::Completed
+        };
+        Ok(&self.stage)
+    }
+
+    /// Report that the last upgraded wave is unhealthy.
+    pub fn report_failure<S: ToString>(
+        &mut self,
+        dir: &PcDirectory,
+        reason: S,
+    ) -> Result<&RolloutStage, RolloutError> {
+        let RolloutStage::AwaitingHealth { wave } = self.stage else {
+            return Err(self.invalid_stage("report a failure"));
+        };
+        self.halt(dir, wave, reason.to_string());
+        Ok(&self.stage)
+    }
+
+    /// Write the rollout state to a JSON file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), RolloutError> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Read a rollout previously written by [Rollout::save].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, RolloutError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    fn halt(&mut self, dir: &PcDirectory, wave: usize, reason: String) {
+        if self.rollback_on_failure {
+            self.roll_back(dir, wave);
+        }
+        self.stage = RolloutStage::Halted { wave, reason };
+    }
+
+    // Restore the previous operating system on all upgraded PCs of the wave.
+    // PCs that cannot be locked keep the target operating system. As the
+    // previous operating system ran on the PC before, it is compatible.
+    fn roll_back(&mut self, dir: &PcDirectory, wave: usize) {
+        let reason = format!("Rollback of OS rollout to {}", self.target);
+        for id in self.waves[wave].iter() {
+            let Some(PcRolloutResult::Upgraded { previous }) = self.results.get(id) else {
+                continue;
+            };
+            let rolled_back = dir
+                .acquire_maintenance_lock(*id, &reason)
+                .and_then(|handle| handle.update_os(previous.clone()));
+            if rolled_back.is_ok() {
+                let previous = previous.clone();
+                self.results
+                    .insert(*id, PcRolloutResult::RolledBack { previous });
+            }
+        }
+    }
+
+    fn invalid_stage(&self, action: &'static str) -> RolloutError {
+        RolloutError::InvalidStage {
+            action,
+            stage: self.stage.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compatibility::CompatibilityMatrix, os::WindowsRelease, pc_directory::get_directory,
+    };
+
+    use super::*;
+
+    fn windows11() -> OperatingSystem {
+        OperatingSystem::windows(WindowsRelease::Win11)
+    }
+
+    // Don's and Lex' PCs cannot run Windows 11.
+    fn compatible(pc: &PcDirectoryEntry) -> bool {
+        CompatibilityMatrix::builtin()
+            .check(&windows11(), &pc.hardware)
+            .is_ok()
+    }
+
+    fn config(canaries: usize, wave_size: WaveSize) -> RolloutConfig {
+        RolloutConfig {
+            target: windows11(),
+            canaries,
+            wave_size,
+            rollback_on_failure: true,
+        }
+    }
+
+    #[test]
+    fn test_plan_waves() {
+        let dir = get_directory();
+        // Maria already runs Windows 11, so five PCs remain.
+        let rollout = Rollout::plan(&dir, config(1, WaveSize::Percent(50)), |_| true);
+        assert_eq!(rollout.waves(), &[vec![1], vec![2, 3], vec![4, 5]]);
+
+        let rollout = Rollout::plan(&dir, config(2, WaveSize::Count(1)), |pc| {
+            pc.os() == OperatingSystem::windows(WindowsRelease::Vista)
+        });
+        assert_eq!(rollout.waves(), &[vec![3, 4]]);
+    }
+
+    #[test]
+    fn test_rollout_waits_for_health_confirmation() {
+        let dir = get_directory();
+        let mut rollout = Rollout::plan(&dir, config(1, WaveSize::Count(2)), compatible);
+
+        rollout.advance(&dir).unwrap();
+        assert_eq!(dir.get_pc(1).unwrap().os(), windows11());
+        assert_eq!(dir.get_pc(2).unwrap().os(), OperatingSystem::macos(10, 14));
+        assert!(rollout.advance(&dir).is_err());
+
+        while rollout.stage() != &RolloutStage::Completed {
+            rollout.confirm_health().unwrap();
+            if let RolloutStage::Ready { .. } = rollout.stage() {
+                rollout.advance(&dir).unwrap();
+            }
+        }
+        assert!(dir
+            .iter_pcs()
+            .filter(|pc| compatible(pc))
+            .all(|pc| pc.os() == windows11()));
+        assert_eq!(rollout.progress().upgraded, 3);
+    }
+
+    #[test]
+    fn test_failure_halts_and_rolls_back() {
+        let dir = get_directory();
+        let mut rollout = Rollout::plan(&dir, config(1, WaveSize::Count(2)), compatible);
+        rollout.advance(&dir).unwrap();
+        rollout.confirm_health().unwrap();
+        rollout.advance(&dir).unwrap();
+        rollout.report_failure(&dir, "mac users are angry").unwrap();
+
+        assert!(matches!(
+            rollout.stage(),
+            RolloutStage::Halted { wave: 1, .. }
+        ));
+        assert_eq!(dir.get_pc(2).unwrap().os(), OperatingSystem::macos(10, 14));
+        assert_eq!(dir.get_pc(5).unwrap().os(), OperatingSystem::linux(6, 22));
+        assert_eq!(dir.get_pc(1).unwrap().os(), windows11());
+        assert_eq!(
+            rollout.progress(),
+            RolloutProgress {
+                pending: 0,
+                upgraded: 1,
+                failed: 0,
+                rolled_back: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_locked_pc_halts_wave() {
+        let dir = get_directory();
+        let mut rollout = Rollout::plan(&dir, config(2, WaveSize::Count(2)), |_| true);
+        let _manual = dir.acquire_maintenance_lock(2, "manual fix").unwrap();
+
+        rollout.advance(&dir).unwrap();
+        assert!(matches!(
+            rollout.stage(),
+            RolloutStage::Halted { wave: 0, .. }
+        ));
+        assert!(matches!(
+            rollout.results()[&2],
+            PcRolloutResult::Failed { .. }
+        ));
+        // PC 1 was upgraded and then rolled back.
+        assert_eq!(dir.get_pc(1).unwrap().os(), OperatingSystem::linux(6, 22));
+    }
+
+    #[test]
+    fn test_incompatible_pc_halts_wave() {
+        let dir = get_directory();
+        let mut rollout = Rollout::plan(&dir, config(1, WaveSize::Count(2)), |_| true);
+        rollout.advance(&dir).unwrap();
+        rollout.confirm_health().unwrap();
+        rollout.advance(&dir).unwrap();
+
+        assert!(matches!(
+            rollout.stage(),
+            RolloutStage::Halted { wave: 1, .. }
+        ));
+        assert!(matches!(
+            &rollout.results()[&3],
+            PcRolloutResult::Failed { error } if error.contains("SEV")
+        ));
+        assert!(matches!(
+            rollout.results()[&2],
+            PcRolloutResult::RolledBack { .. }
+        ));
+    }
+
+    #[test]
+    fn test_resume_after_restart() {
+        let tmp_dir = std::env::var("TMPDIR").unwrap();
+        let dir_path = std::path::PathBuf::from(&tmp_dir).join("rollout_directory.json");
+        let rollout_path = std::path::PathBuf::from(&tmp_dir).join("rollout_state.json");
+
+        {
+            let dir = get_directory();
+            let mut rollout = Rollout::plan(&dir, config(1, WaveSize::Count(4)), compatible);
+            rollout.advance(&dir).unwrap();
+            dir.save(&dir_path).unwrap();
+            rollout.save(&rollout_path).unwrap();
+        }
+
+        let dir = PcDirectory::load(&dir_path).unwrap();
+        let mut rollout = Rollout::load(&rollout_path).unwrap();
+        assert_eq!(rollout.stage(), &RolloutStage::AwaitingHealth { wave: 0 });
+        rollout.confirm_health().unwrap();
+        rollout.advance(&dir).unwrap();
+        rollout.confirm_health().unwrap();
+        assert_eq!(rollout.stage(), &RolloutStage::Completed);
+        assert!(dir
+            .iter_pcs()
+            .filter(|pc| compatible(pc))
+            .all(|pc| pc.os() == windows11()));
+    }
+}
@@ -0,0 +1,440 @@
+//! Maintenance windows that are booked in advance.
+//!
+//! A [MaintenanceScheduler] keeps a calendar of bookings. It does not look at
+//! the clock itself: the current time is passed to [MaintenanceScheduler::tick]
+//! by whoever drives the scheduler. This keeps the scheduler deterministic and
+//! lets tests travel through time without sleeping.
+//!
+//! Whether someone may maintain the PCs is checked when they book the window
+//! with [MaintenanceScheduler::book_as]; the scheduler then acquires the locks
+//! on their behalf.
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDateTime;
+use thiserror::Error;
+
+use crate::{
+    access::{Caller, Permission},
+    location::Location,
+    pc_directory::{MaintenanceHandle, OperationalState, PcDirectory, PcDirectoryError},
+};
+
+/// Point in time as seen by the scheduler.
+pub type Timestamp = NaiveDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BookingId(usize);
+
+/// A maintenance window for one or more PCs.
+#[derive(Debug, Clone)]
+pub struct Booking {
+    pub id: BookingId,
+    /// Ids of the PCs to be maintained.
+    pub pcs: Vec<usize>,
+    /// Start of the window (inclusive).
+    pub start: Timestamp,
+    /// End of the window (exclusive).
+    pub end: Timestamp,
+    pub reason: String,
+}
+
+impl Booking {
+    fn overlaps(&self, start: Timestamp, end: Timestamp) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+/// What happened during a call to [MaintenanceScheduler::tick].
+#[derive(Debug)]
+pub enum SchedulerEvent {
+    /// The maintenance locks for all PCs of the booking were acquired.
+    Started(BookingId),
+    /// The window ended and the maintenance locks were released.
+    Finished(BookingId),
+    /// The maintenance locks could not be acquired. The scheduler tries again
+    /// on the next tick, as long as the window has not ended.
+    Failed {
+        id: BookingId,
+        error: PcDirectoryError,
+    },
+    /// The window ended before the maintenance could be started.
+    Expired(BookingId),
+}
+
+#[derive(Debug, Error)]
+pub enum SchedulerError {
+    #[error("The maintenance window must end after it starts.")]
+    EmptyWindow,
+    #[error("There is no PC with id {id}.")]
+    PcNotFound { id: usize },
+    #[error("PC {pc} is already booked in the overlapping window {booking:?}.")]
+    Overlapping { pc: usize, booking: BookingId },
+    #[error("PC {pc} is already being maintained: {reason}")]
+    InMaintenance { pc: usize, reason: String },
+    #[error("There is no booking with id {id:?}.")]
+    BookingNotFound { id: BookingId },
+    #[error("There are no PCs at {location}.")]
+    EmptyLocation { location: Location },
+    #[error(transparent)]
+    Directory(#[from] PcDirectoryError),
+}
+
+/// Calendar of maintenance windows for the PCs of a [PcDirectory].
+pub struct MaintenanceScheduler<'a> {
+    directory: &'a PcDirectory,
+    next_id: usize,
+    bookings: BTreeMap<BookingId, Booking>,
+    // The handles of bookings whose window is currently open. Dropping them
+    // ends the maintenance.
+    active: HashMap<BookingId, Vec<MaintenanceHandle<'a>>>,
+}
+
+impl<'a> MaintenanceScheduler<'a> {
+    pub fn new(directory: &'a PcDirectory) -> Self {
+        Self {
+            directory,
+            next_id: 0,
+            bookings: Default::default(),
+            active: Default::default(),
+        }
+    }
+
+    /// Book a maintenance window for the given PCs on behalf of `caller`.
+    ///
+    /// # Returns
+    ///
+    /// The id of the new booking, or an error if `caller` may not maintain
+    /// one of the PCs, the window is empty, refers to unknown PCs, overlaps
+    /// with another booking of one of the PCs, or if one of the PCs is
+    /// currently being maintained.
+    pub fn book_as<S: ToString>(
+        &mut self,
+        caller: &Caller,
+        pcs: impl IntoIterator<Item = usize>,
+        start: Timestamp,
+        end: Timestamp,
+        reason: S,
+    ) -> Result<BookingId, SchedulerError> {
+        let pcs: Vec<_> = pcs.into_iter().collect();
+        for &pc in &pcs {
+            match self
+                .directory
+                .authorize(caller, Permission::Maintain, Some(pc))
+            {
+                Err(PcDirectoryError::PcNotFound { id }) => {
+                    return Err(SchedulerError::PcNotFound { id })
+                }
+                result => result?,
+            }
+        }
+        self.book(pcs, start, end, reason)
+    }
+
+    // Book a maintenance window for the given PCs without checking who may
+    // maintain them.
+    pub(crate) fn book<S: ToString>(
+        &mut self,
+        pcs: impl IntoIterator<Item = usize>,
+        start: Timestamp,
+        end: Timestamp,
+        reason: S,
+    ) -> Result<BookingId, SchedulerError> {
+        if end <= start {
+            return Err(SchedulerError::EmptyWindow);
+        }
+        let mut pcs: Vec<_> = pcs.into_iter().collect();
+        pcs.sort_unstable();
+        pcs.dedup();
+
+        for &pc in pcs.iter() {
+            let Some(entry) = self.directory.get_pc(pc) else {
+                return Err(SchedulerError::PcNotFound { id: pc });
+            };
+            if let OperationalState::BeingMaintained { reason } = entry.operational_state() {
+                return Err(SchedulerError::InMaintenance { pc, reason });
+            }
+            if let Some(booking) = self
+                .bookings
+                .values()
+                .find(|b| b.pcs.contains(&pc) && b.overlaps(start, end))
+            {
+                return Err(SchedulerError::Overlapping {
+                    pc,
+                    booking: booking.id,
+                });
+            }
+        }
+
+        let id = BookingId(self.next_id);
+        self.next_id += 1;
+        self.bookings.insert(
+            id,
+            Booking {
+                id,
+                pcs,
+                start,
+                end,
+                reason: reason.to_string(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Book a maintenance window for all PCs at a location, e.g. a whole site
+    /// or a floor, on behalf of `caller`. PCs moved there later are not part
+    /// of the booking.
+    pub fn book_location_as<S: ToString>(
+        &mut self,
+        caller: &Caller,
+        location: &Location,
+        start: Timestamp,
+        end: Timestamp,
+        reason: S,
+    ) -> Result<BookingId, SchedulerError> {
+        let pcs: Vec<_> = self.directory.pcs_at(location).map(|pc| pc.id()).collect();
+        if pcs.is_empty() {
+            return Err(SchedulerError::EmptyLocation {
+                location: location.clone(),
+            });
+        }
+        self.book_as(caller, pcs, start, end, reason)
+    }
+
+    /// Cancel a booking. If its window is currently open, the maintenance
+    /// ends immediately.
+    pub fn cancel(&mut self, id: BookingId) -> Result<Booking, SchedulerError> {
+        self.active.remove(&id);
+        self.bookings
+            .remove(&id)
+            .ok_or(SchedulerError::BookingNotFound { id })
+    }
+
+    pub fn bookings(&self) -> impl Iterator<Item = &Booking> {
+        self.bookings.values()
+    }
+
+    /// Whether the window of the given booking is currently open.
+    pub fn is_active(&self, id: BookingId) -> bool {
+        self.active.contains_key(&id)
+    }
+
+    /// Advance the scheduler to `now`: close all windows that have ended and
+    /// open all windows that have started.
+    pub fn tick(&mut self, now: Timestamp) -> Vec<SchedulerEvent> {
+        let mut events = vec![];
+
+        let ended: Vec<_> = self
+            .bookings
+            .values()
+            .filter(|b| b.end <= now)
+            .map(|b| b.id)
+            .collect();
+        for id in ended {
+            self.bookings.remove(&id);
+            match self.active.remove(&id) {
+                // The handles are dropped here.
+                Some(_handles) => events.push(SchedulerEvent::Finished(id)),
+                None => events.push(SchedulerEvent::Expired(id)),
+            }
+        }
+
+        let due: Vec<_> = self
+            .bookings
+            .values()
+            .filter(|b| b.start <= now && !self.active.contains_key(&b.id))
+            .cloned()
+            .collect();
+        for booking in due {
+            // All or nothing: if one of the locks cannot be acquired, the
+            // ones acquired so far are released again when `handles` is
+            // dropped. Owners only hear about maintenance that did start.
+            let handles: Result<Vec<_>, _> = booking
+                .pcs
+                .iter()
+                .map(|&id| {
+                    let pc = self
+                        .directory
+                        .get_pc(id)
+                        .ok_or(PcDirectoryError::PcNotFound { id })?;
+                    pc.acquire_maintenance_lock(&booking.reason)
+                })
+                .collect();
+            match handles {
+                Ok(mut handles) => {
+                    for handle in &mut handles {
+                        self.directory
+                            .announce_maintenance(handle, booking.reason.clone());
+                    }
+                    self.active.insert(booking.id, handles);
+                    events.push(SchedulerEvent::Started(booking.id));
+                }
+                Err(error) => events.push(SchedulerEvent::Failed {
+                    id: booking.id,
+                    error,
+                }),
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::{access::Role, pc_directory::get_directory, person::EmailAddr};
+
+    use super::*;
+
+    fn helpdesk() -> Caller {
+        Caller::new(
+            EmailAddr::try_from("hans@overkill.com").unwrap(),
+            [Role::Helpdesk],
+        )
+    }
+
+    fn at(hour: u32) -> Timestamp {
+        NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_overlapping_bookings_conflict() {
+        let dir = get_directory();
+        let mut scheduler = MaintenanceScheduler::new(&dir);
+        let first = scheduler.book([0, 1], at(8), at(10), "upgrade").unwrap();
+
+        assert!(matches!(
+            scheduler.book([1, 2], at(9), at(11), "cleanup"),
+            Err(SchedulerError::Overlapping { pc: 1, booking }) if booking == first
+        ));
+        // Back-to-back windows do not overlap.
+        assert!(scheduler.book([1, 2], at(10), at(11), "cleanup").is_ok());
+    }
+
+    #[test]
+    fn test_booking_pc_in_maintenance_fails() {
+        let dir = get_directory();
+        let _handle = dir.acquire_maintenance_lock(3, "manual fix").unwrap();
+        let mut scheduler = MaintenanceScheduler::new(&dir);
+
+        assert!(matches!(
+            scheduler.book([3], at(8), at(10), "upgrade"),
+            Err(SchedulerError::InMaintenance { pc: 3, .. })
+        ));
+        assert!(matches!(
+            scheduler.book([3], at(10), at(8), "upgrade"),
+            Err(SchedulerError::EmptyWindow)
+        ));
+    }
+
+    #[test]
+    fn test_only_maintainers_may_book() {
+        let dir = get_directory();
+        let mut scheduler = MaintenanceScheduler::new(&dir);
+        // Owners may not maintain their own PCs.
+        let don = Caller::new(EmailAddr::try_from("don@drumpf.com").unwrap(), []);
+        assert!(matches!(
+            scheduler.book_as(&don, [3], at(8), at(10), "upgrade"),
+            Err(SchedulerError::Directory(
+                PcDirectoryError::PermissionDenied { .. }
+            ))
+        ));
+        assert!(matches!(
+            scheduler.book_as(&helpdesk(), [3, 9], at(8), at(10), "upgrade"),
+            Err(SchedulerError::PcNotFound { id: 9 })
+        ));
+        assert!(scheduler
+            .book_as(&helpdesk(), [3], at(8), at(10), "upgrade")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_tick_acquires_and_releases() {
+        let dir = get_directory();
+        let mut scheduler = MaintenanceScheduler::new(&dir);
+        let id = scheduler.book([3, 4], at(8), at(10), "upgrade").unwrap();
+
+        assert!(scheduler.tick(at(7)).is_empty());
+        assert!(matches!(scheduler.tick(at(8))[..], [SchedulerEvent::Started(i)] if i == id));
+        assert!(matches!(
+            dir.get_pc(4).unwrap().operational_state(),
+            OperationalState::BeingMaintained { .. }
+        ));
+        assert!(scheduler.tick(at(9)).is_empty());
+        assert!(matches!(scheduler.tick(at(10))[..], [SchedulerEvent::Finished(i)] if i == id));
+        assert!(dir.get_pc(4).unwrap().operational_state().is_on());
+        assert_eq!(scheduler.bookings().count(), 0);
+    }
+
+    #[test]
+    fn test_book_location() {
+        let mut dir = get_directory();
+        let date = at(0).date();
+        let (hq, annex) = (
+            "Zurich/HQ".parse().unwrap(),
+            "Zurich/Annex".parse().unwrap(),
+        );
+        for pc in [1, 2] {
+            dir.move_pc(pc, Some("Zurich/HQ/2/2.01".parse().unwrap()), date)
+                .unwrap();
+        }
+        let mut scheduler = MaintenanceScheduler::new(&dir);
+        assert!(matches!(
+            scheduler.book_location_as(&helpdesk(), &annex, at(8), at(10), "rewiring"),
+            Err(SchedulerError::EmptyLocation { .. })
+        ));
+        let id = scheduler
+            .book_location_as(&helpdesk(), &hq, at(8), at(10), "rewiring")
+            .unwrap();
+        assert_eq!(scheduler.bookings().next().unwrap().pcs, vec![1, 2]);
+
+        scheduler.tick(at(8));
+        assert!(scheduler.is_active(id));
+        assert!(!dir.get_pc(2).unwrap().operational_state().is_on());
+        assert!(dir.get_pc(3).unwrap().operational_state().is_on());
+    }
+
+    #[test]
+    fn test_failed_start_is_retried() {
+        let dir = get_directory();
+        let mut scheduler = MaintenanceScheduler::new(&dir);
+        let id = scheduler.book([3, 4], at(8), at(10), "upgrade").unwrap();
+
+        {
+            let _manual = dir.acquire_maintenance_lock(4, "manual fix").unwrap();
+            assert!(matches!(
+                scheduler.tick(at(8))[..],
+                [SchedulerEvent::Failed { id: i, .. }] if i == id
+            ));
+            // PC 3 was released again.
+            assert!(dir.get_pc(3).unwrap().operational_state().is_on());
+        }
+        assert!(matches!(scheduler.tick(at(9))[..], [SchedulerEvent::Started(i)] if i == id));
+    }
+
+    #[test]
+    fn test_owners_are_only_notified_of_started_maintenance() {
+        let dir = get_directory();
+        dir.set_maintenance_notifications(true);
+        let mut scheduler = MaintenanceScheduler::new(&dir);
+        scheduler.book([3, 4], at(8), at(10), "upgrade").unwrap();
+
+        {
+            let _manual = dir
+                .get_pc(4)
+                .unwrap()
+                .acquire_maintenance_lock("fix")
+                .unwrap();
+            scheduler.tick(at(8));
+        }
+        // Don's PC 3 was locked and released again without him noticing.
+        assert!(dir.get_pc(3).unwrap().mailbox().is_empty());
+        assert!(dir.deferred_recipients().is_empty());
+
+        scheduler.tick(at(9));
+        assert_eq!(dir.deferred_recipients().len(), 2);
+    }
+}
@@ -0,0 +1,465 @@
+//! A JSON API for a directory stored in a file.
+//!
+//! | Request                        | Body                      | Response              |
+//! |--------------------------------|---------------------------|-----------------------|
+//! | `GET /pcs?query=...`           |                           | the (matching) PCs    |
+//! | `GET /pcs/{id}`                |                           | the PC                |
+//! | `POST /pcs`                    | `{"profile", "owner"}`    | `201` and the new PC  |
+//! | `POST /pcs/{id}/maintenance`   | `{"reason"}`              | `201` and the PC      |
+//! | `DELETE /pcs/{id}/maintenance` |                           | the PC                |
+//! | `GET /pcs/{id}/mailbox`        |                           | the messages          |
+//! | `POST /emails`                 | `{"to", "message"}`       | `204`                 |
+//!
+//! The query is written in the [crate::query] language. The owner of a new
+//! PC is either the email address of someone owning a PC already or a whole
+//! [Person]; both are optional. PCs are listed with the names and email
+//! addresses of their owners only. Errors are returned as `{"error": "..."}`
+//! with a status code matching the [PcDirectoryError].
+//!
+//! All requests but reading PCs act on behalf of the caller named in the
+//! `X-Caller` header and are checked against the [RoleConfig], see
+//! [crate::access]. The server does not authenticate callers, it trusts the
+//! header: anyone who can reach it can act as anyone else. Only listen on a
+//! loopback address or behind a proxy that authenticates clients and sets the
+//! header.
+//!
+//! Request bodies larger than [MAX_BODY] bytes are refused with `413`.
+//!
+//! The directory is saved after every change. Maintenance started through the
+//! server lasts until it is ended through the server, but, like all
+//! maintenance locks, not beyond the server process.
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::{
+    access::{Caller, Permission, RoleConfig},
+    location::Location,
+    os::OperatingSystem,
+    pc::{PcBuilder, PcHardware},
+    pc_directory::{
+        MaintenanceLease, OperationalState, PcDirectory, PcDirectoryEntry, PcDirectoryError,
+        PersistenceError,
+    },
+    person::{EmailAddr, Person, PersonId},
+    profiles::Profiles,
+    query::Query,
+    software::Package,
+};
+
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("Could not listen for requests: {0}")]
+    Bind(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Could not receive a request: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Directory(#[from] PersistenceError),
+}
+
+/// The largest request body that is read, in bytes.
+pub const MAX_BODY: u64 = 64 * 1024;
+
+/// Serves a directory file until the process ends.
+pub struct DirectoryServer {
+    http: tiny_http::Server,
+    path: PathBuf,
+    dir: PcDirectory,
+    roles: RoleConfig,
+    profiles: Profiles,
+    /// The PCs being maintained on behalf of clients.
+    leases: BTreeMap<usize, MaintenanceLease>,
+}
+
+/// The HTTP status code for a directory error.
+pub fn status(e: &PcDirectoryError) -> u16 {
+    match e {
+        PcDirectoryError::InvalidEMailAddress => 400,
+        PcDirectoryError::PermissionDenied { .. } => 403,
+        PcDirectoryError::EmailNotFound { .. }
+        | PcDirectoryError::PcNotFound { .. }
+        | PcDirectoryError::PersonNotFound { .. }
+        | PcDirectoryError::NotInstalled { .. } => 404,
+        PcDirectoryError::DuplicateEmailAddress { .. }
+        | PcDirectoryError::Unavailable
+        | PcDirectoryError::InMaintenance { .. } => 409,
+        PcDirectoryError::Incompatible(_) => 422,
+    }
+}
+
+// A failed request.
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl ToString) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<PcDirectoryError> for ApiError {
+    fn from(e: PcDirectoryError) -> Self {
+        Self::new(status(&e), e)
+    }
+}
+
+impl From<PersistenceError> for ApiError {
+    fn from(e: PersistenceError) -> Self {
+        Self::new(500, e)
+    }
+}
+
+// A successful request: the status and the JSON body, if any.
+struct Reply {
+    status: u16,
+    body: Option<serde_json::Value>,
+}
+
+impl Reply {
+    fn ok(body: impl Serialize) -> Self {
+        Self::with_status(200, body)
+    }
+
+    fn with_status(status: u16, body: impl Serialize) -> Self {
+        Self {
+            status,
+            body: Some(serde_json::to_value(body).expect("replies are serializable")),
+        }
+    }
+}
+
+// What everyone may know about an owner; income and the like stay private.
+#[derive(Serialize)]
+struct OwnerView {
+    first: String,
+    last: String,
+    email: EmailAddr,
+}
+
+#[derive(Serialize)]
+struct PcView {
+    id: usize,
+    owner_id: Option<PersonId>,
+    owner: Option<OwnerView>,
+    os: OperatingSystem,
+    state: OperationalState,
+    location: Option<Location>,
+    hardware: PcHardware,
+    software: Vec<Package>,
+}
+
+impl From<&PcDirectoryEntry> for PcView {
+    fn from(pc: &PcDirectoryEntry) -> Self {
+        Self {
+            id: pc.id(),
+            owner_id: pc.owner_id(),
+            owner: pc.owner.as_deref().map(|p| OwnerView {
+                first: p.first.clone(),
+                last: p.last.clone(),
+                email: p.email.clone(),
+            }),
+            os: pc.os(),
+            state: pc.operational_state(),
+            location: pc.location().cloned(),
+            hardware: pc.hardware.clone(),
+            software: pc.software(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NewPc {
+    profile: Option<String>,
+    owner: Option<NewOwner>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NewOwner {
+    Existing(EmailAddr),
+    New(Person),
+}
+
+#[derive(Deserialize)]
+struct NewMaintenance {
+    reason: String,
+}
+
+#[derive(Deserialize)]
+struct NewEmail {
+    to: EmailAddr,
+    message: String,
+}
+
+impl DirectoryServer {
+    /// Listen on `addr` for requests about the directory stored at `path`,
+    /// which is created if it does not exist.
+    pub fn bind<A: ToSocketAddrs, P: Into<PathBuf>>(addr: A, path: P) -> Result<Self, ServerError> {
+        let path = path.into();
+        let dir = match path.exists() {
+            true => PcDirectory::load(&path)?,
+            false => PcDirectory::default(),
+        };
+        Ok(Self {
+            http: tiny_http::Server::http(addr).map_err(ServerError::Bind)?,
+            path,
+            dir,
+            roles: RoleConfig::default(),
+            profiles: Profiles::default(),
+            leases: BTreeMap::new(),
+        })
+    }
+
+    /// Who has which roles. Without roles, callers may only read the
+    /// mailboxes of their own PCs.
+    pub fn with_roles(mut self, roles: RoleConfig) -> Self {
+        self.roles = roles;
+        self
+    }
+
+    /// The profiles for new PCs.
+    pub fn with_profiles(mut self, profiles: Profiles) -> Self {
+        self.dir.set_profiles(profiles.clone());
+        self.profiles = profiles;
+        self
+    }
+
+    /// The address the server listens on, e.g. to find out the port if it was
+    /// bound to port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Answer requests until the process ends.
+    pub fn run(mut self) -> Result<(), ServerError> {
+        loop {
+            let request = self.http.recv()?;
+            self.serve(request);
+        }
+    }
+
+    fn serve(&mut self, mut request: Request) {
+        let mut body = String::new();
+        let read = request
+            .as_reader()
+            .take(MAX_BODY + 1)
+            .read_to_string(&mut body);
+        let reply = match read {
+            Ok(n) if n as u64 > MAX_BODY => Err(ApiError::new(
+                413,
+                format!("Request bodies may be at most {MAX_BODY} bytes long."),
+            )),
+            Ok(_) => {
+                let caller = request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv("X-Caller"))
+                    .map(|h| h.value.to_string());
+                let method = request.method().clone();
+                let url = request.url().to_string();
+                self.handle(&method, &url, caller.as_deref(), &body)
+            }
+            Err(e) => Err(ApiError::new(400, e)),
+        };
+        let (status, body) = match reply {
+            Ok(Reply { status, body }) => (status, body),
+            Err(e) => (e.status, Some(serde_json::json!({ "error": e.message }))),
+        };
+        let body = body.map_or(String::new(), |b| b.to_string());
+        let json = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(json);
+        // The client may be gone already; that is its problem.
+        let _ = request.respond(response);
+    }
+
+    fn handle(
+        &mut self,
+        method: &Method,
+        url: &str,
+        caller: Option<&str>,
+        body: &str,
+    ) -> Result<Reply, ApiError> {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+        let pc = || -> Result<usize, ApiError> {
+            segments[1].parse().map_err(|_| {
+                ApiError::new(404, format!("There is no PC with id {:?}.", segments[1]))
+            })
+        };
+        let caller = || -> Result<Caller, ApiError> {
+            let email =
+                caller.ok_or_else(|| ApiError::new(401, "The X-Caller header is missing."))?;
+            let email = EmailAddr::try_from(email).map_err(|e| ApiError::new(400, e))?;
+            Ok(self.roles.caller(email))
+        };
+        match (method, segments.as_slice()) {
+            (Method::Get, ["pcs"]) => self.list(query),
+            (Method::Post, ["pcs"]) => self.add(&caller()?, parse(body)?),
+            (Method::Get, ["pcs", _]) => self.get(pc()?),
+            (Method::Post, ["pcs", _, "maintenance"]) => {
+                self.start_maintenance(&caller()?, pc()?, parse(body)?)
+            }
+            (Method::Delete, ["pcs", _, "maintenance"]) => self.end_maintenance(&caller()?, pc()?),
+            (Method::Get, ["pcs", _, "mailbox"]) => {
+                Ok(Reply::ok(self.dir.read_mailbox_as(&caller()?, pc()?)?))
+            }
+            (Method::Post, ["emails"]) => self.send_email(&caller()?, parse(body)?),
+            (_, ["pcs"] | ["pcs", _] | ["pcs", _, "maintenance" | "mailbox"] | ["emails"]) => Err(
+                ApiError::new(405, format!("{method} is not supported for {path}.")),
+            ),
+            _ => Err(ApiError::new(404, format!("There is nothing at {path}."))),
+        }
+    }
+
+    fn list(&self, query: &str) -> Result<Reply, ApiError> {
+        let pcs: Vec<PcView> = match param(query, "query") {
+            Some(q) => {
+                let parsed = Query::parse(&q).map_err(|e| ApiError::new(400, e.show(&q)))?;
+                parsed.filter(&self.dir).map(PcView::from).collect()
+            }
+            None => self.dir.iter_pcs().map(PcView::from).collect(),
+        };
+        Ok(Reply::ok(pcs))
+    }
+
+    fn get(&self, id: usize) -> Result<Reply, ApiError> {
+        let pc = self
+            .dir
+            .get_pc(id)
+            .ok_or(PcDirectoryError::PcNotFound { id })?;
+        Ok(Reply::ok(PcView::from(pc)))
+    }
+
+    fn add(&mut self, caller: &Caller, new: NewPc) -> Result<Reply, ApiError> {
+        // Check first, so that callers cannot find out who owns PCs.
+        self.dir.authorize(caller, Permission::AddPc, None)?;
+        let mut pc = match new.profile {
+            Some(name) => self
+                .profiles
+                .builder(&name)
+                .map_err(|e| ApiError::new(400, e))?,
+            None => PcBuilder::default(),
+        };
+        pc.owner = match new.owner {
+            Some(NewOwner::Existing(email)) => match self.dir.find_person(&email) {
+                Some((_, person)) => Some(Person::clone(person)),
+                None => return Err(PcDirectoryError::EmailNotFound { email }.into()),
+            },
+            Some(NewOwner::New(person)) => Some(person),
+            None => None,
+        };
+        self.dir.add_pc_as(caller, pc)?;
+        self.dir.save(&self.path)?;
+        let pc = self.dir.iter_pcs().last().expect("just added");
+        Ok(Reply::with_status(201, PcView::from(pc)))
+    }
+
+    fn start_maintenance(
+        &mut self,
+        caller: &Caller,
+        id: usize,
+        new: NewMaintenance,
+    ) -> Result<Reply, ApiError> {
+        let handle = self
+            .dir
+            .acquire_maintenance_lock_as(caller, id, new.reason)?;
+        self.leases.insert(id, handle.into_lease());
+        let pc = self.dir.get_pc(id).expect("just locked");
+        Ok(Reply::with_status(201, PcView::from(pc)))
+    }
+
+    fn end_maintenance(&mut self, caller: &Caller, id: usize) -> Result<Reply, ApiError> {
+        self.dir.authorize(caller, Permission::Maintain, Some(id))?;
+        let lease = self.leases.remove(&id).ok_or_else(|| {
+            ApiError::new(
+                409,
+                format!("PC {id} is not being maintained through this server."),
+            )
+        })?;
+        drop(self.dir.resume_maintenance(lease)?);
+        // Ending the maintenance may deliver mail.
+        self.dir.save(&self.path)?;
+        self.get(id)
+    }
+
+    fn send_email(&mut self, caller: &Caller, new: NewEmail) -> Result<Reply, ApiError> {
+        self.dir.send_email_as(caller, new.to, new.message)?;
+        self.dir.save(&self.path)?;
+        Ok(Reply {
+            status: 204,
+            body: None,
+        })
+    }
+}
+
+fn parse<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, ApiError> {
+    serde_json::from_str(body).map_err(|e| ApiError::new(400, format!("Invalid request: {e}")))
+}
+
+// The decoded value of a parameter of a URL query string.
+fn param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+fn percent_decode(s: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|h| std::str::from_utf8(h).ok());
+        match (b, hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            (b'+', _) => {
+                bytes.push(b' ');
+                rest = tail;
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_param() {
+        let query = "fields=id&query=os+%3C+%22Windows%2011%22";
+        assert_eq!(
+            param(query, "query").as_deref(),
+            Some(r#"os < "Windows 11""#)
+        );
+        assert_eq!(param(query, "sort"), None);
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(status(&PcDirectoryError::PcNotFound { id: 3 }), 404);
+        assert_eq!(status(&PcDirectoryError::Unavailable), 409);
+    }
+}
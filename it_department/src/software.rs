@@ -0,0 +1,371 @@
+//! Software installed on PCs and advisories about bad versions of it.
+//!
+//! Every PC has a set of installed [Package]s, at most one version of each.
+//! Like the operating system, they can only be changed while the PC is being
+//! maintained, see [crate::pc_directory::MaintenanceHandle::install].
+//!
+//! Versions known to be bad are declared in an advisory file (TOML):
+//!
+//! ```toml
+//! [[advisory]]
+//! package = "OpenSSL"
+//! from = "3.0"
+//! before = "3.0.7"
+//! reason = "CVE-2022-3602: buffer overflow in X.509 certificate verification"
+//! ```
+//!
+//! `from` (inclusive) is optional; `before` is the first good version. An
+//! [AdvisoryReport] lists the PCs running affected versions.
+use std::{cmp::Ordering, collections::BTreeMap, fmt, fs, path::Path, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    pc_directory::PcDirectory,
+    person::EmailAddr,
+    report::{Report, Table},
+};
+
+/// A version like "3.0.7", "2021", "1.1.1w" or "115.3.0esr". Versions are
+/// compared number by number, so "1.10" is newer than "1.9", and missing
+/// numbers count as zero, so "3.0" and "3.0.0" are the same. A suffix after
+/// the last number is compared as text afterwards, so "1.1.1w" is newer than
+/// "1.1.1a", which is newer than "1.1.1".
+#[derive(Debug, Clone, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Version {
+    numbers: Vec<u32>,
+    suffix: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum VersionError {
+    #[error("A version must not be empty.")]
+    Empty,
+    #[error(
+        "Invalid version component {component:?}; versions are numbers separated by dots, \
+         optionally followed by a suffix like \"w\" or \"esr\"."
+    )]
+    InvalidComponent { component: String },
+}
+
+impl Version {
+    fn component(&self, i: usize) -> u32 {
+        self.numbers.get(i).copied().unwrap_or(0)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.numbers.len().max(other.numbers.len());
+        (0..len)
+            .map(|i| self.component(i).cmp(&other.component(i)))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| self.suffix.cmp(&other.suffix))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for Version {
+    type Err = VersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(VersionError::Empty);
+        }
+        let invalid = |c: &str| VersionError::InvalidComponent {
+            component: c.into(),
+        };
+        let mut components: Vec<_> = s.split('.').collect();
+        // Only the last number may be followed by a suffix.
+        let last = components.pop().expect("split yields at least one part");
+        let digits = last
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(last.len());
+        let (last, suffix) = last.split_at(digits);
+        if last.is_empty() || suffix.contains(char::is_whitespace) {
+            return Err(invalid(&format!("{last}{suffix}")));
+        }
+        let numbers = components
+            .into_iter()
+            .chain([last])
+            .map(|c| c.parse().map_err(|_| invalid(c)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            numbers,
+            suffix: suffix.into(),
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let numbers: Vec<_> = self.numbers.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}{}", numbers.join("."), self.suffix)
+    }
+}
+
+impl TryFrom<String> for Version {
+    type Error = VersionError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Version> for String {
+    fn from(value: Version) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub version: Version,
+    pub vendor: String,
+}
+
+impl Package {
+    pub fn new<S: ToString, T: ToString>(name: S, version: Version, vendor: T) -> Self {
+        Self {
+            name: name.to_string(),
+            version,
+            vendor: vendor.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Package {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({})", self.name, self.version, self.vendor)
+    }
+}
+
+// Describe how the installed software changed, for maintenance summaries.
+pub(crate) fn describe_changes(
+    before: &BTreeMap<String, Package>,
+    after: &BTreeMap<String, Package>,
+) -> Vec<String> {
+    let mut changes = vec![];
+    for (name, old) in before {
+        match after.get(name) {
+            None => changes.push(format!("Removed {old}.")),
+            Some(new) if new != old => {
+                changes.push(format!("{name}: {} -> {}.", old.version, new.version))
+            }
+            Some(_) => (),
+        }
+    }
+    for (name, new) in after {
+        if !before.contains_key(name) {
+            changes.push(format!("Installed {new}."));
+        }
+    }
+    changes
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Advisories {
+    #[serde(rename = "advisory", default)]
+    pub advisories: Vec<Advisory>,
+}
+
+/// A range of bad versions of a package.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Advisory {
+    pub package: String,
+    /// Lowest affected version (inclusive). All versions before `before` are
+    /// affected if missing.
+    pub from: Option<Version>,
+    /// The first version that is not affected anymore.
+    pub before: Version,
+    pub reason: String,
+}
+
+impl Advisory {
+    /// Whether `package` is one of the bad versions. Package names are
+    /// compared case-insensitively.
+    pub fn affects(&self, package: &Package) -> bool {
+        package.name.eq_ignore_ascii_case(&self.package)
+            && self
+                .from
+                .as_ref()
+                .map_or(true, |from| &package.version >= from)
+            && package.version < self.before
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AdvisoryError {
+    #[error("Could not read the advisories: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The advisories are malformed: {0}")]
+    Format(#[from] toml::de::Error),
+}
+
+impl Advisories {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, AdvisoryError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(s: &str) -> Result<Self, AdvisoryError> {
+        Ok(toml::from_str(s)?)
+    }
+}
+
+/// The PCs running software with known-bad versions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AdvisoryReport {
+    pub affected: Vec<AffectedPc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AffectedPc {
+    pub pc: usize,
+    pub owner: Option<EmailAddr>,
+    pub package: Package,
+    /// The version to update to at least.
+    pub fixed_in: Version,
+    pub reason: String,
+}
+
+impl AdvisoryReport {
+    pub fn new(dir: &PcDirectory, advisories: &Advisories) -> Self {
+        let mut affected = vec![];
+        for pc in dir.iter_pcs() {
+            for package in pc.software() {
+                let matching = advisories.advisories.iter().filter(|a| a.affects(&package));
+                affected.extend(matching.map(|advisory| AffectedPc {
+                    pc: pc.id(),
+                    owner: pc.owner.as_ref().map(|p| p.email.clone()),
+                    package: package.clone(),
+                    fixed_in: advisory.before.clone(),
+                    reason: advisory.reason.clone(),
+                }));
+            }
+        }
+        Self { affected }
+    }
+}
+
+impl Report for AdvisoryReport {
+    fn summary(&self) -> String {
+        let mut pcs: Vec<_> = self.affected.iter().map(|a| a.pc).collect();
+        pcs.dedup();
+        format!(
+            "{} PCs run software with known-bad versions ({} findings)",
+            pcs.len(),
+            self.affected.len()
+        )
+    }
+
+    fn tables(&self) -> Vec<Table> {
+        vec![Table {
+            name: "affected",
+            title: "Software with known-bad versions",
+            columns: vec!["PC", "owner", "package", "version", "fixed in", "reason"],
+            rows: self
+                .affected
+                .iter()
+                .map(|a| {
+                    vec![
+                        a.pc.to_string(),
+                        a.owner.as_ref().map_or("-".into(), |e| e.as_ref().into()),
+                        a.package.name.clone(),
+                        a.package.version.to_string(),
+                        a.fixed_in.to_string(),
+                        a.reason.clone(),
+                    ]
+                })
+                .collect(),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pc_directory::get_directory, report::ReportFormat};
+
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_versions() {
+        assert!(v("1.10") > v("1.9"));
+        assert_eq!(v("3.0"), v("3.0.0"));
+        assert!(v("3.0.7") < v("3.1"));
+        assert_eq!(v(" 2021 ").to_string(), "2021");
+        assert_eq!("".parse::<Version>(), Err(VersionError::Empty));
+        for invalid in ["3.x.1", "esr", "1.1w.1", "3.0 beta", "3..1"] {
+            assert!(
+                matches!(
+                    invalid.parse::<Version>(),
+                    Err(VersionError::InvalidComponent { .. })
+                ),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_version_suffixes() {
+        assert!(v("1.1.1w") > v("1.1.1a"));
+        assert!(v("1.1.1a") > v("1.1.1"));
+        assert!(v("1.0.2u") < v("1.1.1"));
+        assert!(v("115.3.0esr") >= v("115.3"));
+        assert!(v("115.2.1esr") < v("115.3"));
+        assert_eq!(v("115.3.0esr").to_string(), "115.3.0esr");
+        assert_eq!(v("1.1.1w"), v("1.1.1.0w"));
+    }
+
+    #[test]
+    fn test_advisory_report() {
+        let advisories = Advisories::parse(
+            r#"
+            [[advisory]]
+            package = "openssl"
+            from = "3.0"
+            before = "3.0.7"
+            reason = "CVE-2022-3602"
+
+            [[advisory]]
+            package = "OpenSSL"
+            before = "1.1.1"
+            reason = "End of life"
+            "#,
+        )
+        .unwrap();
+        let dir = get_directory();
+        {
+            let handle = dir.acquire_maintenance_lock(1, "downgrade").unwrap();
+            handle.install(Package::new("OpenSSL", v("3.0.2"), "OpenSSL"));
+        }
+        let report = AdvisoryReport::new(&dir, &advisories);
+        let found: Vec<_> = report
+            .affected
+            .iter()
+            .map(|a| (a.pc, a.reason.as_str()))
+            .collect();
+        assert_eq!(found, vec![(1, "CVE-2022-3602"), (3, "End of life")]);
+        assert!(report
+            .render(ReportFormat::Csv)
+            .contains("affected,1,hans@overkill.com,OpenSSL,3.0.2,3.0.7,CVE-2022-3602\n"));
+    }
+}
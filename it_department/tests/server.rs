@@ -0,0 +1,308 @@
+//! Runs the server on a loopback port and talks HTTP to it.
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    path::PathBuf,
+    sync::mpsc,
+    thread,
+};
+
+use it_company::{
+    access::RoleConfig,
+    pc_directory::{get_directory, PcDirectory},
+    server::DirectoryServer,
+};
+use serde_json::{json, Value};
+
+const ROLES: &str = r#"
+admin = ["maria@dingong.com"]
+helpdesk = ["hans@overkill.com"]
+"#;
+
+fn roles() -> RoleConfig {
+    RoleConfig::parse(ROLES).unwrap()
+}
+
+/// Serve the demo directory from a new file named after the test.
+fn start(name: &str) -> (SocketAddr, PathBuf) {
+    let path = PathBuf::from(std::env::var("TMPDIR").unwrap()).join(format!(
+        "it_company_server_{name}_{}.json",
+        std::process::id()
+    ));
+    get_directory().save(&path).unwrap();
+    let (tx, rx) = mpsc::channel();
+    let served = path.clone();
+    // The directory is not `Send`, so the server is created on its own thread.
+    thread::spawn(move || {
+        let server = DirectoryServer::bind("127.0.0.1:0", served)
+            .unwrap()
+            .with_roles(roles());
+        tx.send(server.local_addr().unwrap()).unwrap();
+        server.run().unwrap();
+    });
+    (rx.recv().unwrap(), path)
+}
+
+/// Send a request and return the status code and the JSON body (`null` if empty).
+fn request(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    caller: Option<&str>,
+    body: Option<Value>,
+) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let body = body.map_or(String::new(), |b| b.to_string());
+    let mut head = format!(
+        "{method} {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        body.len()
+    );
+    if let Some(caller) = caller {
+        head += &format!("X-Caller: {caller}\r\n");
+    }
+    write!(stream, "{head}\r\n{body}").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    let body = match body.is_empty() {
+        true => Value::Null,
+        false => serde_json::from_str(body).unwrap(),
+    };
+    (status, body)
+}
+
+fn ids(pcs: &Value) -> Vec<u64> {
+    pcs.as_array()
+        .unwrap()
+        .iter()
+        .map(|pc| pc["id"].as_u64().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_list_and_add_pcs() {
+    let (addr, path) = start("pcs");
+    let (status, pcs) = request(addr, "GET", "/pcs", None, None);
+    assert_eq!(status, 200);
+    assert_eq!(ids(&pcs), vec![0, 1, 2, 3, 4, 5]);
+
+    let (status, pcs) = request(
+        addr,
+        "GET",
+        "/pcs?query=owner.email%20%3D%20%22hans%40overkill.com%22",
+        None,
+        None,
+    );
+    assert_eq!(status, 200);
+    assert_eq!(ids(&pcs), vec![1]);
+    let (status, error) = request(addr, "GET", "/pcs?query=os+%3C", None, None);
+    assert_eq!(status, 400);
+    assert!(error["error"].is_string());
+    let deep = format!("/pcs?query={}id+%3D+1", "not+".repeat(200));
+    let (status, error) = request(addr, "GET", &deep, None, None);
+    assert_eq!(status, 400);
+    assert!(error["error"].is_string());
+
+    let (status, pc) = request(addr, "GET", "/pcs/3", None, None);
+    assert_eq!(
+        (status, pc["owner"]["email"].as_str()),
+        (200, Some("don@drumpf.com"))
+    );
+    // Nobody's income is published.
+    assert_eq!(pc["owner"].get("affiliation"), None);
+    assert_eq!(request(addr, "GET", "/pcs/99", None, None).0, 404);
+    assert_eq!(request(addr, "GET", "/pcs/first", None, None).0, 404);
+    assert_eq!(request(addr, "GET", "/printers", None, None).0, 404);
+    assert_eq!(request(addr, "DELETE", "/pcs", None, None).0, 405);
+
+    let sue = Some(json!({ "owner": "sue@whatever.com" }));
+    assert_eq!(request(addr, "POST", "/pcs", None, sue.clone()).0, 401);
+    let (status, _) = request(addr, "POST", "/pcs", Some("sue@whatever.com"), sue.clone());
+    assert_eq!(status, 403);
+    let (status, pc) = request(addr, "POST", "/pcs", Some("hans@overkill.com"), sue);
+    assert_eq!(
+        (status, pc["id"].as_u64(), pc["owner_id"].as_u64()),
+        (201, Some(6), Some(2))
+    );
+    let (status, _) = request(
+        addr,
+        "POST",
+        "/pcs",
+        Some("hans@overkill.com"),
+        Some(json!({ "owner": "nobody@nowhere.com" })),
+    );
+    assert_eq!(status, 404);
+    assert_eq!(
+        request(
+            addr,
+            "POST",
+            "/pcs",
+            Some("hans@overkill.com"),
+            Some(json!({ "profile": 3 }))
+        )
+        .0,
+        400
+    );
+    assert_eq!(PcDirectory::load(&path).unwrap().iter_pcs().count(), 7);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_maintenance() {
+    let (addr, path) = start("maintenance");
+    let reason = Some(json!({ "reason": "new RAM" }));
+    assert_eq!(
+        request(addr, "POST", "/pcs/3/maintenance", None, reason.clone()).0,
+        401
+    );
+    assert_eq!(
+        request(
+            addr,
+            "POST",
+            "/pcs/3/maintenance",
+            Some("not an address"),
+            reason.clone()
+        )
+        .0,
+        400
+    );
+    assert_eq!(
+        request(
+            addr,
+            "POST",
+            "/pcs/3/maintenance",
+            Some("don@drumpf.com"),
+            reason.clone()
+        )
+        .0,
+        403
+    );
+
+    let (status, pc) = request(
+        addr,
+        "POST",
+        "/pcs/3/maintenance",
+        Some("hans@overkill.com"),
+        reason.clone(),
+    );
+    assert_eq!(status, 201);
+    assert_eq!(pc["id"], 3);
+    assert_eq!(
+        request(
+            addr,
+            "POST",
+            "/pcs/3/maintenance",
+            Some("maria@dingong.com"),
+            reason.clone()
+        )
+        .0,
+        409
+    );
+    assert_eq!(
+        request(
+            addr,
+            "POST",
+            "/pcs/99/maintenance",
+            Some("maria@dingong.com"),
+            reason
+        )
+        .0,
+        404
+    );
+
+    assert_eq!(
+        request(
+            addr,
+            "DELETE",
+            "/pcs/3/maintenance",
+            Some("don@drumpf.com"),
+            None
+        )
+        .0,
+        403
+    );
+    assert_eq!(
+        request(
+            addr,
+            "DELETE",
+            "/pcs/3/maintenance",
+            Some("maria@dingong.com"),
+            None
+        )
+        .0,
+        200
+    );
+    assert_eq!(
+        request(
+            addr,
+            "DELETE",
+            "/pcs/3/maintenance",
+            Some("maria@dingong.com"),
+            None
+        )
+        .0,
+        409
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_email() {
+    let (addr, path) = start("email");
+    let email = Some(json!({ "to": "don@drumpf.com", "message": "Your PC runs Vista." }));
+    assert_eq!(
+        request(
+            addr,
+            "POST",
+            "/emails",
+            Some("sue@whatever.com"),
+            email.clone()
+        )
+        .0,
+        403
+    );
+    let (status, body) = request(addr, "POST", "/emails", Some("hans@overkill.com"), email);
+    assert_eq!((status, body), (204, Value::Null));
+    let (status, _) = request(
+        addr,
+        "POST",
+        "/emails",
+        Some("hans@overkill.com"),
+        Some(json!({ "to": "nobody@nowhere.com", "message": "Hi" })),
+    );
+    assert_eq!(status, 404);
+    assert_eq!(
+        request(
+            addr,
+            "POST",
+            "/emails",
+            Some("hans@overkill.com"),
+            Some(json!({ "to": "don" }))
+        )
+        .0,
+        400
+    );
+
+    let (status, mailbox) = request(addr, "GET", "/pcs/3/mailbox", Some("don@drumpf.com"), None);
+    assert_eq!((status, mailbox), (200, json!(["Your PC runs Vista."])));
+    assert_eq!(
+        request(
+            addr,
+            "GET",
+            "/pcs/3/mailbox",
+            Some("sue@whatever.com"),
+            None
+        )
+        .0,
+        403
+    );
+    let saved = PcDirectory::load(&path).unwrap();
+    assert_eq!(
+        saved
+            .read_mailbox_as(&roles().caller("don@drumpf.com".try_into().unwrap()), 3)
+            .unwrap(),
+        vec!["Your PC runs Vista."]
+    );
+    std::fs::remove_file(path).unwrap();
+}